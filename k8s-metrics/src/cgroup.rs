@@ -0,0 +1,356 @@
+//! Container usage collected from the local cgroup v2 hierarchy
+//!
+//! Reads the same statistics the kubelet reports: cumulative CPU usage from `cpu.stat`
+//! and working set, i.e. `memory.current` less `inactive_file` of `memory.stat`.
+//! Usage rates are computed between two reads as for the kubelet `/metrics/resource`.
+//!
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+use kubelet::resource_metrics::{MetricsBatch, MetricsPoint, Rate};
+use v1beta1::Usage;
+
+const CPU_STAT: &str = "cpu.stat";
+const MEMORY_CURRENT: &str = "memory.current";
+const MEMORY_STAT: &str = "memory.stat";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CgroupError {
+    #[error("Failed to read {}: {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+
+    #[error("Unexpected format of {}", .0.display())]
+    Format(PathBuf),
+}
+
+impl CgroupError {
+    fn io(path: &Path, source: io::Error) -> Self {
+        Self::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    fn is_not_found(&self) -> bool {
+        matches!(self, Self::Io { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+/// cgroup v2 hierarchy mounted at `root`, `/sys/fs/cgroup` by default
+///
+#[derive(Clone, Debug)]
+pub struct Cgroups {
+    root: PathBuf,
+}
+
+/// cgroup of a container, relative to the hierarchy root
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerCgroup {
+    pub namespace: String,
+    pub pod: String,
+    pub container: String,
+    pub path: PathBuf,
+}
+
+/// Statistics of a single cgroup read at `timestamp`
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CgroupStats {
+    pub timestamp: DateTime<Utc>,
+    /// `usage_usec` of `cpu.stat`
+    ///
+    pub usage_usec: u64,
+    /// `memory.current`
+    ///
+    pub memory_current: u64,
+    /// `inactive_file` of `memory.stat`
+    ///
+    pub inactive_file: u64,
+}
+
+impl Default for Cgroups {
+    fn default() -> Self {
+        Self::new("/sys/fs/cgroup")
+    }
+}
+
+impl Cgroups {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self { root }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Read the statistics of `cgroup` now
+    ///
+    pub fn read(&self, cgroup: impl AsRef<Path>) -> Result<CgroupStats, CgroupError> {
+        self.read_at(cgroup, system_time(time::SystemTime::now()))
+    }
+
+    /// Read the statistics of `cgroup`, stamping them with `timestamp`
+    ///
+    pub fn read_at(
+        &self,
+        cgroup: impl AsRef<Path>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<CgroupStats, CgroupError> {
+        let dir = self.root.join(cgroup);
+        let usage_usec = keyed(&dir.join(CPU_STAT), "usage_usec")?;
+        let memory_current = single(&dir.join(MEMORY_CURRENT))?;
+        let inactive_file = keyed(&dir.join(MEMORY_STAT), "inactive_file")?;
+
+        Ok(CgroupStats {
+            timestamp,
+            usage_usec,
+            memory_current,
+            inactive_file,
+        })
+    }
+
+    /// Read the statistics of `containers` now, as a batch to compute pod metrics from
+    ///
+    /// Containers whose cgroup is gone, e.g. as the container exited meanwhile, are skipped.
+    ///
+    pub fn collect<'a>(
+        &self,
+        containers: impl IntoIterator<Item = &'a ContainerCgroup>,
+    ) -> Result<MetricsBatch, CgroupError> {
+        let mut pods = BTreeMap::<_, BTreeMap<_, _>>::new();
+        for container in containers {
+            let stats = match self.read(&container.path) {
+                Ok(stats) => stats,
+                Err(err) if err.is_not_found() => continue,
+                Err(err) => return Err(err),
+            };
+            pods.entry((container.namespace.clone(), container.pod.clone()))
+                .or_default()
+                .insert(container.container.clone(), stats.to_point());
+        }
+
+        Ok(MetricsBatch { node: None, pods })
+    }
+}
+
+impl CgroupStats {
+    /// Memory in use that cannot be easily reclaimed, in bytes
+    ///
+    pub fn working_set(&self) -> u64 {
+        self.memory_current.saturating_sub(self.inactive_file)
+    }
+
+    /// Usage since the `prev` read and the window it was observed over,
+    /// `None` if the `prev` read is not older or CPU usage decreased
+    ///
+    pub fn usage(&self, prev: &Self) -> Option<(time::Duration, Usage)> {
+        if prev.timestamp >= self.timestamp {
+            return None;
+        }
+        let Rate { window, usage, .. } = self.to_point().rate(&prev.to_point())?;
+        Some((window, usage))
+    }
+
+    /// Point as reported by the kubelet `/metrics/resource`
+    ///
+    pub fn to_point(&self) -> MetricsPoint {
+        MetricsPoint {
+            start_time: None,
+            timestamp: self.timestamp,
+            cumulative_cpu_used: self.usage_usec.saturating_mul(1000),
+            memory_usage: self.working_set(),
+        }
+    }
+}
+
+fn system_time(time: time::SystemTime) -> DateTime<Utc> {
+    let since_epoch = time.duration_since(time::UNIX_EPOCH).unwrap_or_default();
+    DateTime::from_timestamp(since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        .unwrap_or_default()
+}
+
+fn read(path: &Path) -> Result<String, CgroupError> {
+    fs::read_to_string(path).map_err(|err| CgroupError::io(path, err))
+}
+
+/// Value of a single value file, e.g. `memory.current`
+///
+fn single(path: &Path) -> Result<u64, CgroupError> {
+    read(path)?
+        .trim()
+        .parse()
+        .map_err(|_| CgroupError::Format(path.to_path_buf()))
+}
+
+/// Value of `key` in a flat keyed file, e.g. `cpu.stat`
+///
+fn keyed(path: &Path, key: &str) -> Result<u64, CgroupError> {
+    read(path)?
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(' '))
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| CgroupError::Format(path.to_path_buf()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str =
+        "kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod0c8a6e53.slice";
+    const NGINX: &str = "cri-containerd-5d1f0a8e.scope";
+    const ENVOY: &str = "cri-containerd-9b3c27d4.scope";
+
+    /// Fixture hierarchy under a fresh temporary directory, removed when dropped
+    ///
+    #[derive(Debug)]
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("k8s-metrics-cgroup-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn cgroup(&self, container: &str, usage_usec: u64, current: u64, inactive_file: u64) {
+            let dir = self.0.join(POD).join(container);
+            fs::create_dir_all(&dir).unwrap();
+            let cpu_stat = format!(
+                "usage_usec {usage_usec}\nuser_usec 1937102\nsystem_usec 911189\nnr_periods 0\nnr_throttled 0\nthrottled_usec 0\n"
+            );
+            let memory_stat = format!(
+                "anon 9224192\nfile 16252928\nkernel 1245184\nactive_anon 0\ninactive_anon 9224192\nactive_file 12869632\ninactive_file {inactive_file}\nunevictable 0\n"
+            );
+            fs::write(dir.join(CPU_STAT), cpu_stat).unwrap();
+            fs::write(dir.join(MEMORY_CURRENT), format!("{current}\n")).unwrap();
+            fs::write(dir.join(MEMORY_STAT), memory_stat).unwrap();
+        }
+
+        fn cgroups(&self) -> Cgroups {
+            Cgroups::new(&self.0)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn container(name: &str, scope: &str) -> ContainerCgroup {
+        ContainerCgroup {
+            namespace: "default".to_string(),
+            pod: "web-0".to_string(),
+            container: name.to_string(),
+            path: Path::new(POD).join(scope),
+        }
+    }
+
+    fn time(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(secs, 0).unwrap()
+    }
+
+    #[test]
+    fn read() {
+        let fixture = Fixture::new("read");
+        fixture.cgroup(NGINX, 2848291, 26120192, 3383296);
+        let stats = fixture
+            .cgroups()
+            .read_at(Path::new(POD).join(NGINX), time(1710234960))
+            .unwrap();
+        assert_eq!(stats.usage_usec, 2848291);
+        assert_eq!(stats.memory_current, 26120192);
+        assert_eq!(stats.inactive_file, 3383296);
+        assert_eq!(stats.working_set(), 22736896);
+        assert_eq!(stats.timestamp, time(1710234960));
+
+        let now = fixture.cgroups().read(Path::new(POD).join(NGINX)).unwrap();
+        assert!(now.timestamp > stats.timestamp);
+    }
+
+    #[test]
+    fn read_invalid() {
+        let fixture = Fixture::new("read_invalid");
+        let cgroups = fixture.cgroups();
+        let err = cgroups.read(POD).unwrap_err();
+        assert!(err.is_not_found(), "{err}");
+
+        fixture.cgroup(NGINX, 2848291, 26120192, 3383296);
+        let dir = fixture.0.join(POD).join(NGINX);
+        fs::write(dir.join(MEMORY_STAT), "anon 9224192\n").unwrap();
+        let err = cgroups.read(Path::new(POD).join(NGINX)).unwrap_err();
+        assert!(matches!(&err, CgroupError::Format(path) if path.ends_with(MEMORY_STAT)));
+        fs::write(dir.join(MEMORY_CURRENT), "max\n").unwrap();
+        let err = cgroups.read(Path::new(POD).join(NGINX)).unwrap_err();
+        assert!(matches!(&err, CgroupError::Format(path) if path.ends_with(MEMORY_CURRENT)));
+    }
+
+    #[test]
+    fn working_set() {
+        let stats = CgroupStats {
+            timestamp: time(1710234960),
+            usage_usec: 0,
+            memory_current: 4096,
+            inactive_file: 8192,
+        };
+        assert_eq!(stats.working_set(), 0);
+    }
+
+    #[test]
+    fn usage() {
+        let fixture = Fixture::new("usage");
+        let cgroups = fixture.cgroups();
+        let path = Path::new(POD).join(NGINX);
+        fixture.cgroup(NGINX, 2_000_000, 26120192, 3383296);
+        let prev = cgroups.read_at(&path, time(1710234960)).unwrap();
+        fixture.cgroup(NGINX, 2_750_000, 26120192, 3313664);
+        let last = cgroups.read_at(&path, time(1710234975)).unwrap();
+
+        let (window, usage) = last.usage(&prev).unwrap();
+        assert_eq!(window, time::Duration::from_secs(15));
+        assert_eq!(usage.cpu.0, "50m");
+        assert_eq!(usage.memory.0, "22272Ki");
+        assert_eq!(prev.usage(&last), None);
+        assert_eq!(last.usage(&last), None);
+    }
+
+    #[test]
+    fn pod_metrics() {
+        let fixture = Fixture::new("pod_metrics");
+        let cgroups = fixture.cgroups();
+        let containers = [
+            container("envoy", ENVOY),
+            container("nginx", NGINX),
+            container("gone", "cri-containerd-00000000.scope"),
+        ];
+        fixture.cgroup(NGINX, 2_000_000, 26120192, 3383296);
+        fixture.cgroup(ENVOY, 1_000_000, 10485760, 0);
+        let prev = cgroups.collect(&containers).unwrap();
+        fixture.cgroup(NGINX, 2_750_000, 26120192, 3313664);
+        fixture.cgroup(ENVOY, 1_187_500, 10485760, 0);
+        let last = cgroups.collect(&containers).unwrap();
+
+        let pods = last.pod_metrics(&prev);
+        assert_eq!(pods.len(), 1);
+        let pod = &pods[0];
+        assert_eq!(pod.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(pod.metadata.name.as_deref(), Some("web-0"));
+        assert_eq!(pod.containers.len(), 2);
+        assert_eq!(pod.containers[0].name, "envoy");
+        assert_eq!(pod.containers[0].usage.memory.0, "10Mi");
+        assert_eq!(pod.containers[1].name, "nginx");
+        assert_eq!(pod.containers[1].usage.memory.0, "22272Ki");
+        assert!(pod.window > time::Duration::ZERO);
+        assert!(pod.containers[1].cpu().unwrap() > 0.0);
+    }
+}
@@ -1,9 +1,156 @@
 use super::*;
 
+#[cfg(feature = "kube")]
+pub use api::CustomMetricsApi;
+pub use request::MetricParams;
+
+pub mod v1beta1;
 pub mod v1beta2;
 
+#[cfg(feature = "kube")]
+mod api;
+mod request;
+
+/// Kind of the objects described by custom metrics
+///
+/// It is implemented for every `k8s::Resource`, implement it directly
+/// for object kinds that have no such Rust type, e.g. CRDs.
+///
 pub trait CustomMetric {
+    const API_VERSION: &'static str;
     const KIND: &'static str;
     const URL_PATH_SEGMENT: &'static str;
     type Scope: k8s::ResourceScope;
 }
+
+impl<K: k8s::Resource> CustomMetric for K {
+    const API_VERSION: &'static str = K::API_VERSION;
+    const KIND: &'static str = K::KIND;
+    const URL_PATH_SEGMENT: &'static str = K::URL_PATH_SEGMENT;
+    type Scope = K::Scope;
+}
+
+pub const GROUP: &str = "custom.metrics.k8s.io";
+
+/// Version of the custom metrics API
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    V1beta1,
+    V1beta2,
+}
+
+impl Version {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::V1beta1 => "v1beta1",
+            Self::V1beta2 => "v1beta2",
+        }
+    }
+
+    /// Detect the version to talk to the server offering `group`
+    ///
+    /// The server preferred version wins if it is known, otherwise the
+    /// newest known version offered by the server is used.
+    ///
+    pub fn detect(group: &metav1::APIGroup) -> Option<Self> {
+        if group.name != GROUP {
+            return None;
+        }
+
+        group
+            .preferred_version
+            .as_ref()
+            .and_then(|preferred| Self::from_version(&preferred.version))
+            .or_else(|| {
+                group
+                    .versions
+                    .iter()
+                    .filter_map(|version| Self::from_version(&version.version))
+                    .max()
+            })
+    }
+
+    /// Detect the version of the custom metrics API among all `groups` the server offers
+    ///
+    pub fn detect_in(groups: &metav1::APIGroupList) -> Option<Self> {
+        groups.groups.iter().find_map(Self::detect)
+    }
+
+    fn from_version(version: &str) -> Option<Self> {
+        match version {
+            "v1beta1" => Some(Self::V1beta1),
+            "v1beta2" => Some(Self::V1beta2),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    const API_GROUP: &str = r#"{
+  "kind": "APIGroup",
+  "apiVersion": "v1",
+  "name": "custom.metrics.k8s.io",
+  "versions": [
+    {
+      "groupVersion": "custom.metrics.k8s.io/v1beta2",
+      "version": "v1beta2"
+    },
+    {
+      "groupVersion": "custom.metrics.k8s.io/v1beta1",
+      "version": "v1beta1"
+    }
+  ],
+  "preferredVersion": {
+    "groupVersion": "custom.metrics.k8s.io/v1beta1",
+    "version": "v1beta1"
+  }
+}"#;
+
+    fn group() -> metav1::APIGroup {
+        json::from_str(API_GROUP).unwrap()
+    }
+
+    #[test]
+    fn preferred() {
+        assert_eq!(Version::detect(&group()), Some(Version::V1beta1));
+    }
+
+    #[test]
+    fn newest() {
+        let group = metav1::APIGroup {
+            preferred_version: None,
+            ..group()
+        };
+        assert_eq!(Version::detect(&group), Some(Version::V1beta2));
+    }
+
+    #[test]
+    fn unknown_preferred() {
+        let mut group = group();
+        group.preferred_version.as_mut().unwrap().version = "v2".to_string();
+        assert_eq!(Version::detect(&group), Some(Version::V1beta2));
+    }
+
+    #[test]
+    fn not_served() {
+        let other = metav1::APIGroup {
+            name: "metrics.k8s.io".to_string(),
+            ..group()
+        };
+        let groups = metav1::APIGroupList {
+            groups: vec![other.clone()],
+        };
+        assert_eq!(Version::detect(&other), None);
+        assert_eq!(Version::detect_in(&groups), None);
+
+        let groups = metav1::APIGroupList {
+            groups: vec![other, group()],
+        };
+        assert_eq!(Version::detect_in(&groups), Some(Version::V1beta1));
+    }
+}
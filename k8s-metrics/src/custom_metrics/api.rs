@@ -0,0 +1,257 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use super::*;
+
+use v1beta2::{MetricValue, MetricValueList};
+
+/// Client of the custom metrics API for metrics describing objects of kind `K`
+///
+/// `kube::Api<MetricValue<K>>` cannot be used for that as the custom metrics API
+/// puts the metric name after the object name, e.g. `.../pods/{name}/{metric}`.
+///
+pub struct CustomMetricsApi<K> {
+    client: kube::Client,
+    namespace: Option<String>,
+    phantom: PhantomData<K>,
+}
+
+impl<K> CustomMetricsApi<K>
+where
+    K: CustomMetric<Scope = k8s::ClusterResourceScope>,
+{
+    /// Metrics of cluster scoped objects, e.g. nodes
+    ///
+    pub fn all(client: kube::Client) -> Self {
+        Self::new(client, None)
+    }
+}
+
+impl<K> CustomMetricsApi<K>
+where
+    K: CustomMetric<Scope = k8s::NamespaceResourceScope>,
+{
+    /// Metrics of objects in `namespace`
+    ///
+    pub fn namespaced(client: kube::Client, namespace: &str) -> Self {
+        Self::new(client, Some(namespace.to_string()))
+    }
+
+    /// Metrics of objects in the client's default namespace
+    ///
+    pub fn default_namespaced(client: kube::Client) -> Self {
+        let namespace = client.default_namespace().to_string();
+        Self::new(client, Some(namespace))
+    }
+}
+
+impl<K> CustomMetricsApi<K>
+where
+    K: CustomMetric,
+{
+    /// Get `metric` of the object `name`
+    ///
+    pub async fn get(&self, name: &str, metric: &str) -> kube::Result<MetricValueList<K>> {
+        self.get_with(name, metric, &default()).await
+    }
+
+    /// Get `metric` of the object `name` limited to the metric series selected by `params`
+    ///
+    pub async fn get_with(
+        &self,
+        name: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> kube::Result<MetricValueList<K>> {
+        let path = request::object_path(
+            <MetricValue<K> as k8s::Resource>::API_VERSION,
+            self.namespace.as_deref(),
+            K::URL_PATH_SEGMENT,
+            name,
+            metric,
+        );
+        self.request(path, params).await
+    }
+
+    /// List `metric` of all the objects matching label `selector`
+    ///
+    pub async fn list(&self, selector: &str, metric: &str) -> kube::Result<MetricValueList<K>> {
+        let params = MetricParams::default().labels(selector);
+        self.list_with(metric, &params).await
+    }
+
+    /// List `metric` of all the objects and metric series selected by `params`
+    ///
+    pub async fn list_with(
+        &self,
+        metric: &str,
+        params: &MetricParams,
+    ) -> kube::Result<MetricValueList<K>> {
+        self.get_with("*", metric, params).await
+    }
+
+    async fn request<T>(&self, path: String, params: &MetricParams) -> kube::Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let request = request::get(path, params).map_err(kube::Error::HttpError)?;
+        self.client.request(request).await
+    }
+}
+
+impl CustomMetricsApi<corev1::Namespace> {
+    /// Get `metric` describing the `namespace` itself
+    ///
+    pub async fn namespace_metric(
+        &self,
+        namespace: &str,
+        metric: &str,
+    ) -> kube::Result<MetricValueList<corev1::Namespace>> {
+        let path = request::namespace_path(
+            <MetricValue<corev1::Namespace> as k8s::Resource>::API_VERSION,
+            namespace,
+            metric,
+        );
+        self.request(path, &default()).await
+    }
+}
+
+impl<K> CustomMetricsApi<K> {
+    fn new(client: kube::Client, namespace: Option<String>) -> Self {
+        Self {
+            client,
+            namespace,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K> Clone for CustomMetricsApi<K> {
+    fn clone(&self) -> Self {
+        Self::new(self.client.clone(), self.namespace.clone())
+    }
+}
+
+impl<K> fmt::Debug for CustomMetricsApi<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomMetricsApi")
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    const PODS: &str = r#"{
+  "kind": "MetricValueList",
+  "apiVersion": "custom.metrics.k8s.io/v1beta2",
+  "metadata": {},
+  "items": [
+    {
+      "describedObject": {
+        "kind": "Pod",
+        "namespace": "default",
+        "name": "web-0",
+        "apiVersion": "/v1"
+      },
+      "metric": {
+        "name": "http_requests",
+        "selector": null
+      },
+      "timestamp": "2024-03-12T09:17:03Z",
+      "value": "66m"
+    }
+  ]
+}"#;
+
+    const NAMESPACE: &str = r#"{
+  "kind": "MetricValueList",
+  "apiVersion": "custom.metrics.k8s.io/v1beta2",
+  "metadata": {},
+  "items": [
+    {
+      "describedObject": {
+        "kind": "Namespace",
+        "name": "default",
+        "apiVersion": "/v1"
+      },
+      "metric": {
+        "name": "queue_length",
+        "selector": null
+      },
+      "timestamp": "2024-03-12T09:17:03Z",
+      "value": "12"
+    }
+  ]
+}"#;
+
+    /// Local mock of the API server answering `body` on `uri` and 404 otherwise
+    ///
+    fn client(uri: &'static str, body: &'static str) -> kube::Client {
+        let service = tower::service_fn(move |request: http::Request<kube::client::Body>| {
+            let response = if request.uri() == uri {
+                http::Response::new(kube::client::Body::from(body.as_bytes().to_vec()))
+            } else {
+                let status = r#"{"kind":"Status","apiVersion":"v1","status":"Failure","reason":"NotFound","code":404}"#;
+                let mut response =
+                    http::Response::new(kube::client::Body::from(status.as_bytes().to_vec()));
+                *response.status_mut() = http::StatusCode::NOT_FOUND;
+                response
+            };
+            async move { Ok::<_, Infallible>(response) }
+        });
+        kube::Client::new(service, "default")
+    }
+
+    #[tokio::test]
+    async fn get() {
+        let client = client(
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/web-0/http_requests",
+            PODS,
+        );
+        let api = CustomMetricsApi::<corev1::Pod>::default_namespaced(client);
+        let list = api.get("web-0", "http_requests").await.unwrap();
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 0.066);
+    }
+
+    #[tokio::test]
+    async fn list() {
+        let client = client(
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/*/http_requests?labelSelector=app%3Dweb",
+            PODS,
+        );
+        let api = CustomMetricsApi::<corev1::Pod>::namespaced(client, "default");
+        let list = api.list("app=web", "http_requests").await.unwrap();
+        assert_eq!(
+            list.items[0].described_object.name.as_deref(),
+            Some("web-0")
+        );
+    }
+
+    #[tokio::test]
+    async fn namespace_metric() {
+        let client = client(
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/metrics/queue_length",
+            NAMESPACE,
+        );
+        let api = CustomMetricsApi::<corev1::Namespace>::all(client);
+        let list = api
+            .namespace_metric("default", "queue_length")
+            .await
+            .unwrap();
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 12.0);
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let client = client("/", PODS);
+        let api = CustomMetricsApi::<corev1::Node>::all(client);
+        let err = api.get("node-1", "load_average").await.unwrap_err();
+        assert!(matches!(err, kube::Error::Api(response) if response.code == 404));
+    }
+}
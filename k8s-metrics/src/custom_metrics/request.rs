@@ -0,0 +1,133 @@
+use percent_encoding::utf8_percent_encode;
+
+use super::*;
+
+/// Query parameters of custom metrics requests
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricParams {
+    /// `labelSelector` selecting the described objects,
+    /// only meaningful when querying all objects (`*`)
+    ///
+    pub label_selector: Option<String>,
+
+    /// `metricLabelSelector` selecting the metric series
+    ///
+    pub metric_label_selector: Option<String>,
+}
+
+impl MetricParams {
+    /// Select described objects by their labels
+    ///
+    pub fn labels(mut self, selector: impl ToString) -> Self {
+        self.label_selector = Some(selector.to_string());
+        self
+    }
+
+    /// Select metric series by their labels
+    ///
+    pub fn metric_labels(mut self, selector: impl ToString) -> Self {
+        self.metric_label_selector = Some(selector.to_string());
+        self
+    }
+
+    fn query(&self) -> Option<String> {
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        let non_empty = |selector: &&String| !selector.is_empty();
+        if let Some(selector) = self.label_selector.as_ref().filter(non_empty) {
+            query.append_pair("labelSelector", selector);
+        }
+        if let Some(selector) = self.metric_label_selector.as_ref().filter(non_empty) {
+            query.append_pair("metricLabelSelector", selector);
+        }
+        let query = query.finish();
+        (!query.is_empty()).then_some(query)
+    }
+}
+
+/// Path of `metric` describing object `name` (or `*` for all objects) of `resource` (e.g. `pods`)
+///
+/// `namespace`, `name` and `metric` are percent-encoded.
+///
+pub(super) fn object_path(
+    api_version: &str,
+    namespace: Option<&str>,
+    resource: &str,
+    name: &str,
+    metric: &str,
+) -> String {
+    let name = utf8_percent_encode(name, PATH_SEGMENT);
+    let metric = utf8_percent_encode(metric, PATH_SEGMENT);
+    match namespace {
+        Some(namespace) => {
+            let namespace = utf8_percent_encode(namespace, PATH_SEGMENT);
+            format!("/apis/{api_version}/namespaces/{namespace}/{resource}/{name}/{metric}")
+        }
+        None => format!("/apis/{api_version}/{resource}/{name}/{metric}"),
+    }
+}
+
+/// Path of `metric` describing the namespace itself
+///
+pub(super) fn namespace_path(api_version: &str, namespace: &str, metric: &str) -> String {
+    let namespace = utf8_percent_encode(namespace, PATH_SEGMENT);
+    let metric = utf8_percent_encode(metric, PATH_SEGMENT);
+    format!("/apis/{api_version}/namespaces/{namespace}/metrics/{metric}")
+}
+
+pub(super) fn get(
+    path: String,
+    params: &MetricParams,
+) -> Result<http::Request<Vec<u8>>, http::Error> {
+    let uri = match params.query() {
+        Some(query) => format!("{path}?{query}"),
+        None => path,
+    };
+
+    http::Request::get(uri).body(Vec::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoded_path() {
+        let path = object_path(
+            "custom.metrics.k8s.io/v1beta2",
+            Some("default"),
+            "pods",
+            "*",
+            "requests/s?verb=GET#100%",
+        );
+        assert_eq!(
+            path,
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/*/requests%2Fs%3Fverb=GET%23100%25"
+        );
+        let path = namespace_path("custom.metrics.k8s.io/v1beta2", "jobs", "queue/depth");
+        assert_eq!(
+            path,
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/jobs/metrics/queue%2Fdepth"
+        );
+    }
+
+    #[test]
+    fn query() {
+        let params = MetricParams::default().labels("app=web").metric_labels("");
+        let request = get(
+            object_path(
+                "custom.metrics.k8s.io/v1beta1",
+                None,
+                "nodes",
+                "node-1",
+                "load",
+            ),
+            &params,
+        )
+        .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta1/nodes/node-1/load?labelSelector=app%3Dweb"
+        );
+    }
+}
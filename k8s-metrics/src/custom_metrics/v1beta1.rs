@@ -0,0 +1,183 @@
+use std::marker::PhantomData;
+
+use super::*;
+
+/// `MetricValue` is the metric value for some object
+///
+/// Unlike `v1beta2::MetricValue` the metric is identified by `metric_name`
+/// and `selector` directly rather than by `MetricIdentifier`.
+///
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricValue<M> {
+    #[serde(default)]
+    pub metadata: metav1::ObjectMeta,
+
+    /// a reference to the described object
+    ///
+    pub described_object: corev1::ObjectReference,
+
+    /// the name of the metric
+    ///
+    pub metric_name: String, // `json:"metricName" protobuf:"bytes,2,name=metricName"`
+
+    /// indicates the time at which the metrics were produced
+    ///
+    pub timestamp: metav1::Time,
+
+    /// indicates the window ([Timestamp-Window, Timestamp]) from
+    /// which these metrics were calculated, when returning rate
+    /// metrics calculated from cumulative metrics (or zero for
+    /// non-calculated instantaneous metrics).
+    ///
+    #[serde(default, rename = "window", skip_serializing_if = "Option::is_none")]
+    pub window_seconds: Option<i64>, // `json:"window,omitempty" protobuf:"bytes,4,opt,name=window"`
+
+    /// the value of the metric for this
+    ///
+    pub value: resource::Quantity, // `json:"value" protobuf:"bytes,5,name=value"`
+
+    /// selector represents the label selector that could be used to select
+    /// this metric, and will generally just be the selector passed in to
+    /// the query used to fetch this metric.
+    /// When left blank, only the metric's Name will be used to gather metrics.
+    /// +optional
+    ///
+    pub selector: Option<metav1::LabelSelector>, // `json:"selector" protobuf:"bytes,6,opt,name=selector"`
+
+    #[serde(skip)]
+    pub phantom: PhantomData<M>,
+}
+
+impl<M> MetricValue<M> {
+    /// `v1beta2::MetricIdentifier` of this metric
+    ///
+    pub fn metric(&self) -> v1beta2::MetricIdentifier {
+        v1beta2::MetricIdentifier {
+            name: self.metric_name.clone(),
+            selector: self.selector.clone(),
+        }
+    }
+}
+
+impl<M: CustomMetric> k8s::Resource for MetricValue<M> {
+    const API_VERSION: &'static str = "custom.metrics.k8s.io/v1beta1";
+    const GROUP: &'static str = "custom.metrics.k8s.io";
+    const KIND: &'static str = M::KIND;
+    const VERSION: &'static str = "v1beta1";
+    const URL_PATH_SEGMENT: &'static str = M::URL_PATH_SEGMENT;
+    type Scope = M::Scope;
+}
+
+impl<M: CustomMetric> k8s::Metadata for MetricValue<M> {
+    type Ty = metav1::ObjectMeta;
+
+    fn metadata(&self) -> &<Self as k8s::Metadata>::Ty {
+        &self.metadata
+    }
+
+    fn metadata_mut(&mut self) -> &mut <Self as k8s::Metadata>::Ty {
+        &mut self.metadata
+    }
+}
+
+impl<M: CustomMetric> k8s::ListableResource for MetricValue<M> {
+    const LIST_KIND: &'static str = "MetricValueList";
+}
+
+pub type MetricValueList<M> = k8s::List<MetricValue<M>>;
+
+impl<M> From<v1beta2::MetricValue<M>> for MetricValue<M> {
+    fn from(value: v1beta2::MetricValue<M>) -> Self {
+        Self {
+            metadata: value.metadata,
+            described_object: value.described_object,
+            metric_name: value.metric.name,
+            timestamp: value.timestamp,
+            window_seconds: value.window_seconds,
+            value: value.value,
+            selector: value.metric.selector,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<M> From<MetricValue<M>> for v1beta2::MetricValue<M> {
+    fn from(value: MetricValue<M>) -> Self {
+        let metric = v1beta2::MetricIdentifier {
+            name: value.metric_name,
+            selector: value.selector,
+        };
+
+        Self {
+            metadata: value.metadata,
+            described_object: value.described_object,
+            metric,
+            timestamp: value.timestamp,
+            window_seconds: value.window_seconds,
+            value: value.value,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    /// Captured from prometheus-adapter serving `custom.metrics.k8s.io/v1beta1`
+    ///
+    const PROMETHEUS_ADAPTER: &str = r#"{
+  "kind": "MetricValueList",
+  "apiVersion": "custom.metrics.k8s.io/v1beta1",
+  "metadata": {
+    "selfLink": "/apis/custom.metrics.k8s.io/v1beta1/namespaces/default/pods/%2A/http_requests"
+  },
+  "items": [
+    {
+      "describedObject": {
+        "kind": "Pod",
+        "namespace": "default",
+        "name": "sample-app-579bc6c9f7-v7wf4",
+        "apiVersion": "/v1"
+      },
+      "metricName": "http_requests",
+      "timestamp": "2024-03-12T09:17:03Z",
+      "window": 60,
+      "value": "66m",
+      "selector": {
+        "matchLabels": {
+          "app": "sample-app"
+        }
+      }
+    }
+  ]
+}"#;
+
+    #[test]
+    fn prometheus_adapter() {
+        let list: MetricValueList<corev1::Pod> = json::from_str(PROMETHEUS_ADAPTER).unwrap();
+        let value = &list.items[0];
+        assert_eq!(value.metric_name, "http_requests");
+        assert_eq!(value.window_seconds, Some(60));
+        assert!(value.selector.is_some());
+        assert_eq!(value.metric().name, "http_requests");
+    }
+
+    #[test]
+    fn lossless_conversion() {
+        let list: MetricValueList<corev1::Pod> = json::from_str(PROMETHEUS_ADAPTER).unwrap();
+        let value = list.items.into_iter().next().unwrap();
+        let original = json::to_value(&value).unwrap();
+
+        let value = v1beta2::MetricValue::from(value);
+        assert_eq!(value.metric.name, "http_requests");
+        assert_eq!(value.window_seconds, Some(60));
+        let selector = value.metric.selector.as_ref().unwrap();
+        assert_eq!(selector.match_labels.as_ref().unwrap()["app"], "sample-app");
+
+        let value = MetricValue::from(value);
+        assert_eq!(json::to_value(&value).unwrap(), original);
+    }
+}
@@ -2,9 +2,18 @@ use std::marker::PhantomData;
 
 use super::*;
 
+pub use builder::MetricValueBuilder;
+pub use dynamic::{CustomMetricResource, DynamicKind, DynamicMetricValue, DynamicMetricValueList};
+
+mod builder;
+mod dynamic;
+
+const API_VERSION: &str = "custom.metrics.k8s.io/v1beta2";
+const LIST_KIND: &str = "MetricValueList";
+
 /// `MetricIdentifier` identifies a metric by name and, optionally, selector
 ///
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct MetricIdentifier {
     /// name is the name of the given metric
     ///
@@ -29,9 +38,10 @@ impl MetricIdentifier {
 
 /// `MetricValue` is the metric value for some object
 ///
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "camelCase")]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct MetricValue<M> {
+    #[serde(default)]
     pub metadata: metav1::ObjectMeta,
 
     /// a reference to the described object
@@ -49,7 +59,8 @@ pub struct MetricValue<M> {
     /// metrics calculated from cumulative metrics (or zero for
     /// non-calculated instantaneous metrics).
     ///
-    pub window_seconds: i64, // `json:"windowSeconds,omitempty" protobuf:"bytes,4,opt,name=windowSeconds"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window_seconds: Option<i64>, // `json:"windowSeconds,omitempty" protobuf:"bytes,4,opt,name=windowSeconds"`
 
     /// the value of the metric for this
     ///
@@ -59,8 +70,8 @@ pub struct MetricValue<M> {
     pub phantom: PhantomData<M>,
 }
 
-impl<M: k8s::Resource> k8s::Resource for MetricValue<M> {
-    const API_VERSION: &'static str = "custom.metrics.k8s.io/v1beta2";
+impl<M: CustomMetric> k8s::Resource for MetricValue<M> {
+    const API_VERSION: &'static str = API_VERSION;
     const GROUP: &'static str = "custom.metrics.k8s.io";
     const KIND: &'static str = M::KIND;
     const VERSION: &'static str = "v1beta2";
@@ -68,7 +79,7 @@ impl<M: k8s::Resource> k8s::Resource for MetricValue<M> {
     type Scope = M::Scope;
 }
 
-impl<M: k8s::Metadata> k8s::Metadata for MetricValue<M> {
+impl<M: CustomMetric> k8s::Metadata for MetricValue<M> {
     type Ty = metav1::ObjectMeta;
 
     fn metadata(&self) -> &<Self as k8s_openapi::Metadata>::Ty {
@@ -80,57 +91,51 @@ impl<M: k8s::Metadata> k8s::Metadata for MetricValue<M> {
     }
 }
 
-impl<M> MetricValue<M>
-where
-    M: k8s::Metadata<Ty = metav1::ObjectMeta>,
-{
+impl<M: CustomMetric> MetricValue<M> {
     /// Create new `MetricValue` for given `object` and `namespace`
     ///
     pub fn new(name: impl ToString, namespace: impl ToString, object: impl ToString) -> Self {
-        let name = name.to_string();
-        let namespace = namespace.to_string();
-        let object_name = object.to_string();
-
-        let metadata = metav1::ObjectMeta {
-            name: Some(name.clone()),
-            namespace: Some(namespace.clone()),
-            ..default()
-        };
-
         let described_object = corev1::ObjectReference {
-            name: Some(object_name),
-            namespace: Some(namespace),
+            name: Some(object.to_string()),
+            namespace: Some(namespace.to_string()),
             api_version: Some(M::API_VERSION.to_string()),
             kind: Some(M::KIND.to_string()),
             ..default()
         };
 
-        let metric = MetricIdentifier::new(name);
+        Self::describing(name, described_object)
+    }
 
-        let timestamp = metav1::Time(DateTime::<Utc>::default());
+    /// Create `MetricValue` describing object by its `corev1::ObjectReference`
+    ///
+    pub fn with_object_ref(name: impl ToString, object_ref: &corev1::ObjectReference) -> Self {
+        Self::describing(name, object_ref.clone())
+    }
+}
 
-        Self {
-            metadata,
-            described_object,
-            metric,
-            timestamp,
-            window_seconds: default(),
-            value: default(),
+impl<M> MetricValue<M> {
+    /// Forget the statically known kind of the described object
+    ///
+    pub fn into_dynamic(self) -> DynamicMetricValue {
+        MetricValue {
+            metadata: self.metadata,
+            described_object: self.described_object,
+            metric: self.metric,
+            timestamp: self.timestamp,
+            window_seconds: self.window_seconds,
+            value: self.value,
             phantom: PhantomData,
         }
     }
 
-    /// Create `MetricValue` describing object by its `corev1::ObjectReference`
-    ///
-    pub fn with_object_ref(name: impl ToString, object_ref: &corev1::ObjectReference) -> Self {
+    fn describing(name: impl ToString, described_object: corev1::ObjectReference) -> Self {
         let name = name.to_string();
 
         let metadata = metav1::ObjectMeta {
             name: Some(name.clone()),
-            namespace: object_ref.namespace.clone(),
+            namespace: described_object.namespace.clone(),
             ..default()
         };
-        let described_object = object_ref.clone();
         let metric = MetricIdentifier::new(name);
         let timestamp = metav1::Time(DateTime::<Utc>::default());
 
@@ -144,7 +149,12 @@ where
             phantom: PhantomData,
         }
     }
+}
 
+impl<M> MetricValue<M>
+where
+    M: k8s::Metadata<Ty = metav1::ObjectMeta>,
+{
     /// Create `MetricValue` describing `object`
     ///
     pub fn with_object(name: impl ToString, object: &M) -> Self {
@@ -153,8 +163,85 @@ where
     }
 }
 
-impl<M: k8s::ListableResource> k8s::ListableResource for MetricValue<M> {
-    const LIST_KIND: &'static str = "MetricValueList";
+impl<M> MetricValue<M>
+where
+    M: CustomMetric<Scope = k8s::NamespaceResourceScope>,
+{
+    /// Request `metric` of the object `name` in `namespace`
+    ///
+    pub fn get_namespaced(
+        namespace: &str,
+        name: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        let path = request::object_path(
+            <Self as k8s::Resource>::API_VERSION,
+            Some(namespace),
+            M::URL_PATH_SEGMENT,
+            name,
+            metric,
+        );
+        request::get(path, params)
+    }
+
+    /// Request `metric` of all the objects in `namespace` selected by `params`
+    ///
+    pub fn list_namespaced(
+        namespace: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        Self::get_namespaced(namespace, "*", metric, params)
+    }
+}
+
+impl<M> MetricValue<M>
+where
+    M: CustomMetric<Scope = k8s::ClusterResourceScope>,
+{
+    /// Request `metric` of the cluster scoped object `name`
+    ///
+    pub fn get(
+        name: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        let path = request::object_path(
+            <Self as k8s::Resource>::API_VERSION,
+            None,
+            M::URL_PATH_SEGMENT,
+            name,
+            metric,
+        );
+        request::get(path, params)
+    }
+
+    /// Request `metric` of all the cluster scoped objects selected by `params`
+    ///
+    pub fn list(
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        Self::get("*", metric, params)
+    }
+}
+
+impl MetricValue<corev1::Namespace> {
+    /// Request `metric` describing the `namespace` itself
+    ///
+    pub fn namespace_metric(
+        namespace: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        let path = request::namespace_path(<Self as k8s::Resource>::API_VERSION, namespace, metric);
+        request::get(path, params)
+    }
+}
+
+impl<M: CustomMetric> k8s::ListableResource for MetricValue<M> {
+    const LIST_KIND: &'static str = LIST_KIND;
 }
 
 pub type MetricValueList<M> = k8s::List<MetricValue<M>>;
@@ -173,3 +260,202 @@ where
         ..default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    /// Captured from prometheus-adapter
+    ///
+    const PROMETHEUS_ADAPTER: &str = r#"{
+  "kind": "MetricValueList",
+  "apiVersion": "custom.metrics.k8s.io/v1beta2",
+  "metadata": {},
+  "items": [
+    {
+      "describedObject": {
+        "kind": "Pod",
+        "namespace": "default",
+        "name": "sample-app-579bc6c9f7-v7wf4",
+        "apiVersion": "/v1"
+      },
+      "metric": {
+        "name": "http_requests",
+        "selector": null
+      },
+      "timestamp": "2024-03-12T09:17:03Z",
+      "value": "66m"
+    },
+    {
+      "describedObject": {
+        "kind": "Pod",
+        "namespace": "default",
+        "name": "sample-app-579bc6c9f7-x2k9q",
+        "apiVersion": "/v1"
+      },
+      "metric": {
+        "name": "http_requests",
+        "selector": {
+          "matchLabels": {
+            "app": "sample-app"
+          }
+        }
+      },
+      "timestamp": "2024-03-12T09:17:03Z",
+      "windowSeconds": 60,
+      "value": "1200m"
+    }
+  ]
+}"#;
+
+    #[test]
+    fn prometheus_adapter() {
+        let list: MetricValueList<corev1::Pod> = json::from_str(PROMETHEUS_ADAPTER).unwrap();
+        assert_eq!(list.items.len(), 2);
+
+        let value = &list.items[0];
+        assert_eq!(
+            value.described_object.name.as_deref(),
+            Some("sample-app-579bc6c9f7-v7wf4")
+        );
+        assert_eq!(value.described_object.kind.as_deref(), Some("Pod"));
+        assert_eq!(value.metric.name, "http_requests");
+        assert!(value.metric.selector.is_none());
+        assert_eq!(value.window_seconds, None);
+        assert_eq!(value.value.to_f64().unwrap(), 0.066);
+
+        let value = &list.items[1];
+        assert!(value.metric.selector.is_some());
+        assert_eq!(value.window_seconds, Some(60));
+    }
+
+    #[test]
+    fn serialize() {
+        let value = MetricValue::<corev1::Pod>::new("http_requests", "default", "web-0");
+        let value = json::to_value(&value).unwrap();
+        assert_eq!(value["describedObject"]["name"], "web-0");
+        assert_eq!(value["metric"]["name"], "http_requests");
+        assert!(value.get("windowSeconds").is_none());
+        assert!(value.get("described_object").is_none());
+        assert!(value.get("window_seconds").is_none());
+    }
+
+    #[test]
+    fn get_namespaced() {
+        let params = MetricParams::default().metric_labels("verb=GET");
+        let request = MetricValue::<corev1::Pod>::get_namespaced(
+            "default",
+            "web-0",
+            "http_requests",
+            &params,
+        )
+        .unwrap();
+        assert_eq!(request.method(), http::Method::GET);
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/web-0/http_requests?metricLabelSelector=verb%3DGET"
+        );
+    }
+
+    #[test]
+    fn list_namespaced() {
+        let params = MetricParams::default()
+            .labels("app in (web,api)")
+            .metric_labels("verb=GET");
+        let request =
+            MetricValue::<corev1::Pod>::list_namespaced("default", "http_requests", &params)
+                .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/*/http_requests?labelSelector=app+in+%28web%2Capi%29&metricLabelSelector=verb%3DGET"
+        );
+    }
+
+    #[test]
+    fn cluster_scoped() {
+        let params = MetricParams::default();
+        let request = MetricValue::<corev1::Node>::get("node-1", "load_average", &params).unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/nodes/node-1/load_average"
+        );
+        let request = MetricValue::<corev1::Node>::list("load_average", &params).unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/nodes/*/load_average"
+        );
+    }
+
+    #[test]
+    fn namespace_metric() {
+        let params = MetricParams::default();
+        let request =
+            MetricValue::<corev1::Namespace>::namespace_metric("default", "queue_length", &params)
+                .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/metrics/queue_length"
+        );
+    }
+
+    #[test]
+    fn encoded_name() {
+        let params = MetricParams::default();
+        let request = MetricValue::<corev1::Pod>::get_namespaced(
+            "default",
+            "web 0",
+            "http_requests",
+            &params,
+        )
+        .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/pods/web%200/http_requests"
+        );
+    }
+
+    /// CRD without Rust resource type
+    ///
+    #[derive(Debug)]
+    struct Widget;
+
+    impl CustomMetric for Widget {
+        const API_VERSION: &'static str = "example.com/v1";
+        const KIND: &'static str = "Widget";
+        const URL_PATH_SEGMENT: &'static str = "widgets.example.com";
+        type Scope = k8s::NamespaceResourceScope;
+    }
+
+    #[test]
+    fn custom_resource() {
+        let value = MetricValue::<Widget>::new("queue_length", "default", "widget-1");
+        assert_eq!(value.described_object.kind.as_deref(), Some("Widget"));
+        assert_eq!(
+            value.described_object.api_version.as_deref(),
+            Some("example.com/v1")
+        );
+
+        let request = MetricValue::<Widget>::list_namespaced(
+            "default",
+            "queue_length",
+            &MetricParams::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/widgets.example.com/*/queue_length"
+        );
+
+        let text = json::to_string(&MetricValueList::<Widget> {
+            items: vec![value],
+            metadata: default(),
+        })
+        .unwrap();
+        let list: MetricValueList<Widget> = json::from_str(&text).unwrap();
+        assert_eq!(
+            list.items[0].described_object.name.as_deref(),
+            Some("widget-1")
+        );
+    }
+}
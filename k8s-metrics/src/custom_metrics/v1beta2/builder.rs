@@ -0,0 +1,152 @@
+use super::*;
+
+/// Builder for `MetricValue`
+///
+#[derive(Debug)]
+pub struct MetricValueBuilder<M> {
+    value: MetricValue<M>,
+}
+
+impl<M: CustomMetric> MetricValue<M> {
+    /// Start building `MetricValue` of metric `name` produced right now
+    ///
+    pub fn builder(name: impl ToString) -> MetricValueBuilder<M> {
+        let described_object = corev1::ObjectReference {
+            api_version: Some(M::API_VERSION.to_string()),
+            kind: Some(M::KIND.to_string()),
+            ..default()
+        };
+        let value = Self::describing(name, described_object);
+        MetricValueBuilder::from(value).timestamp(now())
+    }
+}
+
+impl<M> MetricValueBuilder<M> {
+    /// Describe object `name` in `namespace`
+    ///
+    pub fn object(mut self, namespace: impl ToString, name: impl ToString) -> Self {
+        let namespace = namespace.to_string();
+        self.value.metadata.namespace = Some(namespace.clone());
+        self.value.described_object.namespace = Some(namespace);
+        self.value.described_object.name = Some(name.to_string());
+        self
+    }
+
+    /// Describe object by its `corev1::ObjectReference`
+    ///
+    pub fn object_ref(mut self, object_ref: &corev1::ObjectReference) -> Self {
+        self.value.metadata.namespace = object_ref.namespace.clone();
+        self.value.described_object = object_ref.clone();
+        self
+    }
+
+    /// Set the value of the metric, numbers are converted to canonical quantity
+    ///
+    pub fn value(mut self, value: impl ToQuantity) -> Self {
+        self.value.value = value.to_quantity();
+        self
+    }
+
+    /// Set the time at which the metric was produced (defaults to now)
+    ///
+    pub fn timestamp(mut self, timestamp: metav1::Time) -> Self {
+        self.value.timestamp = timestamp;
+        self
+    }
+
+    /// Set the window the rate metric was calculated from, in whole seconds
+    ///
+    pub fn window(mut self, window: time::Duration) -> Self {
+        self.value.window_seconds = Some(i64::try_from(window.as_secs()).unwrap_or(i64::MAX));
+        self
+    }
+
+    /// Set the label selector used to select the metric
+    ///
+    pub fn selector(mut self, selector: metav1::LabelSelector) -> Self {
+        self.value.metric.selector = Some(selector);
+        self
+    }
+
+    pub fn build(self) -> MetricValue<M> {
+        self.value
+    }
+}
+
+impl<M> MetricValueBuilder<M>
+where
+    M: k8s::Metadata<Ty = metav1::ObjectMeta>,
+{
+    /// Describe `object`
+    ///
+    pub fn described_object(self, object: &M) -> Self {
+        self.object_ref(&object_ref(object))
+    }
+}
+
+impl<M> From<MetricValue<M>> for MetricValueBuilder<M> {
+    fn from(value: MetricValue<M>) -> Self {
+        Self { value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let selector = metav1::LabelSelector::parse("verb=GET").unwrap();
+        let value = MetricValue::<corev1::Pod>::builder("http_requests")
+            .object("default", "web-0")
+            .value(0.5)
+            .window(time::Duration::from_secs(60))
+            .selector(selector.clone())
+            .build();
+
+        assert_eq!(value.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(value.described_object.name.as_deref(), Some("web-0"));
+        assert_eq!(value.described_object.kind.as_deref(), Some("Pod"));
+        assert_eq!(value.value, resource::Quantity("500m".to_string()));
+        assert_eq!(value.window_seconds, Some(60));
+        assert_eq!(value.metric.selector, Some(selector));
+        assert!(value.timestamp.0 > DateTime::<Utc>::default());
+    }
+
+    #[test]
+    fn described_object() {
+        let pod = corev1::Pod {
+            metadata: metav1::ObjectMeta {
+                name: Some("web-0".to_string()),
+                namespace: Some("default".to_string()),
+                uid: Some("0c4a4cda-8b3b-4f7e-9a3c-1e1f1d2b3a4c".to_string()),
+                ..default()
+            },
+            ..default()
+        };
+        let timestamp = metav1::Time(DateTime::<Utc>::default());
+        let value = MetricValue::builder("http_requests")
+            .described_object(&pod)
+            .timestamp(timestamp)
+            .value(25)
+            .build();
+        let other = MetricValue::with_object("http_requests", &pod);
+
+        assert_eq!(
+            value,
+            MetricValue {
+                value: 25.to_quantity(),
+                ..other
+            }
+        );
+        assert_eq!(value.clone(), value);
+    }
+
+    #[test]
+    fn dynamic() {
+        let resource = CustomMetricResource::new("example.com/v1", "Widget", "widgets", true);
+        let value = DynamicMetricValue::new(&resource, "queue_length", Some("default"), "w-1");
+        let value = MetricValueBuilder::from(value).value(7_u32).build();
+        assert_eq!(value.value, resource::Quantity("7".to_string()));
+    }
+}
@@ -0,0 +1,259 @@
+use super::*;
+
+/// Marker for `MetricValue` describing objects of kind only known at runtime
+///
+/// The actual kind is described by `CustomMetricResource`,
+/// much like kube's `DynamicObject` is described by `ApiResource`.
+/// `DynamicMetricValue` is not a `k8s::Resource`, its requests are built
+/// by `CustomMetricResource::get` and `CustomMetricResource::list`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DynamicKind;
+
+pub type DynamicMetricValue = MetricValue<DynamicKind>;
+
+/// List of `DynamicMetricValue`
+///
+pub type DynamicMetricValueList = DynamicList<DynamicMetricValue>;
+
+impl DynamicListItem for DynamicMetricValue {
+    const LIST_API_VERSION: &'static str = API_VERSION;
+    const LIST_KIND: &'static str = LIST_KIND;
+}
+
+/// Runtime description of the objects described by custom metrics
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CustomMetricResource {
+    /// API group, empty for the core group
+    ///
+    pub group: String,
+    pub version: String,
+    pub api_version: String,
+    pub kind: String,
+    /// plural resource name, e.g. `widgets`
+    ///
+    pub plural: String,
+    pub namespaced: bool,
+}
+
+impl CustomMetricResource {
+    /// Describe objects of `kind` served as `plural` resource in `api_version` (e.g. `example.com/v1`)
+    ///
+    pub fn new(
+        api_version: impl ToString,
+        kind: impl ToString,
+        plural: impl ToString,
+        namespaced: bool,
+    ) -> Self {
+        let api_version = api_version.to_string();
+        let (group, version) = api_version
+            .split_once('/')
+            .unwrap_or(("", api_version.as_str()));
+
+        Self {
+            group: group.to_string(),
+            version: version.to_string(),
+            api_version: api_version.clone(),
+            kind: kind.to_string(),
+            plural: plural.to_string(),
+            namespaced,
+        }
+    }
+
+    /// Describe resource discovered in `group_version` (e.g. `example.com/v1`)
+    ///
+    pub fn from_api_resource(group_version: &str, resource: &metav1::APIResource) -> Self {
+        let api_version = match (&resource.group, &resource.version) {
+            (Some(group), Some(version)) if group.is_empty() => version.clone(),
+            (Some(group), Some(version)) => format!("{group}/{version}"),
+            _ => group_version.to_string(),
+        };
+        Self::new(
+            api_version,
+            &resource.kind,
+            &resource.name,
+            resource.namespaced,
+        )
+    }
+
+    /// Resource as used in the custom metrics API paths, e.g. `widgets.example.com`
+    ///
+    pub fn url_path_segment(&self) -> String {
+        if self.group.is_empty() {
+            self.plural.clone()
+        } else {
+            format!("{}.{}", self.plural, self.group)
+        }
+    }
+
+    /// Request `metric` of the object `name`
+    ///
+    /// `namespace` is ignored for cluster scoped resources.
+    ///
+    pub fn get(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        let path = request::object_path(
+            API_VERSION,
+            namespace.filter(|_| self.namespaced),
+            &self.url_path_segment(),
+            name,
+            metric,
+        );
+        request::get(path, params)
+    }
+
+    /// Request `metric` of all the objects selected by `params`
+    ///
+    /// `namespace` is ignored for cluster scoped resources.
+    ///
+    pub fn list(
+        &self,
+        namespace: Option<&str>,
+        metric: &str,
+        params: &MetricParams,
+    ) -> Result<http::Request<Vec<u8>>, http::Error> {
+        self.get(namespace, "*", metric, params)
+    }
+
+    fn object_ref(&self, namespace: Option<&str>, name: &str) -> corev1::ObjectReference {
+        corev1::ObjectReference {
+            name: Some(name.to_string()),
+            namespace: namespace
+                .filter(|_| self.namespaced)
+                .map(ToString::to_string),
+            api_version: Some(self.api_version.clone()),
+            kind: Some(self.kind.clone()),
+            ..default()
+        }
+    }
+}
+
+impl DynamicMetricValue {
+    /// Create new `MetricValue` for given `object` of `resource`
+    ///
+    /// `namespace` is ignored for cluster scoped resources.
+    ///
+    pub fn new(
+        resource: &CustomMetricResource,
+        name: impl ToString,
+        namespace: Option<&str>,
+        object: &str,
+    ) -> Self {
+        Self::describing(name, resource.object_ref(namespace, object))
+    }
+
+    /// Create `MetricValue` describing object by its `corev1::ObjectReference`
+    ///
+    pub fn with_object_ref(name: impl ToString, object_ref: &corev1::ObjectReference) -> Self {
+        Self::describing(name, object_ref.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    fn widgets() -> CustomMetricResource {
+        CustomMetricResource::new("example.com/v1", "Widget", "widgets", true)
+    }
+
+    #[test]
+    fn resource() {
+        let resource = widgets();
+        assert_eq!(resource.group, "example.com");
+        assert_eq!(resource.version, "v1");
+        assert_eq!(resource.url_path_segment(), "widgets.example.com");
+
+        let nodes = CustomMetricResource::new("v1", "Node", "nodes", false);
+        assert_eq!(nodes.group, "");
+        assert_eq!(nodes.url_path_segment(), "nodes");
+    }
+
+    #[test]
+    fn from_api_resource() {
+        let api_resource = metav1::APIResource {
+            name: "widgets".to_string(),
+            kind: "Widget".to_string(),
+            namespaced: true,
+            ..default()
+        };
+        let resource = CustomMetricResource::from_api_resource("example.com/v1", &api_resource);
+        assert_eq!(resource, widgets());
+    }
+
+    #[test]
+    fn new() {
+        let value = DynamicMetricValue::new(&widgets(), "queue_length", Some("default"), "w-1");
+        let object = &value.described_object;
+        assert_eq!(object.kind.as_deref(), Some("Widget"));
+        assert_eq!(object.api_version.as_deref(), Some("example.com/v1"));
+        assert_eq!(object.namespace.as_deref(), Some("default"));
+        assert_eq!(value.metric.name, "queue_length");
+
+        let other = DynamicMetricValue::with_object_ref("queue_length", object);
+        assert_eq!(other.described_object, value.described_object);
+        assert_eq!(other.metadata, value.metadata);
+    }
+
+    #[test]
+    fn requests() {
+        let params = MetricParams::default().labels("app=web");
+        let request = widgets()
+            .list(Some("default"), "queue_length", &params)
+            .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/namespaces/default/widgets.example.com/*/queue_length?labelSelector=app%3Dweb"
+        );
+
+        let nodes = CustomMetricResource::new("v1", "Node", "nodes", false);
+        let request = nodes
+            .get(Some("default"), "node-1", "load_average", &default())
+            .unwrap();
+        assert_eq!(
+            request.uri(),
+            "/apis/custom.metrics.k8s.io/v1beta2/nodes/node-1/load_average"
+        );
+    }
+
+    #[test]
+    fn list() {
+        let text = r#"{
+  "kind": "MetricValueList",
+  "apiVersion": "custom.metrics.k8s.io/v1beta2",
+  "metadata": {},
+  "items": [
+    {
+      "describedObject": {
+        "kind": "Widget",
+        "namespace": "default",
+        "name": "w-1",
+        "apiVersion": "example.com/v1"
+      },
+      "metric": {
+        "name": "queue_length",
+        "selector": null
+      },
+      "timestamp": "2024-03-12T09:17:03Z",
+      "value": "7"
+    }
+  ]
+}"#;
+        let list: DynamicMetricValueList = json::from_str(text).unwrap();
+        assert_eq!(
+            list.items[0].described_object.kind.as_deref(),
+            Some("Widget")
+        );
+
+        let list = json::to_value(&list).unwrap();
+        assert_eq!(list["kind"], "MetricValueList");
+        assert_eq!(list["apiVersion"], "custom.metrics.k8s.io/v1beta2");
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use super::*;
+
+/// Metric advertised by custom or external metrics server discovery
+///
+/// Custom metrics are advertised as `{resource}/{metric}`, e.g. `pods/http_requests`
+/// or `deployments.apps/http_requests`, external metrics just by their name.
+///
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DiscoveredMetric {
+    /// resource described by the metric, `None` for external metrics
+    ///
+    pub resource: Option<String>,
+    /// the name of the metric
+    ///
+    pub metric: String,
+    /// whether the metric is requested in the namespace
+    ///
+    pub namespaced: bool,
+}
+
+/// Catalogue of the metrics a metrics API group version provides
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricCatalogue {
+    pub group_version: String,
+    pub metrics: Vec<DiscoveredMetric>,
+}
+
+impl DiscoveredMetric {
+    pub fn new(resource: &metav1::APIResource) -> Self {
+        let (resource_name, metric) = match resource.name.split_once('/') {
+            Some((resource, metric)) => (Some(resource.to_string()), metric.to_string()),
+            None => (None, resource.name.clone()),
+        };
+
+        Self {
+            resource: resource_name,
+            metric,
+            namespaced: resource.namespaced,
+        }
+    }
+
+    /// `APIResource` advertising this metric, served as `kind` (e.g. `MetricValueList`)
+    ///
+    pub fn api_resource(&self, kind: &str) -> metav1::APIResource {
+        metav1::APIResource {
+            name: self.to_string(),
+            namespaced: self.namespaced,
+            kind: kind.to_string(),
+            verbs: vec!["get".to_string()],
+            ..default()
+        }
+    }
+}
+
+impl fmt::Display for DiscoveredMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(resource) = &self.resource {
+            write!(f, "{resource}/")?;
+        }
+        f.write_str(&self.metric)
+    }
+}
+
+impl MetricCatalogue {
+    /// Sorted and deduplicated catalogue of metrics in `list`
+    ///
+    pub fn new(list: &metav1::APIResourceList) -> Self {
+        let metrics = list
+            .resources
+            .iter()
+            .map(DiscoveredMetric::new)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        Self {
+            group_version: list.group_version.clone(),
+            metrics,
+        }
+    }
+
+    /// Resources described by the metrics
+    ///
+    pub fn resources(&self) -> BTreeSet<&str> {
+        self.metrics
+            .iter()
+            .filter_map(|metric| metric.resource.as_deref())
+            .collect()
+    }
+
+    /// Metrics describing `resource`
+    ///
+    pub fn metrics_for<'a>(
+        &'a self,
+        resource: &'a str,
+    ) -> impl Iterator<Item = &'a DiscoveredMetric> + 'a {
+        self.metrics
+            .iter()
+            .filter(move |metric| metric.resource.as_deref() == Some(resource))
+    }
+
+    /// Look up `metric` describing `resource`, `None` resource for external metrics
+    ///
+    pub fn find(&self, resource: Option<&str>, metric: &str) -> Option<&DiscoveredMetric> {
+        self.metrics.iter().find(|candidate| {
+            candidate.resource.as_deref() == resource && candidate.metric == metric
+        })
+    }
+
+    /// Complete `prefix` to the advertised names, e.g. `pods/http` to `pods/http_requests`
+    ///
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        self.metrics
+            .iter()
+            .map(ToString::to_string)
+            .filter(|name| name.starts_with(prefix))
+            .collect()
+    }
+}
+
+impl From<&metav1::APIResourceList> for MetricCatalogue {
+    fn from(list: &metav1::APIResourceList) -> Self {
+        Self::new(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    /// Captured from prometheus-adapter
+    ///
+    const CUSTOM: &str = r#"{
+  "kind": "APIResourceList",
+  "apiVersion": "v1",
+  "groupVersion": "custom.metrics.k8s.io/v1beta2",
+  "resources": [
+    {
+      "name": "pods/http_requests_per_second",
+      "singularName": "",
+      "namespaced": true,
+      "kind": "MetricValueList",
+      "verbs": ["get"]
+    },
+    {
+      "name": "namespaces/http_requests_per_second",
+      "singularName": "",
+      "namespaced": false,
+      "kind": "MetricValueList",
+      "verbs": ["get"]
+    },
+    {
+      "name": "deployments.apps/http_requests_per_second",
+      "singularName": "",
+      "namespaced": true,
+      "kind": "MetricValueList",
+      "verbs": ["get"]
+    },
+    {
+      "name": "pods/fs_usage_bytes",
+      "singularName": "",
+      "namespaced": true,
+      "kind": "MetricValueList",
+      "verbs": ["get"]
+    },
+    {
+      "name": "pods/fs_usage_bytes",
+      "singularName": "",
+      "namespaced": true,
+      "kind": "MetricValueList",
+      "verbs": ["get"]
+    }
+  ]
+}"#;
+
+    /// Captured from KEDA metrics apiserver
+    ///
+    const EXTERNAL: &str = r#"{
+  "kind": "APIResourceList",
+  "apiVersion": "v1",
+  "groupVersion": "external.metrics.k8s.io/v1beta1",
+  "resources": [
+    {
+      "name": "externalmetrics",
+      "singularName": "",
+      "namespaced": true,
+      "kind": "ExternalMetricValueList",
+      "verbs": ["get"]
+    }
+  ]
+}"#;
+
+    fn catalogue(text: &str) -> MetricCatalogue {
+        let list: metav1::APIResourceList = json::from_str(text).unwrap();
+        MetricCatalogue::from(&list)
+    }
+
+    #[test]
+    fn custom() {
+        let catalogue = catalogue(CUSTOM);
+        assert_eq!(catalogue.group_version, "custom.metrics.k8s.io/v1beta2");
+        assert_eq!(catalogue.metrics.len(), 4);
+        assert_eq!(
+            catalogue.resources().into_iter().collect::<Vec<_>>(),
+            ["deployments.apps", "namespaces", "pods"]
+        );
+        let pods = catalogue
+            .metrics_for("pods")
+            .map(|metric| metric.metric.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(pods, ["fs_usage_bytes", "http_requests_per_second"]);
+
+        let metric = catalogue
+            .find(Some("namespaces"), "http_requests_per_second")
+            .unwrap();
+        assert!(!metric.namespaced);
+        assert!(catalogue.find(None, "http_requests_per_second").is_none());
+    }
+
+    #[test]
+    fn external() {
+        let catalogue = catalogue(EXTERNAL);
+        let metric = catalogue.find(None, "externalmetrics").unwrap();
+        assert!(metric.namespaced);
+        assert!(catalogue.resources().is_empty());
+        assert_eq!(metric.to_string(), "externalmetrics");
+    }
+
+    #[test]
+    fn complete() {
+        let catalogue = catalogue(CUSTOM);
+        assert_eq!(
+            catalogue.complete("pods/"),
+            ["pods/fs_usage_bytes", "pods/http_requests_per_second"]
+        );
+        assert_eq!(
+            catalogue.complete("dep"),
+            ["deployments.apps/http_requests_per_second"]
+        );
+        assert!(catalogue.complete("nodes/").is_empty());
+    }
+
+    #[test]
+    fn api_resource() {
+        let list: metav1::APIResourceList = json::from_str(CUSTOM).unwrap();
+        let resource = &list.resources[0];
+        let metric = DiscoveredMetric::new(resource);
+        assert_eq!(&metric.api_resource("MetricValueList"), resource);
+    }
+}
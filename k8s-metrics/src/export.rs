@@ -0,0 +1,10 @@
+//! Republishing metrics in formats understood by other monitoring systems
+//!
+
+use super::*;
+
+pub mod influx;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod prometheus;
+pub mod snapshot;
@@ -0,0 +1,320 @@
+//! InfluxDB line protocol encoding
+//!
+//! Container and node usage are written as `cpu_cores`, `memory_bytes` and `window_seconds`
+//! fields, custom metric values as a `value` field of a measurement named after the metric.
+//! Field types never change between points, as InfluxDB rejects conflicting types.
+//!
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::*;
+
+use custom_metrics::v1beta2::MetricValue;
+use v1beta1::{NodeMetrics, PodMetrics};
+
+/// Names of the written measurements
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Measurements {
+    /// Usage of containers, tagged by `namespace`, `pod` and `container`
+    ///
+    pub container: String,
+    /// Usage of nodes, tagged by `node`
+    ///
+    pub node: String,
+    /// Prepended to the names of custom metrics
+    ///
+    pub custom_prefix: String,
+}
+
+impl Default for Measurements {
+    fn default() -> Self {
+        Self {
+            container: "k8s_container".to_string(),
+            node: "k8s_node".to_string(),
+            custom_prefix: String::new(),
+        }
+    }
+}
+
+/// Encoder of metrics into InfluxDB line protocol
+///
+#[derive(Clone, Debug, Default)]
+pub struct InfluxEncoder {
+    measurements: Measurements,
+    tags: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+enum Field {
+    Float(f64),
+    Integer(i64),
+}
+
+impl InfluxEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn measurements(mut self, measurements: Measurements) -> Self {
+        self.measurements = measurements;
+        self
+    }
+
+    /// Tag the points with the value of object label `label` (e.g. `app.kubernetes.io/name`) as `tag`
+    ///
+    pub fn tag(mut self, label: impl ToString, tag: impl ToString) -> Self {
+        self.tags.push((label.to_string(), tag.to_string()));
+        self
+    }
+
+    /// Write a point for every container of `pods`
+    ///
+    pub fn encode_pods(&self, writer: &mut impl io::Write, pods: &[PodMetrics]) -> io::Result<()> {
+        for pod in pods {
+            for container in &pod.containers {
+                let mut tags = self.tags(&pod.metadata);
+                tags.extend(tag("namespace", &pod.metadata.namespace));
+                tags.extend(tag("pod", &pod.metadata.name));
+                tags.insert("container".to_string(), container.name.clone());
+                let fields = [
+                    ("cpu_cores", Field::Float(container.cpu().map_err(invalid)?)),
+                    (
+                        "memory_bytes",
+                        Field::Integer(container.memory().map_err(invalid)?),
+                    ),
+                    ("window_seconds", Field::Float(pod.window.as_secs_f64())),
+                ];
+                let measurement = &self.measurements.container;
+                line(writer, measurement, &tags, &fields, &pod.timestamp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a point for every one of `nodes`
+    ///
+    pub fn encode_nodes(
+        &self,
+        writer: &mut impl io::Write,
+        nodes: &[NodeMetrics],
+    ) -> io::Result<()> {
+        for node in nodes {
+            let mut tags = self.tags(&node.metadata);
+            tags.extend(tag("node", &node.metadata.name));
+            let fields = [
+                ("cpu_cores", Field::Float(node.cpu().map_err(invalid)?)),
+                (
+                    "memory_bytes",
+                    Field::Integer(node.memory().map_err(invalid)?),
+                ),
+                ("window_seconds", Field::Float(node.window.as_secs_f64())),
+            ];
+            let measurement = &self.measurements.node;
+            line(writer, measurement, &tags, &fields, &node.timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Write a point for every one of custom metric `values`
+    ///
+    /// The described object is tagged by its lowercased kind (e.g. `pod=web-0`) next to its
+    /// `namespace`, the labels matched by the metric selector become tags as is.
+    /// The value is always a float `value` field, `window_seconds` is written when known.
+    ///
+    pub fn encode_metric_values<M>(
+        &self,
+        writer: &mut impl io::Write,
+        values: &[MetricValue<M>],
+    ) -> io::Result<()> {
+        for value in values {
+            let object = &value.described_object;
+            let kind = object.kind.as_deref().unwrap_or("object").to_lowercase();
+            let mut tags = self.tags(&value.metadata);
+            let selected = value
+                .metric
+                .selector
+                .iter()
+                .flat_map(|selector| selector.match_labels.iter().flatten());
+            tags.extend(selected.map(|(label, value)| (label.clone(), value.clone())));
+            if kind != "namespace" {
+                tags.extend(tag("namespace", &object.namespace));
+            }
+            tags.extend(tag(&kind, &object.name));
+
+            let number = value
+                .value
+                .to_f64()
+                .or_else(|_| value.value.to_memory().map(|bytes| bytes as f64))
+                .map_err(invalid)?;
+            let mut fields = vec![("value", Field::Float(number))];
+            if let Some(window) = value.window_seconds {
+                fields.push(("window_seconds", Field::Float(window as f64)));
+            }
+            let measurement = format!("{}{}", self.measurements.custom_prefix, value.metric.name);
+            line(writer, &measurement, &tags, &fields, &value.timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Tags mapped from the labels of the object described by `metadata`
+    ///
+    fn tags(&self, metadata: &metav1::ObjectMeta) -> BTreeMap<String, String> {
+        let labels = metadata.labels.as_ref();
+        self.tags
+            .iter()
+            .filter_map(|(label, tag)| Some((tag.clone(), labels?.get(label)?.clone())))
+            .collect()
+    }
+}
+
+/// Write a point, tags sorted by key as recommended for performance and empty tags omitted
+///
+fn line(
+    writer: &mut impl io::Write,
+    measurement: &str,
+    tags: &BTreeMap<String, String>,
+    fields: &[(&str, Field)],
+    timestamp: &metav1::Time,
+) -> io::Result<()> {
+    write!(writer, "{}", escape(measurement, &[',', ' ']))?;
+    for (key, value) in tags.iter().filter(|(_, value)| !value.is_empty()) {
+        let key = escape(key, &[',', '=', ' ']);
+        let value = escape(value, &[',', '=', ' ']);
+        write!(writer, ",{key}={value}")?;
+    }
+    let fields = fields
+        .iter()
+        .map(|(key, field)| {
+            let key = escape(key, &[',', '=', ' ']);
+            match field {
+                Field::Float(value) => format!("{key}={value:?}"),
+                Field::Integer(value) => format!("{key}={value}i"),
+            }
+        })
+        .collect::<Vec<_>>();
+    let nanos = timestamp.0.timestamp_nanos_opt().unwrap_or_default();
+    writeln!(writer, " {} {nanos}", fields.join(","))
+}
+
+fn tag(key: &str, value: &Option<String>) -> Option<(String, String)> {
+    value.clone().map(|value| (key.to_string(), value))
+}
+
+fn invalid(err: QuantityParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Escape `special` characters with backslash, control characters are escaped as in Telegraf
+///
+fn escape(text: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\t' => escaped.push_str(r"\t"),
+            '\n' => escaped.push_str(r"\n"),
+            '\r' => escaped.push_str(r"\r"),
+            '\x0c' => escaped.push_str(r"\f"),
+            c if special.contains(&c) => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fixtures::{labels, node, pod, time, TIMESTAMP};
+
+    const NANOS: i64 = TIMESTAMP * 1_000_000_000;
+
+    fn encode(f: impl Fn(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        f(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pods() {
+        let mut pod = pod("default", "web-0", &[("nginx", "50m", "22272Ki")]);
+        pod.metadata.labels = Some(labels(&[
+            ("app.kubernetes.io/name", "web"),
+            ("team", "front end,ops"),
+        ]));
+        let encoder = InfluxEncoder::new()
+            .tag("app.kubernetes.io/name", "app")
+            .tag("team", "team")
+            .tag("missing", "missing");
+        let text = encode(|out| encoder.encode_pods(out, std::slice::from_ref(&pod)));
+        assert_eq!(
+            text,
+            format!(
+                "k8s_container,app=web,container=nginx,namespace=default,pod=web-0,team=front\\ end\\,ops \
+                 cpu_cores=0.05,memory_bytes=22806528i,window_seconds=15.0 {NANOS}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn nodes() {
+        let mut node = node("kind-control-plane", "2", "1Gi");
+        node.window = time::Duration::from_millis(12500);
+        let measurements = Measurements {
+            node: "node usage".to_string(),
+            ..default()
+        };
+        let encoder = InfluxEncoder::new().measurements(measurements);
+        let text = encode(|out| encoder.encode_nodes(out, std::slice::from_ref(&node)));
+        assert_eq!(
+            text,
+            format!(
+                "node\\ usage,node=kind-control-plane \
+                 cpu_cores=2.0,memory_bytes=1073741824i,window_seconds=12.5 {NANOS}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn metric_values() {
+        let selector = metav1::LabelSelector::parse("verb=GET").unwrap();
+        let value = MetricValue::<corev1::Pod>::builder("http_requests")
+            .object("default", "web-0")
+            .value(3)
+            .selector(selector)
+            .timestamp(time())
+            .window(time::Duration::from_secs(60))
+            .build();
+        let unnamed = MetricValue::<corev1::Pod>::builder("pods_running")
+            .object("jobs", "")
+            .value(0.5)
+            .timestamp(time())
+            .build();
+        let measurements = Measurements {
+            custom_prefix: "custom_".to_string(),
+            ..default()
+        };
+        let encoder = InfluxEncoder::new().measurements(measurements);
+        let values = [value, unnamed];
+        let text = encode(|out| encoder.encode_metric_values(out, &values));
+        assert_eq!(
+            text,
+            format!(
+                "custom_http_requests,namespace=default,pod=web-0,verb=GET value=3.0,window_seconds=60.0 {NANOS}\n\
+                 custom_pods_running,namespace=jobs value=0.5 {NANOS}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a b,c=d", &[',', ' ']), r"a\ b\,c=d");
+        assert_eq!(escape("a b,c=d", &[',', '=', ' ']), r"a\ b\,c\=d");
+        assert_eq!(escape("a\nb\tc", &[]), r"a\nb\tc");
+    }
+}
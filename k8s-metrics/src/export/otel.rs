@@ -0,0 +1,477 @@
+//! OpenTelemetry (OTLP) metrics conversion
+//!
+//! Every value becomes a gauge data point of the resource it describes, identified by
+//! semantic convention attributes like `k8s.namespace.name` and `k8s.pod.name`.
+//! Metric names follow the OpenTelemetry Collector `kubeletstats` receiver.
+//! The data point interval starts `window` before its `timestamp`.
+//!
+
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::metrics::v1::{
+    metric, number_data_point, Gauge, Metric, NumberDataPoint, ResourceMetrics, ScopeMetrics,
+};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+
+use super::*;
+
+use custom_metrics::v1beta2::MetricValue;
+use external_metrics::v1beta1::ExternalMetricValue;
+use v1beta1::{Container, NodeMetrics, PodMetrics};
+
+pub const K8S_NAMESPACE_NAME: &str = "k8s.namespace.name";
+pub const K8S_POD_NAME: &str = "k8s.pod.name";
+pub const K8S_POD_UID: &str = "k8s.pod.uid";
+pub const K8S_CONTAINER_NAME: &str = "k8s.container.name";
+pub const K8S_NODE_NAME: &str = "k8s.node.name";
+pub const K8S_NODE_UID: &str = "k8s.node.uid";
+
+const SCOPE: &str = env!("CARGO_PKG_NAME");
+const CPU_UNIT: &str = "{cpu}";
+const MEMORY_UNIT: &str = "By";
+
+/// Conversion into OpenTelemetry resource metrics
+///
+pub trait ToResourceMetrics {
+    fn to_resource_metrics(&self) -> Result<Vec<ResourceMetrics>, QuantityParseError>;
+}
+
+impl ToResourceMetrics for PodMetrics {
+    /// Pod usage (`k8s.pod.cpu.usage`, `k8s.pod.memory.working_set`) followed by the usage
+    /// of each container (`container.cpu.usage`, `container.memory.working_set`)
+    ///
+    fn to_resource_metrics(&self) -> Result<Vec<ResourceMetrics>, QuantityParseError> {
+        let mut attributes = Vec::new();
+        attributes.extend(attribute(K8S_NAMESPACE_NAME, &self.metadata.namespace));
+        attributes.extend(attribute(K8S_POD_NAME, &self.metadata.name));
+        attributes.extend(attribute(K8S_POD_UID, &self.metadata.uid));
+        let point = Point::new(&self.timestamp, Some(self.window));
+
+        let pod = resource_metrics(
+            attributes.clone(),
+            vec![
+                gauge(
+                    "k8s.pod.cpu.usage",
+                    "Total CPU usage of the pod",
+                    CPU_UNIT,
+                    point.double(self.cpu()?),
+                ),
+                gauge(
+                    "k8s.pod.memory.working_set",
+                    "Pod memory working set",
+                    MEMORY_UNIT,
+                    point.int(self.memory()?),
+                ),
+            ],
+        );
+        let containers = self
+            .containers
+            .iter()
+            .map(|container| container_metrics(container, attributes.clone(), &point));
+
+        std::iter::once(Ok(pod)).chain(containers).collect()
+    }
+}
+
+impl ToResourceMetrics for NodeMetrics {
+    /// Node usage (`k8s.node.cpu.usage`, `k8s.node.memory.working_set`)
+    ///
+    fn to_resource_metrics(&self) -> Result<Vec<ResourceMetrics>, QuantityParseError> {
+        let mut attributes = Vec::new();
+        attributes.extend(attribute(K8S_NODE_NAME, &self.metadata.name));
+        attributes.extend(attribute(K8S_NODE_UID, &self.metadata.uid));
+        let point = Point::new(&self.timestamp, Some(self.window));
+
+        let node = resource_metrics(
+            attributes,
+            vec![
+                gauge(
+                    "k8s.node.cpu.usage",
+                    "Total CPU usage of the node",
+                    CPU_UNIT,
+                    point.double(self.cpu()?),
+                ),
+                gauge(
+                    "k8s.node.memory.working_set",
+                    "Node memory working set",
+                    MEMORY_UNIT,
+                    point.int(self.memory()?),
+                ),
+            ],
+        );
+        Ok(vec![node])
+    }
+}
+
+impl<M> ToResourceMetrics for MetricValue<M> {
+    /// Gauge named after the metric, of the described object identified by
+    /// `k8s.<kind>.name` and `k8s.namespace.name`
+    ///
+    /// The labels matched by the metric selector become the data point attributes.
+    ///
+    fn to_resource_metrics(&self) -> Result<Vec<ResourceMetrics>, QuantityParseError> {
+        let object = &self.described_object;
+        let kind = object.kind.as_deref().unwrap_or_default().to_lowercase();
+        let mut attributes = Vec::new();
+        if kind != "namespace" {
+            attributes.extend(attribute(K8S_NAMESPACE_NAME, &object.namespace));
+        }
+        if !kind.is_empty() {
+            attributes.extend(attribute(&format!("k8s.{kind}.name"), &object.name));
+            attributes.extend(attribute(&format!("k8s.{kind}.uid"), &object.uid));
+        }
+
+        let window = self.window_seconds.map(seconds);
+        let mut data_point = Point::new(&self.timestamp, window).quantity(&self.value)?;
+        data_point.attributes = self
+            .metric
+            .selector
+            .iter()
+            .flat_map(|selector| selector.match_labels.iter().flatten())
+            .map(|(key, value)| key_value(key, value))
+            .collect();
+        let metric = gauge(&self.metric.name, "", "", data_point);
+
+        Ok(vec![resource_metrics(attributes, vec![metric])])
+    }
+}
+
+impl<M> ToResourceMetrics for ExternalMetricValue<M> {
+    /// Gauge named after the metric, the metric labels become the data point attributes
+    ///
+    /// External metrics describe no Kubernetes object, the resource is only identified
+    /// by `k8s.namespace.name` if the value carries the namespace it was queried in.
+    ///
+    fn to_resource_metrics(&self) -> Result<Vec<ResourceMetrics>, QuantityParseError> {
+        let attributes = attribute(K8S_NAMESPACE_NAME, &self.metadata.namespace)
+            .into_iter()
+            .collect();
+
+        let window = self.window_seconds.map(seconds);
+        let mut data_point = Point::new(&self.timestamp, window).quantity(&self.value)?;
+        data_point.attributes = self
+            .metric_labels
+            .iter()
+            .map(|(key, value)| key_value(key, value))
+            .collect();
+        let metric = gauge(&self.metric_name, "", "", data_point);
+
+        Ok(vec![resource_metrics(attributes, vec![metric])])
+    }
+}
+
+/// Interval of a data point, in nanoseconds since the epoch
+///
+#[derive(Debug)]
+struct Point {
+    start_time_unix_nano: u64,
+    time_unix_nano: u64,
+}
+
+impl Point {
+    /// Interval ending at `timestamp`, starting `window` before it or unknown (zero)
+    ///
+    fn new(timestamp: &metav1::Time, window: Option<time::Duration>) -> Self {
+        let time_unix_nano = unix_nano(timestamp.0);
+        let start_time_unix_nano = window
+            .map(|window| time_unix_nano.saturating_sub(window.as_nanos() as u64))
+            .unwrap_or_default();
+        Self {
+            start_time_unix_nano,
+            time_unix_nano,
+        }
+    }
+
+    fn double(&self, value: f64) -> NumberDataPoint {
+        self.data_point(number_data_point::Value::AsDouble(value))
+    }
+
+    fn int(&self, value: i64) -> NumberDataPoint {
+        self.data_point(number_data_point::Value::AsInt(value))
+    }
+
+    /// Data point of `quantity`, integral if it is a whole number (like memory)
+    ///
+    fn quantity(
+        &self,
+        quantity: &resource::Quantity,
+    ) -> Result<NumberDataPoint, QuantityParseError> {
+        match quantity.to_f64() {
+            Ok(value) if value.fract() == 0.0 && value.abs() < i64::MAX as f64 => {
+                Ok(self.int(value as i64))
+            }
+            Ok(value) => Ok(self.double(value)),
+            Err(err) => quantity
+                .to_memory()
+                .map(|bytes| self.int(bytes))
+                .map_err(|_| err),
+        }
+    }
+
+    fn data_point(&self, value: number_data_point::Value) -> NumberDataPoint {
+        NumberDataPoint {
+            start_time_unix_nano: self.start_time_unix_nano,
+            time_unix_nano: self.time_unix_nano,
+            value: Some(value),
+            ..default()
+        }
+    }
+}
+
+fn container_metrics(
+    container: &Container,
+    mut attributes: Vec<KeyValue>,
+    point: &Point,
+) -> Result<ResourceMetrics, QuantityParseError> {
+    attributes.push(key_value(K8S_CONTAINER_NAME, &container.name));
+    let metrics = vec![
+        gauge(
+            "container.cpu.usage",
+            "Total CPU usage of the container",
+            CPU_UNIT,
+            point.double(container.cpu()?),
+        ),
+        gauge(
+            "container.memory.working_set",
+            "Container memory working set",
+            MEMORY_UNIT,
+            point.int(container.memory()?),
+        ),
+    ];
+    Ok(resource_metrics(attributes, metrics))
+}
+
+fn resource_metrics(attributes: Vec<KeyValue>, metrics: Vec<Metric>) -> ResourceMetrics {
+    let scope = InstrumentationScope {
+        name: SCOPE.to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        ..default()
+    };
+    ResourceMetrics {
+        resource: Some(Resource {
+            attributes,
+            ..default()
+        }),
+        scope_metrics: vec![ScopeMetrics {
+            scope: Some(scope),
+            metrics,
+            ..default()
+        }],
+        ..default()
+    }
+}
+
+fn gauge(name: &str, description: &str, unit: &str, data_point: NumberDataPoint) -> Metric {
+    Metric {
+        name: name.to_string(),
+        description: description.to_string(),
+        unit: unit.to_string(),
+        data: Some(metric::Data::Gauge(Gauge {
+            data_points: vec![data_point],
+        })),
+        ..default()
+    }
+}
+
+fn attribute(key: &str, value: &Option<String>) -> Option<KeyValue> {
+    value.as_ref().map(|value| key_value(key, value))
+}
+
+fn key_value(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn seconds(seconds: i64) -> time::Duration {
+    time::Duration::from_secs(seconds.max(0) as u64)
+}
+
+fn unix_nano(time: DateTime<Utc>) -> u64 {
+    time.timestamp_nanos_opt().unwrap_or_default().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fixtures::{time, TIMESTAMP};
+
+    const TIME_UNIX_NANO: u64 = TIMESTAMP as u64 * 1_000_000_000;
+
+    fn attributes(resource_metrics: &ResourceMetrics) -> Vec<(&str, &str)> {
+        resource_metrics
+            .resource
+            .iter()
+            .flat_map(|resource| &resource.attributes)
+            .map(|attribute| (attribute.key.as_str(), string(attribute)))
+            .collect()
+    }
+
+    fn string(key_value: &KeyValue) -> &str {
+        match key_value
+            .value
+            .as_ref()
+            .and_then(|value| value.value.as_ref())
+        {
+            Some(any_value::Value::StringValue(value)) => value,
+            other => panic!("unexpected value {other:?}"),
+        }
+    }
+
+    fn gauges(resource_metrics: &ResourceMetrics) -> Vec<(&str, &str, &NumberDataPoint)> {
+        resource_metrics.scope_metrics[0]
+            .metrics
+            .iter()
+            .map(|metric| match &metric.data {
+                Some(metric::Data::Gauge(gauge)) => (
+                    metric.name.as_str(),
+                    metric.unit.as_str(),
+                    &gauge.data_points[0],
+                ),
+                other => panic!("unexpected data {other:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pod() {
+        let pod = fixtures::pod(
+            "default",
+            "web-0",
+            &[("nginx", "50m", "22272Ki"), ("envoy", "12500u", "10Mi")],
+        );
+        let resource_metrics = pod.to_resource_metrics().unwrap();
+        assert_eq!(resource_metrics.len(), 3);
+
+        assert_eq!(
+            attributes(&resource_metrics[0]),
+            [(K8S_NAMESPACE_NAME, "default"), (K8S_POD_NAME, "web-0")]
+        );
+        let scope = resource_metrics[0].scope_metrics[0].scope.as_ref().unwrap();
+        assert_eq!(scope.name, "k8s-metrics");
+        let points = gauges(&resource_metrics[0]);
+        let (name, unit, cpu) = points[0];
+        assert_eq!((name, unit), ("k8s.pod.cpu.usage", "{cpu}"));
+        assert_eq!(cpu.value, Some(number_data_point::Value::AsDouble(0.0625)));
+        assert_eq!(cpu.time_unix_nano, TIME_UNIX_NANO);
+        assert_eq!(cpu.start_time_unix_nano, TIME_UNIX_NANO - 15_000_000_000);
+        let (name, unit, memory) = points[1];
+        assert_eq!((name, unit), ("k8s.pod.memory.working_set", "By"));
+        assert_eq!(
+            memory.value,
+            Some(number_data_point::Value::AsInt(33292288))
+        );
+
+        assert_eq!(
+            attributes(&resource_metrics[2]),
+            [
+                (K8S_NAMESPACE_NAME, "default"),
+                (K8S_POD_NAME, "web-0"),
+                (K8S_CONTAINER_NAME, "envoy")
+            ]
+        );
+        let points = gauges(&resource_metrics[2]);
+        assert_eq!(points[0].0, "container.cpu.usage");
+        assert_eq!(
+            points[0].2.value,
+            Some(number_data_point::Value::AsDouble(0.0125))
+        );
+        assert_eq!(points[1].0, "container.memory.working_set");
+        assert_eq!(
+            points[1].2.value,
+            Some(number_data_point::Value::AsInt(10485760))
+        );
+    }
+
+    #[test]
+    fn node() {
+        let mut node = fixtures::node("kind-control-plane", "200m", "1Gi");
+        node.window = time::Duration::from_secs(10);
+        let resource_metrics = node.to_resource_metrics().unwrap();
+        assert_eq!(resource_metrics.len(), 1);
+        assert_eq!(
+            attributes(&resource_metrics[0]),
+            [(K8S_NODE_NAME, "kind-control-plane")]
+        );
+        let points = gauges(&resource_metrics[0]);
+        assert_eq!(points[0].0, "k8s.node.cpu.usage");
+        assert_eq!(
+            points[0].2.value,
+            Some(number_data_point::Value::AsDouble(0.2))
+        );
+        assert_eq!(
+            points[0].2.start_time_unix_nano,
+            TIME_UNIX_NANO - 10_000_000_000
+        );
+        assert_eq!(points[1].0, "k8s.node.memory.working_set");
+        assert_eq!(
+            points[1].2.value,
+            Some(number_data_point::Value::AsInt(1 << 30))
+        );
+    }
+
+    #[test]
+    fn metric_value() {
+        let selector = metav1::LabelSelector::parse("verb=GET").unwrap();
+        let value = MetricValue::<corev1::Pod>::builder("http_requests")
+            .object("default", "web-0")
+            .value(2.5)
+            .selector(selector)
+            .timestamp(time())
+            .window(time::Duration::from_secs(60))
+            .build();
+        let resource_metrics = value.to_resource_metrics().unwrap();
+        assert_eq!(
+            attributes(&resource_metrics[0]),
+            [(K8S_NAMESPACE_NAME, "default"), (K8S_POD_NAME, "web-0")]
+        );
+        let points = gauges(&resource_metrics[0]);
+        let (name, _, point) = points[0];
+        assert_eq!(name, "http_requests");
+        assert_eq!(point.value, Some(number_data_point::Value::AsDouble(2.5)));
+        assert_eq!(point.start_time_unix_nano, TIME_UNIX_NANO - 60_000_000_000);
+        assert_eq!(point.attributes.len(), 1);
+        assert_eq!(point.attributes[0].key, "verb");
+        assert_eq!(string(&point.attributes[0]), "GET");
+
+        let namespace = MetricValue::<corev1::Namespace>::builder("pods_running")
+            .object("", "jobs")
+            .value(3)
+            .timestamp(time())
+            .build();
+        let resource_metrics = namespace.to_resource_metrics().unwrap();
+        assert_eq!(
+            attributes(&resource_metrics[0]),
+            [(K8S_NAMESPACE_NAME, "jobs")]
+        );
+        let points = gauges(&resource_metrics[0]);
+        assert_eq!(points[0].2.value, Some(number_data_point::Value::AsInt(3)));
+        assert_eq!(points[0].2.start_time_unix_nano, 0);
+    }
+
+    #[test]
+    fn external_metric_value() {
+        let value = ExternalMetricValue::<()>::with_labels(
+            "queue_depth",
+            [("queue", "jobs")],
+            resource::Quantity("1500".to_string()),
+        );
+        let resource_metrics = value.to_resource_metrics().unwrap();
+        assert_eq!(attributes(&resource_metrics[0]), []);
+        let points = gauges(&resource_metrics[0]);
+        let (name, _, point) = points[0];
+        assert_eq!(name, "queue_depth");
+        assert_eq!(point.value, Some(number_data_point::Value::AsInt(1500)));
+        assert_eq!(point.attributes[0].key, "queue");
+        assert_eq!(string(&point.attributes[0]), "jobs");
+    }
+
+    #[test]
+    fn invalid_quantity() {
+        let node = fixtures::node("kind-control-plane", "lots", "1Gi");
+        assert!(node.to_resource_metrics().is_err());
+    }
+}
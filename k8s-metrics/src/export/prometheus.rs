@@ -0,0 +1,535 @@
+//! Prometheus text exposition and OpenMetrics encoding
+//!
+//! Pod and node usage become gauges like `k8s_pod_cpu_cores{namespace,pod,container}`,
+//! custom metric values become gauges named after the metric, labelled with the described object.
+//!
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::*;
+
+use custom_metrics::v1beta2::MetricValue;
+use v1beta1::{NodeMetrics, PodMetrics};
+
+/// Content type of the Prometheus text exposition format
+///
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Content type of the OpenMetrics text format
+///
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Prometheus text exposition format 0.0.4, timestamps in milliseconds
+    ///
+    #[default]
+    Text,
+    /// OpenMetrics 1.0, timestamps in seconds, terminated by `# EOF`
+    ///
+    OpenMetrics,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Text => TEXT_CONTENT_TYPE,
+            Self::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+}
+
+/// Names of the exported metric families
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetricNames {
+    pub pod_cpu: String,
+    pub pod_memory: String,
+    pub node_cpu: String,
+    pub node_memory: String,
+    /// Prepended to the names of custom metrics
+    ///
+    pub custom_prefix: String,
+}
+
+impl Default for MetricNames {
+    fn default() -> Self {
+        Self {
+            pod_cpu: "k8s_pod_cpu_cores".to_string(),
+            pod_memory: "k8s_pod_memory_bytes".to_string(),
+            node_cpu: "k8s_node_cpu_cores".to_string(),
+            node_memory: "k8s_node_memory_bytes".to_string(),
+            custom_prefix: String::new(),
+        }
+    }
+}
+
+/// Encoder of metrics into Prometheus text or OpenMetrics
+///
+/// Each `encode_*()` call writes complete metric families, so every kind of metrics
+/// should be encoded once per exposition. OpenMetrics expositions are terminated with `finish()`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct PrometheusEncoder {
+    format: Format,
+    names: MetricNames,
+    labels: Vec<(String, String)>,
+    without_timestamps: bool,
+}
+
+#[derive(Debug)]
+struct Sample {
+    labels: Vec<(String, String)>,
+    value: f64,
+    timestamp: DateTime<Utc>,
+}
+
+impl PrometheusEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn names(mut self, names: MetricNames) -> Self {
+        self.names = names;
+        self
+    }
+
+    /// Export the value of object label `label` (e.g. `app.kubernetes.io/name`) as label `name`
+    ///
+    pub fn label(mut self, label: impl ToString, name: impl ToString) -> Self {
+        self.labels
+            .push((label.to_string(), sanitize_label(&name.to_string())));
+        self
+    }
+
+    /// Leave the samples without timestamps, letting the scraper stamp them
+    ///
+    pub fn without_timestamps(mut self) -> Self {
+        self.without_timestamps = true;
+        self
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        self.format.content_type()
+    }
+
+    /// Write CPU and memory usage of every container of `pods`
+    ///
+    pub fn encode_pods(&self, writer: &mut impl io::Write, pods: &[PodMetrics]) -> io::Result<()> {
+        let mut cpu = Vec::new();
+        let mut memory = Vec::new();
+        for pod in pods {
+            let timestamp = pod.timestamp.0;
+            for container in &pod.containers {
+                let labels = vec![
+                    label("namespace", &pod.metadata.namespace),
+                    label("pod", &pod.metadata.name),
+                    ("container".to_string(), container.name.clone()),
+                ];
+                let labels = self.labels(labels, &pod.metadata);
+                cpu.push(Sample {
+                    labels: labels.clone(),
+                    value: container.cpu().map_err(invalid)?,
+                    timestamp,
+                });
+                memory.push(Sample {
+                    labels,
+                    value: container.memory().map_err(invalid)? as f64,
+                    timestamp,
+                });
+            }
+        }
+
+        let names = &self.names;
+        self.family(
+            writer,
+            &names.pod_cpu,
+            "CPU usage of the container in cores",
+            &cpu,
+        )?;
+        self.family(
+            writer,
+            &names.pod_memory,
+            "Working set of the container in bytes",
+            &memory,
+        )
+    }
+
+    /// Write CPU and memory usage of `nodes`
+    ///
+    pub fn encode_nodes(
+        &self,
+        writer: &mut impl io::Write,
+        nodes: &[NodeMetrics],
+    ) -> io::Result<()> {
+        let mut cpu = Vec::new();
+        let mut memory = Vec::new();
+        for node in nodes {
+            let timestamp = node.timestamp.0;
+            let labels = vec![label("node", &node.metadata.name)];
+            let labels = self.labels(labels, &node.metadata);
+            cpu.push(Sample {
+                labels: labels.clone(),
+                value: node.cpu().map_err(invalid)?,
+                timestamp,
+            });
+            memory.push(Sample {
+                labels,
+                value: node.memory().map_err(invalid)? as f64,
+                timestamp,
+            });
+        }
+
+        let names = &self.names;
+        self.family(
+            writer,
+            &names.node_cpu,
+            "CPU usage of the node in cores",
+            &cpu,
+        )?;
+        self.family(
+            writer,
+            &names.node_memory,
+            "Working set of the node in bytes",
+            &memory,
+        )
+    }
+
+    /// Write custom metric `values`, one family per metric name
+    ///
+    /// The described object is labelled by its lowercased kind (e.g. `pod="web-0"`)
+    /// next to its `namespace`, the labels matched by the metric selector are added as is.
+    ///
+    pub fn encode_metric_values<M>(
+        &self,
+        writer: &mut impl io::Write,
+        values: &[MetricValue<M>],
+    ) -> io::Result<()> {
+        let mut families = BTreeMap::<_, Vec<_>>::new();
+        for value in values {
+            let object = &value.described_object;
+            let kind = object.kind.as_deref().unwrap_or("object").to_lowercase();
+            let name = object.name.clone().unwrap_or_default();
+            let mut labels = Vec::new();
+            if let Some(namespace) = object.namespace.as_ref().filter(|_| kind != "namespace") {
+                labels.push(("namespace".to_string(), namespace.clone()));
+            }
+            labels.push((sanitize_label(&kind), name));
+            let selected = value
+                .metric
+                .selector
+                .iter()
+                .flat_map(|selector| selector.match_labels.iter().flatten());
+            for (label, value) in selected {
+                labels.push((sanitize_label(label), value.clone()));
+            }
+            let labels = self.labels(labels, &value.metadata);
+            let number = value
+                .value
+                .to_f64()
+                .or_else(|_| value.value.to_memory().map(|bytes| bytes as f64))
+                .map_err(invalid)?;
+
+            let family = format!("{}{}", self.names.custom_prefix, value.metric.name);
+            families
+                .entry(sanitize_name(&family))
+                .or_default()
+                .push(Sample {
+                    labels,
+                    value: number,
+                    timestamp: value.timestamp.0,
+                });
+        }
+
+        for (name, samples) in families {
+            let help = format!("Custom metric {name}");
+            self.family(writer, &name, &help, &samples)?;
+        }
+        Ok(())
+    }
+
+    /// Terminate the exposition, as required by OpenMetrics
+    ///
+    pub fn finish(&self, writer: &mut impl io::Write) -> io::Result<()> {
+        match self.format {
+            Format::Text => Ok(()),
+            Format::OpenMetrics => writeln!(writer, "# EOF"),
+        }
+    }
+
+    /// `labels` followed by the mapped labels of the object described by `metadata`
+    ///
+    fn labels(
+        &self,
+        mut labels: Vec<(String, String)>,
+        metadata: &metav1::ObjectMeta,
+    ) -> Vec<(String, String)> {
+        let object_labels = metadata.labels.iter().flatten();
+        for (label, name) in &self.labels {
+            let value = object_labels
+                .clone()
+                .find_map(|(key, value)| (key == label).then_some(value));
+            if let Some(value) = value {
+                labels.push((name.clone(), value.clone()));
+            }
+        }
+        labels
+    }
+
+    fn family(
+        &self,
+        writer: &mut impl io::Write,
+        name: &str,
+        help: &str,
+        samples: &[Sample],
+    ) -> io::Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let quote = self.format == Format::OpenMetrics;
+        writeln!(writer, "# HELP {name} {}", escape(help, quote))?;
+        writeln!(writer, "# TYPE {name} gauge")?;
+        for sample in samples {
+            write!(writer, "{name}")?;
+            if !sample.labels.is_empty() {
+                let labels = sample
+                    .labels
+                    .iter()
+                    .map(|(label, value)| format!("{label}=\"{}\"", escape(value, true)))
+                    .collect::<Vec<_>>();
+                write!(writer, "{{{}}}", labels.join(","))?;
+            }
+            write!(writer, " {}", number(sample.value))?;
+            if !self.without_timestamps {
+                match self.format {
+                    Format::Text => write!(writer, " {}", sample.timestamp.timestamp_millis())?,
+                    Format::OpenMetrics => write!(
+                        writer,
+                        " {}",
+                        number(sample.timestamp.timestamp_millis() as f64 / 1e3)
+                    )?,
+                }
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+fn label(name: &str, value: &Option<String>) -> (String, String) {
+    (name.to_string(), value.clone().unwrap_or_default())
+}
+
+fn invalid(err: QuantityParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escape backslash and newline, and double quote if `quote`d
+/// as in label values (and OpenMetrics help)
+///
+fn escape(text: &str, quote: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str(r"\\"),
+            '\n' => escaped.push_str(r"\n"),
+            '"' if quote => escaped.push_str(r#"\""#),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Metric name with invalid characters replaced by `_`
+///
+fn sanitize_name(name: &str) -> String {
+    sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// Label name with invalid characters replaced by `_`
+///
+fn sanitize_label(name: &str) -> String {
+    sanitize(name, |c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn sanitize(name: &str, valid: impl Fn(char) -> bool) -> String {
+    let mut sanitized = name
+        .chars()
+        .map(|c| if valid(c) { c } else { '_' })
+        .collect::<String>();
+    if sanitized.is_empty() || sanitized.starts_with(|c: char| c.is_ascii_digit()) {
+        sanitized.insert(0, '_');
+    }
+    sanitized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::labels;
+    use k8s::chrono::TimeDelta;
+
+    /// Fixture time plus `millis`, to exercise sub-second timestamps
+    ///
+    fn time(millis: i64) -> metav1::Time {
+        metav1::Time(fixtures::time().0 + TimeDelta::milliseconds(millis))
+    }
+
+    fn pod() -> PodMetrics {
+        let mut pod = fixtures::pod(
+            "default",
+            "web-0",
+            &[("nginx", "50m", "22272Ki"), ("envoy", "12500u", "10Mi")],
+        );
+        pod.metadata.labels = Some(labels(&[("app.kubernetes.io/name", "web")]));
+        pod.timestamp = time(400);
+        pod
+    }
+
+    fn node() -> NodeMetrics {
+        NodeMetrics {
+            timestamp: time(213),
+            ..fixtures::node("kind-control-plane", "200m", "1Gi")
+        }
+    }
+
+    fn encode(encoder: &PrometheusEncoder, f: impl Fn(&mut Vec<u8>) -> io::Result<()>) -> String {
+        let mut out = Vec::new();
+        f(&mut out).unwrap();
+        encoder.finish(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pods() {
+        let encoder = PrometheusEncoder::new().label("app.kubernetes.io/name", "app");
+        let text = encode(&encoder, |out| encoder.encode_pods(out, &[pod()]));
+        assert_eq!(
+            text,
+            r#"# HELP k8s_pod_cpu_cores CPU usage of the container in cores
+# TYPE k8s_pod_cpu_cores gauge
+k8s_pod_cpu_cores{namespace="default",pod="web-0",container="nginx",app="web"} 0.05 1710234975400
+k8s_pod_cpu_cores{namespace="default",pod="web-0",container="envoy",app="web"} 0.0125 1710234975400
+# HELP k8s_pod_memory_bytes Working set of the container in bytes
+# TYPE k8s_pod_memory_bytes gauge
+k8s_pod_memory_bytes{namespace="default",pod="web-0",container="nginx",app="web"} 22806528 1710234975400
+k8s_pod_memory_bytes{namespace="default",pod="web-0",container="envoy",app="web"} 10485760 1710234975400
+"#
+        );
+    }
+
+    #[test]
+    fn nodes_openmetrics() {
+        let names = MetricNames {
+            node_cpu: "node_cpu".to_string(),
+            ..default()
+        };
+        let encoder = PrometheusEncoder::new()
+            .format(Format::OpenMetrics)
+            .names(names);
+        assert_eq!(encoder.content_type(), OPENMETRICS_CONTENT_TYPE);
+        let text = encode(&encoder, |out| encoder.encode_nodes(out, &[node()]));
+        assert_eq!(
+            text,
+            r#"# HELP node_cpu CPU usage of the node in cores
+# TYPE node_cpu gauge
+node_cpu{node="kind-control-plane"} 0.2 1710234975.213
+# HELP k8s_node_memory_bytes Working set of the node in bytes
+# TYPE k8s_node_memory_bytes gauge
+k8s_node_memory_bytes{node="kind-control-plane"} 1073741824 1710234975.213
+# EOF
+"#
+        );
+    }
+
+    #[test]
+    fn metric_values() {
+        let selector = metav1::LabelSelector::parse("verb=GET").unwrap();
+        let value = |pod: &str, value: f64| {
+            MetricValue::<corev1::Pod>::builder("http_requests")
+                .object("default", pod)
+                .value(value)
+                .selector(selector.clone())
+                .timestamp(fixtures::time())
+                .build()
+        };
+        let names = MetricNames {
+            custom_prefix: "custom_".to_string(),
+            ..default()
+        };
+        let encoder = PrometheusEncoder::new().names(names).without_timestamps();
+        let text = encode(&encoder, |out| {
+            encoder.encode_metric_values(out, &[value("web-0", 2.5), value("web-1", 3.0)])
+        });
+        assert_eq!(
+            text,
+            r#"# HELP custom_http_requests Custom metric custom_http_requests
+# TYPE custom_http_requests gauge
+custom_http_requests{namespace="default",pod="web-0",verb="GET"} 2.5
+custom_http_requests{namespace="default",pod="web-1",verb="GET"} 3
+"#
+        );
+
+        let namespace = MetricValue::<corev1::Namespace>::builder("queue.depth")
+            .object("", "jobs")
+            .value(1500)
+            .timestamp(fixtures::time())
+            .build();
+        let encoder = PrometheusEncoder::new();
+        let text = encode(&encoder, |out| {
+            encoder.encode_metric_values(out, std::slice::from_ref(&namespace))
+        });
+        assert!(
+            text.ends_with("queue_depth{namespace=\"jobs\"} 1500 1710234975000\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        let encoder = PrometheusEncoder::new();
+        let text = encode(&encoder, |out| {
+            encoder.encode_pods(out, &[])?;
+            encoder.encode_nodes(out, &[])
+        });
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn escaping() {
+        assert_eq!(escape("a\\b\n\"c\"", true), r#"a\\b\n\"c\""#);
+        assert_eq!(escape("a\\b\n\"c\"", false), r#"a\\b\n"c""#);
+        assert_eq!(
+            sanitize_name("http.requests-per:second"),
+            "http_requests_per:second"
+        );
+        assert_eq!(
+            sanitize_label("app.kubernetes.io/name"),
+            "app_kubernetes_io_name"
+        );
+        assert_eq!(sanitize_label("1st"), "_1st");
+        assert_eq!(number(f64::INFINITY), "+Inf");
+        assert_eq!(number(f64::NAN), "NaN");
+        assert_eq!(number(3.0), "3");
+    }
+}
@@ -0,0 +1,626 @@
+//! Flat CSV and JSON Lines snapshots of pod and node metrics
+//!
+//! Each container of a pod and each node becomes a row of the configured columns,
+//! CPU usage in cores and memory usage in bytes. Snapshots can be read back for replay.
+//!
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use k8s::chrono::SecondsFormat;
+use k8s::serde_json as json;
+
+use super::*;
+
+use quantity::binary;
+use v1beta1::{Container, NodeMetrics, PodMetrics, Usage};
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Json(#[from] json::Error),
+
+    #[error(transparent)]
+    Quantity(#[from] QuantityParseError),
+
+    /// The snapshot is malformed, e.g. a row identifies neither a container nor a node
+    ///
+    #[error("Invalid snapshot at row {row}: {message}")]
+    Invalid { row: usize, message: String },
+}
+
+impl SnapshotError {
+    fn invalid(row: usize, message: impl ToString) -> Self {
+        let message = message.to_string();
+        Self::Invalid { row, message }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// Comma separated values with a header row
+    ///
+    #[default]
+    Csv,
+    /// A JSON object per line, keyed by column names
+    ///
+    JsonLines,
+}
+
+/// Column of a snapshot row
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Column {
+    Namespace,
+    Pod,
+    Container,
+    /// Node name, set for node rows only
+    ///
+    Node,
+    /// RFC 3339 timestamp of the metrics
+    ///
+    Timestamp,
+    /// Window of the metrics in seconds
+    ///
+    Window,
+    /// CPU usage in cores
+    ///
+    Cpu,
+    /// Memory usage in bytes
+    ///
+    Memory,
+    /// Value of the object label, named `label:<key>`
+    ///
+    Label(String),
+}
+
+impl Column {
+    /// Columns written unless configured otherwise
+    ///
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::Namespace,
+            Self::Pod,
+            Self::Container,
+            Self::Node,
+            Self::Timestamp,
+            Self::Window,
+            Self::Cpu,
+            Self::Memory,
+        ]
+    }
+
+    /// Column named `name`, `None` if unknown
+    ///
+    pub fn parse(name: &str) -> Option<Self> {
+        let column = match name {
+            "namespace" => Self::Namespace,
+            "pod" => Self::Pod,
+            "container" => Self::Container,
+            "node" => Self::Node,
+            "timestamp" => Self::Timestamp,
+            "window" => Self::Window,
+            "cpu" => Self::Cpu,
+            "memory" => Self::Memory,
+            name => Self::Label(name.strip_prefix("label:")?.to_string()),
+        };
+        Some(column)
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Namespace => f.write_str("namespace"),
+            Self::Pod => f.write_str("pod"),
+            Self::Container => f.write_str("container"),
+            Self::Node => f.write_str("node"),
+            Self::Timestamp => f.write_str("timestamp"),
+            Self::Window => f.write_str("window"),
+            Self::Cpu => f.write_str("cpu"),
+            Self::Memory => f.write_str("memory"),
+            Self::Label(key) => write!(f, "label:{key}"),
+        }
+    }
+}
+
+/// Writer of pod and node metrics snapshots
+///
+#[derive(Clone, Debug)]
+pub struct SnapshotWriter {
+    format: SnapshotFormat,
+    columns: Vec<Column>,
+}
+
+/// Pod and node metrics read from a snapshot
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub pods: Vec<PodMetrics>,
+    pub nodes: Vec<NodeMetrics>,
+}
+
+/// Row of a container or a node
+///
+#[derive(Debug, Default)]
+struct Row {
+    namespace: Option<String>,
+    pod: Option<String>,
+    container: Option<String>,
+    node: Option<String>,
+    timestamp: Option<DateTime<Utc>>,
+    window: Option<time::Duration>,
+    cpu: Option<f64>,
+    memory: Option<i64>,
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+enum Cell {
+    Text(String),
+    Float(f64),
+    Integer(i64),
+}
+
+impl SnapshotWriter {
+    pub fn new(format: SnapshotFormat) -> Self {
+        let columns = Column::defaults();
+        Self { format, columns }
+    }
+
+    /// Write exactly `columns`, in this order
+    ///
+    pub fn columns(mut self, columns: impl IntoIterator<Item = Column>) -> Self {
+        self.columns = columns.into_iter().collect();
+        self
+    }
+
+    /// Expand object labels `keys` into `label:<key>` columns
+    ///
+    pub fn labels<K: ToString>(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        let labels = keys.into_iter().map(|key| Column::Label(key.to_string()));
+        self.columns.extend(labels);
+        self
+    }
+
+    /// Write a row for every container of `pods` and every one of `nodes`
+    ///
+    pub fn write(
+        &self,
+        writer: &mut impl io::Write,
+        pods: &[PodMetrics],
+        nodes: &[NodeMetrics],
+    ) -> Result<(), SnapshotError> {
+        let containers = pods.iter().flat_map(|pod| {
+            pod.containers
+                .iter()
+                .map(move |container| Row::container(pod, container))
+        });
+        let nodes = nodes.iter().map(Row::node);
+        let rows = containers.chain(nodes);
+
+        match self.format {
+            SnapshotFormat::Csv => {
+                let header = self.columns.iter().map(ToString::to_string);
+                csv_record(writer, header)?;
+                for row in rows {
+                    let row = row?;
+                    let cells = self.columns.iter().map(|column| {
+                        row.cell(column)
+                            .map(|cell| cell.to_string())
+                            .unwrap_or_default()
+                    });
+                    csv_record(writer, cells)?;
+                }
+            }
+            SnapshotFormat::JsonLines => {
+                for row in rows {
+                    let row = row?;
+                    let object = self
+                        .columns
+                        .iter()
+                        .filter_map(|column| Some((column.to_string(), row.cell(column)?.into())))
+                        .collect::<json::Map<_, _>>();
+                    writer.write_all(&json::to_vec(&object)?)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    /// Read a snapshot written in `format`, grouping container rows into pods
+    ///
+    /// Columns missing from the snapshot take their default (zero) values, except that each
+    /// row must identify either a container by `pod` and `container` or a `node`.
+    ///
+    pub fn read(format: SnapshotFormat, mut reader: impl io::Read) -> Result<Self, SnapshotError> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+
+        let rows = match format {
+            SnapshotFormat::Csv => csv_rows(&text)?,
+            SnapshotFormat::JsonLines => json_rows(&text)?,
+        };
+        Self::from_rows(rows)
+    }
+
+    fn from_rows(rows: Vec<Row>) -> Result<Self, SnapshotError> {
+        let mut snapshot = Self::default();
+        let mut pods = BTreeMap::new();
+
+        for (index, row) in rows.into_iter().enumerate() {
+            let timestamp = metav1::Time(row.timestamp.unwrap_or_default());
+            let window = row.window.unwrap_or_default();
+            let usage = Usage {
+                cpu: row.cpu.unwrap_or_default().to_quantity(),
+                memory: binary(i128::from(row.memory.unwrap_or_default())),
+            };
+            let labels = (!row.labels.is_empty()).then_some(row.labels);
+
+            match (row.pod, row.container, row.node) {
+                (Some(pod), Some(container), _) => {
+                    let key = (
+                        row.namespace.clone(),
+                        pod.clone(),
+                        timestamp.clone(),
+                        window,
+                    );
+                    let index = *pods.entry(key).or_insert_with(|| {
+                        snapshot.pods.push(PodMetrics {
+                            metadata: metav1::ObjectMeta {
+                                name: Some(pod),
+                                namespace: row.namespace,
+                                ..default()
+                            },
+                            timestamp,
+                            window,
+                            ..default()
+                        });
+                        snapshot.pods.len() - 1
+                    });
+                    let pod = &mut snapshot.pods[index];
+                    if let Some(labels) = labels {
+                        pod.metadata.labels.get_or_insert_default().extend(labels);
+                    }
+                    let name = container;
+                    pod.containers.push(Container { name, usage });
+                }
+                (_, _, Some(node)) => snapshot.nodes.push(NodeMetrics {
+                    metadata: metav1::ObjectMeta {
+                        name: Some(node),
+                        labels,
+                        ..default()
+                    },
+                    timestamp,
+                    window,
+                    usage,
+                }),
+                _ => {
+                    let message = "row identifies neither a container nor a node";
+                    return Err(SnapshotError::invalid(index + 1, message));
+                }
+            }
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl Row {
+    fn container(pod: &PodMetrics, container: &Container) -> Result<Self, QuantityParseError> {
+        Ok(Self {
+            namespace: pod.metadata.namespace.clone(),
+            pod: pod.metadata.name.clone(),
+            container: Some(container.name.clone()),
+            node: None,
+            timestamp: Some(pod.timestamp.0),
+            window: Some(pod.window),
+            cpu: Some(container.cpu()?),
+            memory: Some(container.memory()?),
+            labels: pod.metadata.labels.clone().unwrap_or_default(),
+        })
+    }
+
+    fn node(node: &NodeMetrics) -> Result<Self, QuantityParseError> {
+        Ok(Self {
+            node: node.metadata.name.clone(),
+            timestamp: Some(node.timestamp.0),
+            window: Some(node.window),
+            cpu: Some(node.cpu()?),
+            memory: Some(node.memory()?),
+            labels: node.metadata.labels.clone().unwrap_or_default(),
+            ..default()
+        })
+    }
+
+    fn cell(&self, column: &Column) -> Option<Cell> {
+        let text = |text: &Option<String>| text.clone().map(Cell::Text);
+        match column {
+            Column::Namespace => text(&self.namespace),
+            Column::Pod => text(&self.pod),
+            Column::Container => text(&self.container),
+            Column::Node => text(&self.node),
+            Column::Timestamp => self.timestamp.map(|timestamp| {
+                Cell::Text(timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            }),
+            Column::Window => self.window.map(|window| Cell::Float(window.as_secs_f64())),
+            Column::Cpu => self.cpu.map(Cell::Float),
+            Column::Memory => self.memory.map(Cell::Integer),
+            Column::Label(key) => self.labels.get(key).cloned().map(Cell::Text),
+        }
+    }
+
+    /// Set `column` parsed from `text`, empty text leaves the column unset
+    ///
+    fn set(&mut self, column: &Column, text: String) -> Result<(), String> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        let invalid = |err: &dyn fmt::Display| format!("{column}: {err}");
+        match column {
+            Column::Namespace => self.namespace = Some(text),
+            Column::Pod => self.pod = Some(text),
+            Column::Container => self.container = Some(text),
+            Column::Node => self.node = Some(text),
+            Column::Timestamp => {
+                let timestamp = DateTime::parse_from_rfc3339(&text).map_err(|err| invalid(&err))?;
+                self.timestamp = Some(timestamp.to_utc());
+            }
+            Column::Window => {
+                let seconds = text.parse::<f64>().map_err(|err| invalid(&err))?;
+                let window =
+                    time::Duration::try_from_secs_f64(seconds).map_err(|err| invalid(&err))?;
+                self.window = Some(window);
+            }
+            Column::Cpu => self.cpu = Some(text.parse().map_err(|err| invalid(&err))?),
+            Column::Memory => self.memory = Some(text.parse().map_err(|err| invalid(&err))?),
+            Column::Label(key) => {
+                self.labels.insert(key.clone(), text);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Float(value) => value.fmt(f),
+            Self::Integer(value) => value.fmt(f),
+        }
+    }
+}
+
+impl From<Cell> for json::Value {
+    fn from(cell: Cell) -> Self {
+        match cell {
+            Cell::Text(text) => Self::String(text),
+            Cell::Float(value) => value.into(),
+            Cell::Integer(value) => value.into(),
+        }
+    }
+}
+
+fn csv_record(writer: &mut impl io::Write, fields: impl Iterator<Item = String>) -> io::Result<()> {
+    let fields = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>();
+    write!(writer, "{}\r\n", fields.join(","))
+}
+
+/// Records of RFC 4180 `text`, fields may be quoted and span lines
+///
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, SnapshotError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => {
+                        let message = "unterminated quoted field";
+                        return Err(SnapshotError::invalid(records.len(), message));
+                    }
+                }
+            },
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+fn csv_rows(text: &str) -> Result<Vec<Row>, SnapshotError> {
+    let mut records = csv_records(text)?.into_iter();
+    let header = records.next().unwrap_or_default();
+    let columns = header
+        .iter()
+        .map(|name| Column::parse(name))
+        .collect::<Vec<_>>();
+
+    records
+        .enumerate()
+        .map(|(index, record)| {
+            let mut row = Row::default();
+            for (column, field) in columns.iter().zip(record) {
+                if let Some(column) = column {
+                    row.set(column, field)
+                        .map_err(|message| SnapshotError::invalid(index + 1, message))?;
+                }
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
+fn json_rows(text: &str) -> Result<Vec<Row>, SnapshotError> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            let object = json::from_str::<json::Map<String, json::Value>>(line)?;
+            let mut row = Row::default();
+            for (name, value) in object {
+                let Some(column) = Column::parse(&name) else {
+                    continue;
+                };
+                let text = match value {
+                    json::Value::Null => continue,
+                    json::Value::String(text) => text,
+                    value => value.to_string(),
+                };
+                row.set(&column, text)
+                    .map_err(|message| SnapshotError::invalid(index + 1, message))?;
+            }
+            Ok(row)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use fixtures::{labels, node, pod};
+
+    fn pods() -> Vec<PodMetrics> {
+        let mut pod = pod(
+            "default",
+            "web-0",
+            &[("nginx", "50m", "22272Ki"), ("envoy", "12500u", "10Mi")],
+        );
+        pod.metadata.labels = Some(labels(&[("app", "web"), ("tier", "front, end")]));
+        pod.window = time::Duration::from_millis(12500);
+        vec![pod]
+    }
+
+    fn nodes() -> Vec<NodeMetrics> {
+        vec![node("kind-control-plane", "200m", "1Gi")]
+    }
+
+    fn write(writer: &SnapshotWriter) -> String {
+        let mut out = Vec::new();
+        writer.write(&mut out, &pods(), &nodes()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv() {
+        let writer = SnapshotWriter::new(SnapshotFormat::Csv).labels(["app", "tier"]);
+        let text = write(&writer);
+        assert_eq!(
+            text,
+            "namespace,pod,container,node,timestamp,window,cpu,memory,label:app,label:tier\r\n\
+             default,web-0,nginx,,2024-03-12T09:16:15Z,12.5,0.05,22806528,web,\"front, end\"\r\n\
+             default,web-0,envoy,,2024-03-12T09:16:15Z,12.5,0.0125,10485760,web,\"front, end\"\r\n\
+             ,,,kind-control-plane,2024-03-12T09:16:15Z,15,0.2,1073741824,,\r\n"
+        );
+    }
+
+    #[test]
+    fn json_lines() {
+        let writer = SnapshotWriter::new(SnapshotFormat::JsonLines)
+            .columns([Column::Pod, Column::Container, Column::Node, Column::Cpu])
+            .labels(["app"]);
+        let text = write(&writer);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        let row = json::from_str::<json::Value>(lines[0]).unwrap();
+        assert_eq!(
+            row,
+            json::json!({"pod": "web-0", "container": "nginx", "cpu": 0.05, "label:app": "web"})
+        );
+        let row = json::from_str::<json::Value>(lines[2]).unwrap();
+        assert_eq!(row, json::json!({"node": "kind-control-plane", "cpu": 0.2}));
+    }
+
+    #[test]
+    fn roundtrip() {
+        for format in [SnapshotFormat::Csv, SnapshotFormat::JsonLines] {
+            let writer = SnapshotWriter::new(format).labels(["app", "tier"]);
+            let text = write(&writer);
+            let snapshot = Snapshot::read(format, text.as_bytes()).unwrap();
+            assert_eq!(snapshot.pods, pods(), "{format:?}");
+            assert_eq!(snapshot.nodes, nodes(), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn read_partial() {
+        let text = "pod,container,cpu,unknown\nweb-0,nginx,0.25,x\nweb-1,nginx,1,y\n";
+        let snapshot = Snapshot::read(SnapshotFormat::Csv, text.as_bytes()).unwrap();
+        assert_eq!(snapshot.pods.len(), 2);
+        let pod = &snapshot.pods[0];
+        assert_eq!(pod.metadata.namespace, None);
+        assert_eq!(pod.containers[0].usage.cpu.0, "250m");
+        assert_eq!(pod.containers[0].usage.memory.0, "0");
+        assert_eq!(pod.window, time::Duration::ZERO);
+        assert!(snapshot.nodes.is_empty());
+    }
+
+    #[test]
+    fn read_invalid() {
+        let read = |format, text: &str| Snapshot::read(format, text.as_bytes()).unwrap_err();
+        let err = read(SnapshotFormat::Csv, "pod,cpu\nweb-0,1\n");
+        assert!(
+            matches!(err, SnapshotError::Invalid { row: 1, .. }),
+            "{err}"
+        );
+        let err = read(SnapshotFormat::Csv, "node,cpu\nkind,\"1\n");
+        assert!(matches!(err, SnapshotError::Invalid { .. }), "{err}");
+        let err = read(SnapshotFormat::Csv, "node,window\nkind,-1\n");
+        assert!(
+            matches!(err, SnapshotError::Invalid { row: 1, .. }),
+            "{err}"
+        );
+        let err = read(
+            SnapshotFormat::JsonLines,
+            "{\"node\":\"kind\"}\n{\"node\":\"kind\",\"cpu\":\"x\"}",
+        );
+        assert!(
+            matches!(err, SnapshotError::Invalid { row: 2, .. }),
+            "{err}"
+        );
+        let err = read(SnapshotFormat::JsonLines, "[1]");
+        assert!(matches!(err, SnapshotError::Json(_)), "{err}");
+    }
+
+    #[test]
+    fn csv_quoting() {
+        let records = csv_records("a,\"b,\"\"c\"\"\nd\",\r\n\"\",e").unwrap();
+        assert_eq!(records, [vec!["a", "b,\"c\"\nd", ""], vec!["", "e"]]);
+    }
+}
@@ -1,7 +1,13 @@
 use super::*;
 
+#[cfg(feature = "kube")]
+pub use api::{ExternalMetricsApi, ExternalMetricsError};
+
 pub mod v1beta1;
 
+#[cfg(feature = "kube")]
+mod api;
+
 pub trait ExternalMetric {
     const KIND: &'static str;
     const URL_PATH_SEGMENT: &'static str;
@@ -0,0 +1,228 @@
+use std::fmt;
+
+use super::*;
+
+use v1beta1::{DynamicExternalMetricValueList, ExternalMetricResource, ExternalMetricValueList};
+
+/// Client of the external metrics API
+///
+pub struct ExternalMetricsApi {
+    client: kube::Client,
+    namespace: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExternalMetricsError {
+    /// The adapter does not know the metric (404)
+    ///
+    #[error("External metric '{metric_name}' not found: {source}")]
+    MetricNotFound {
+        metric_name: String,
+        #[source]
+        source: kube::Error,
+    },
+
+    /// The adapter serving external metrics is not available (503)
+    ///
+    #[error("External metrics adapter unavailable: {0}")]
+    AdapterUnavailable(#[source] kube::Error),
+
+    #[error(transparent)]
+    Selector(#[from] SelectorParseError),
+
+    #[error(transparent)]
+    Kube(#[from] kube::Error),
+}
+
+impl ExternalMetricsApi {
+    /// External metrics in `namespace`
+    ///
+    pub fn namespaced(client: kube::Client, namespace: &str) -> Self {
+        let namespace = namespace.to_string();
+        Self { client, namespace }
+    }
+
+    /// External metrics in the client's default namespace
+    ///
+    pub fn default_namespaced(client: kube::Client) -> Self {
+        let namespace = client.default_namespace().to_string();
+        Self { client, namespace }
+    }
+
+    /// List values of `metric_name` for the time series selected by `metric_selector`
+    ///
+    pub async fn list(
+        &self,
+        metric_name: &str,
+        metric_selector: &metav1::LabelSelector,
+    ) -> Result<DynamicExternalMetricValueList, ExternalMetricsError> {
+        self.request(&ExternalMetricResource::new(metric_name), metric_selector)
+            .await
+    }
+
+    /// List values of statically known metric `M` for the time series selected by `metric_selector`
+    ///
+    pub async fn list_metric<M>(
+        &self,
+        metric_selector: &metav1::LabelSelector,
+    ) -> Result<ExternalMetricValueList<M>, ExternalMetricsError>
+    where
+        M: ExternalMetric,
+    {
+        self.request(&ExternalMetricResource::erase::<M>(), metric_selector)
+            .await
+    }
+
+    async fn request<T>(
+        &self,
+        resource: &ExternalMetricResource,
+        metric_selector: &metav1::LabelSelector,
+    ) -> Result<T, ExternalMetricsError>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let url = resource.list_url(&self.namespace, metric_selector)?;
+        let request = http::Request::get(url)
+            .body(Vec::new())
+            .map_err(kube::Error::HttpError)?;
+
+        self.client
+            .request(request)
+            .await
+            .map_err(|err| ExternalMetricsError::new(&resource.metric_name, err))
+    }
+}
+
+impl ExternalMetricsError {
+    fn new(metric_name: &str, err: kube::Error) -> Self {
+        match &err {
+            kube::Error::Api(response) if response.code == 404 => Self::MetricNotFound {
+                metric_name: metric_name.to_string(),
+                source: err,
+            },
+            kube::Error::Api(response) if response.code == 503 => Self::AdapterUnavailable(err),
+            _ => Self::Kube(err),
+        }
+    }
+}
+
+impl Clone for ExternalMetricsApi {
+    fn clone(&self) -> Self {
+        Self::namespaced(self.client.clone(), &self.namespace)
+    }
+}
+
+impl fmt::Debug for ExternalMetricsApi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExternalMetricsApi")
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use super::*;
+
+    const KEDA: &str = r#"{
+  "kind": "ExternalMetricValueList",
+  "apiVersion": "external.metrics.k8s.io/v1beta1",
+  "metadata": {},
+  "items": [
+    {
+      "metricName": "s0-rabbitmq-worker_tasks",
+      "metricLabels": null,
+      "timestamp": "2024-03-12T09:16:07Z",
+      "value": "18"
+    }
+  ]
+}"#;
+
+    const NOT_FOUND: &str = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"the server could not find the metric s0-unknown for ","reason":"NotFound","code":404}"#;
+
+    const UNAVAILABLE: &str = r#"{"kind":"Status","apiVersion":"v1","metadata":{},"status":"Failure","message":"the server is currently unable to handle the request","reason":"ServiceUnavailable","code":503}"#;
+
+    #[derive(Debug)]
+    struct WorkerTasks;
+
+    impl ExternalMetric for WorkerTasks {
+        const KIND: &'static str = "ExternalMetricValue";
+        const URL_PATH_SEGMENT: &'static str = "s0-rabbitmq-worker_tasks";
+    }
+
+    /// Local mock of the API server answering `body` with `status` on `uri` and 404 otherwise
+    ///
+    fn client(uri: &'static str, status: u16, body: &'static str) -> kube::Client {
+        let service = tower::service_fn(move |request: http::Request<kube::client::Body>| {
+            let (status, body) = if request.uri() == uri {
+                (status, body)
+            } else {
+                (404, NOT_FOUND)
+            };
+            let mut response =
+                http::Response::new(kube::client::Body::from(body.as_bytes().to_vec()));
+            *response.status_mut() = http::StatusCode::from_u16(status).unwrap();
+            async move { Ok::<_, Infallible>(response) }
+        });
+        kube::Client::new(service, "default")
+    }
+
+    #[tokio::test]
+    async fn list() {
+        let client = client(
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/keda/s0-rabbitmq-worker_tasks?labelSelector=scaledobject.keda.sh%2Fname%3Dworker",
+            200,
+            KEDA,
+        );
+        let api = ExternalMetricsApi::namespaced(client, "keda");
+        let selector = metav1::LabelSelector::parse("scaledobject.keda.sh/name=worker").unwrap();
+        let list = api
+            .list("s0-rabbitmq-worker_tasks", &selector)
+            .await
+            .unwrap();
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 18.0);
+    }
+
+    #[tokio::test]
+    async fn list_metric() {
+        let client = client(
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/s0-rabbitmq-worker_tasks",
+            200,
+            KEDA,
+        );
+        let api = ExternalMetricsApi::default_namespaced(client);
+        let list = api.list_metric::<WorkerTasks>(&default()).await.unwrap();
+        assert_eq!(list.items[0].metric_name, "s0-rabbitmq-worker_tasks");
+    }
+
+    #[tokio::test]
+    async fn metric_not_found() {
+        let client = client("/", 200, KEDA);
+        let api = ExternalMetricsApi::default_namespaced(client);
+        let err = api.list("s0-unknown", &default()).await.unwrap_err();
+        assert!(
+            matches!(&err, ExternalMetricsError::MetricNotFound { metric_name, .. } if metric_name == "s0-unknown"),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn adapter_unavailable() {
+        let client = client(
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/s0-rabbitmq-worker_tasks",
+            503,
+            UNAVAILABLE,
+        );
+        let api = ExternalMetricsApi::default_namespaced(client);
+        let err = api
+            .list_metric::<WorkerTasks>(&default())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ExternalMetricsError::AdapterUnavailable(_)),
+            "{err:?}"
+        );
+    }
+}
@@ -3,14 +3,25 @@ use std::marker::PhantomData;
 
 use super::*;
 
+pub use dynamic::{
+    DynamicExternalMetric, DynamicExternalMetricValue, DynamicExternalMetricValueList,
+    ExternalMetricResource,
+};
+
+mod dynamic;
+
+const API_VERSION: &str = "external.metrics.k8s.io/v1beta1";
+const LIST_KIND: &str = "ExternalMetricValueList";
+
 /// `ExternalMetricValue` is a metric value for external metric
 ///
 /// A single metric value is identified by metric name and a set of string labels.
 /// For one metric there can be multiple values with different sets of labels.
 ///
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(rename = "camelCase")]
+#[serde(rename_all = "camelCase")]
 pub struct ExternalMetricValue<M> {
+    #[serde(default)]
     pub metadata: metav1::ObjectMeta,
 
     /// the name of the metric
@@ -19,6 +30,7 @@ pub struct ExternalMetricValue<M> {
 
     /// a set of labels that identify a single time series for the metric
     ///
+    #[serde(default, deserialize_with = "null_as_default")]
     pub metric_labels: BTreeMap<String, String>, // `json:"metricLabels" protobuf:"bytes,2,rep,name=metricLabels"`
 
     /// indicates the time at which the metrics were produced
@@ -30,18 +42,154 @@ pub struct ExternalMetricValue<M> {
     /// metrics calculated from cumulative metrics (or zero for
     /// non-calculated instantaneous metrics).
     ///
-    #[serde(default, rename = "window")]
-    pub window_seconds: i64, // `json:"window,omitempty" protobuf:"bytes,4,opt,name=window"`
+    #[serde(default, rename = "window", skip_serializing_if = "Option::is_none")]
+    pub window_seconds: Option<i64>, // `json:"window,omitempty" protobuf:"bytes,4,opt,name=window"`
 
     /// the value of the metric
     ///
     pub value: resource::Quantity, // `json:"value" protobuf:"bytes,5,name=value"`
 
+    #[serde(skip)]
     phantom: PhantomData<M>,
 }
 
+/// Builder for `ExternalMetricValue`
+///
+#[derive(Debug)]
+pub struct ExternalMetricValueBuilder<M> {
+    value: ExternalMetricValue<M>,
+}
+
+impl<M> ExternalMetricValue<M> {
+    /// Create new `ExternalMetricValue` for `metric_name` produced right now
+    ///
+    pub fn new(metric_name: impl ToString, value: resource::Quantity) -> Self {
+        Self {
+            metadata: default(),
+            metric_name: metric_name.to_string(),
+            metric_labels: default(),
+            timestamp: now(),
+            window_seconds: None,
+            value,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Create new `ExternalMetricValue` for the time series identified by `metric_name` and `labels`
+    ///
+    pub fn with_labels<K, V>(
+        metric_name: impl ToString,
+        labels: impl IntoIterator<Item = (K, V)>,
+        value: resource::Quantity,
+    ) -> Self
+    where
+        K: ToString,
+        V: ToString,
+    {
+        Self::builder(metric_name)
+            .labels(labels)
+            .value(value)
+            .build()
+    }
+
+    /// Forget the statically known metric
+    ///
+    pub fn into_dynamic(self) -> DynamicExternalMetricValue {
+        ExternalMetricValue {
+            metadata: self.metadata,
+            metric_name: self.metric_name,
+            metric_labels: self.metric_labels,
+            timestamp: self.timestamp,
+            window_seconds: self.window_seconds,
+            value: self.value,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Start building `ExternalMetricValue` for `metric_name`
+    ///
+    pub fn builder(metric_name: impl ToString) -> ExternalMetricValueBuilder<M> {
+        let value = Self::new(metric_name, default());
+        ExternalMetricValueBuilder { value }
+    }
+}
+
+impl<M> ExternalMetricValueBuilder<M> {
+    /// Add single label identifying the time series
+    ///
+    pub fn label(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.value
+            .metric_labels
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Add labels identifying the time series
+    ///
+    pub fn labels<K, V>(self, labels: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: ToString,
+        V: ToString,
+    {
+        labels
+            .into_iter()
+            .fold(self, |builder, (key, value)| builder.label(key, value))
+    }
+
+    /// Set the time at which the metric was produced (defaults to now)
+    ///
+    pub fn timestamp(mut self, timestamp: metav1::Time) -> Self {
+        self.value.timestamp = timestamp;
+        self
+    }
+
+    /// Set the window the rate metric was calculated from, in whole seconds
+    ///
+    pub fn window(mut self, window: time::Duration) -> Self {
+        self.value.window_seconds = Some(i64::try_from(window.as_secs()).unwrap_or(i64::MAX));
+        self
+    }
+
+    /// Set the value of the metric, numbers are converted to canonical quantity
+    ///
+    pub fn value(mut self, value: impl ToQuantity) -> Self {
+        self.value.value = value.to_quantity();
+        self
+    }
+
+    pub fn build(self) -> ExternalMetricValue<M> {
+        self.value
+    }
+}
+
+// Implemented by hand, derive would require `M: Clone` and `M: PartialEq` from the marker types
+impl<M> Clone for ExternalMetricValue<M> {
+    fn clone(&self) -> Self {
+        Self {
+            metadata: self.metadata.clone(),
+            metric_name: self.metric_name.clone(),
+            metric_labels: self.metric_labels.clone(),
+            timestamp: self.timestamp.clone(),
+            window_seconds: self.window_seconds,
+            value: self.value.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<M> PartialEq for ExternalMetricValue<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.metadata == other.metadata
+            && self.metric_name == other.metric_name
+            && self.metric_labels == other.metric_labels
+            && self.timestamp == other.timestamp
+            && self.window_seconds == other.window_seconds
+            && self.value == other.value
+    }
+}
+
 impl<M: ExternalMetric> k8s::Resource for ExternalMetricValue<M> {
-    const API_VERSION: &'static str = "external.metrics.k8s.io/v1beta1";
+    const API_VERSION: &'static str = API_VERSION;
     const GROUP: &'static str = "external.metrics.k8s.io";
     const KIND: &'static str = M::KIND;
     const VERSION: &'static str = "v1beta1";
@@ -49,8 +197,165 @@ impl<M: ExternalMetric> k8s::Resource for ExternalMetricValue<M> {
     type Scope = k8s::ClusterResourceScope;
 }
 
+impl<M: ExternalMetric> ExternalMetricValue<M> {
+    /// URL path of the metric values in `namespace`
+    ///
+    pub fn url_path(namespace: &str) -> String {
+        ExternalMetricResource::erase::<M>().url_path(namespace)
+    }
+}
+
 impl<M: ExternalMetric> k8s::ListableResource for ExternalMetricValue<M> {
-    const LIST_KIND: &'static str = "ExternalMetricValueList";
+    const LIST_KIND: &'static str = LIST_KIND;
 }
 
 pub type ExternalMetricValueList<M> = k8s::List<ExternalMetricValue<M>>;
+
+/// Go adapters (e.g. KEDA) send `"metricLabels": null` for metrics without labels
+///
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    #[derive(Debug)]
+    struct QueueMessagesReady;
+
+    impl ExternalMetric for QueueMessagesReady {
+        const KIND: &'static str = "ExternalMetricValue";
+        const URL_PATH_SEGMENT: &'static str = "queue_messages_ready";
+    }
+
+    /// Captured from prometheus-adapter
+    ///
+    const PROMETHEUS_ADAPTER: &str = r#"{
+  "kind": "ExternalMetricValueList",
+  "apiVersion": "external.metrics.k8s.io/v1beta1",
+  "metadata": {},
+  "items": [
+    {
+      "metricName": "queue_messages_ready",
+      "metricLabels": {
+        "__name__": "queue_messages_ready",
+        "namespace": "default",
+        "queue": "worker_tasks"
+      },
+      "timestamp": "2024-03-12T09:15:42Z",
+      "value": "25"
+    }
+  ]
+}"#;
+
+    /// Captured from KEDA metrics apiserver
+    ///
+    const KEDA: &str = r#"{
+  "kind": "ExternalMetricValueList",
+  "apiVersion": "external.metrics.k8s.io/v1beta1",
+  "metadata": {},
+  "items": [
+    {
+      "metricName": "s0-rabbitmq-worker_tasks",
+      "metricLabels": null,
+      "timestamp": "2024-03-12T09:16:07Z",
+      "window": 30,
+      "value": "18500m"
+    }
+  ]
+}"#;
+
+    #[test]
+    fn prometheus_adapter() {
+        let list: ExternalMetricValueList<QueueMessagesReady> =
+            json::from_str(PROMETHEUS_ADAPTER).unwrap();
+        assert_eq!(list.items.len(), 1);
+        let value = &list.items[0];
+        assert_eq!(value.metric_name, "queue_messages_ready");
+        assert_eq!(value.metric_labels["queue"], "worker_tasks");
+        assert_eq!(value.window_seconds, None);
+        assert_eq!(value.value.to_f64().unwrap(), 25.0);
+    }
+
+    #[test]
+    fn keda() {
+        let list: ExternalMetricValueList<QueueMessagesReady> = json::from_str(KEDA).unwrap();
+        let value = &list.items[0];
+        assert_eq!(value.metric_name, "s0-rabbitmq-worker_tasks");
+        assert!(value.metric_labels.is_empty());
+        assert_eq!(value.window_seconds, Some(30));
+        assert_eq!(value.value.to_f64().unwrap(), 18.5);
+    }
+
+    #[test]
+    fn serialize() {
+        let list: ExternalMetricValueList<QueueMessagesReady> =
+            json::from_str(PROMETHEUS_ADAPTER).unwrap();
+        let value = json::to_value(&list.items[0]).unwrap();
+        assert_eq!(value["metricName"], "queue_messages_ready");
+        assert_eq!(value["metricLabels"]["queue"], "worker_tasks");
+        assert!(value.get("window").is_none());
+        assert!(value.get("phantom").is_none());
+        assert!(value.get("metric_name").is_none());
+    }
+
+    #[test]
+    fn new() {
+        let value =
+            ExternalMetricValue::<QueueMessagesReady>::new("queue_messages_ready", quantity("25"));
+        assert_eq!(value.metric_name, "queue_messages_ready");
+        assert!(value.metric_labels.is_empty());
+        assert_eq!(value.window_seconds, None);
+        assert!(value.timestamp.0 > DateTime::<Utc>::default());
+    }
+
+    #[test]
+    fn builder() {
+        let timestamp = metav1::Time("2024-03-12T09:15:42Z".parse().unwrap());
+        let value = ExternalMetricValue::<QueueMessagesReady>::builder("queue_messages_ready")
+            .label("queue", "worker_tasks")
+            .labels([("namespace", "default")])
+            .timestamp(timestamp.clone())
+            .window(time::Duration::from_secs(30))
+            .value(quantity("25"))
+            .build();
+        assert_eq!(value.metric_labels.len(), 2);
+        assert_eq!(value.timestamp, timestamp);
+        assert_eq!(value.window_seconds, Some(30));
+
+        let labels = [("queue", "worker_tasks"), ("namespace", "default")];
+        let other =
+            ExternalMetricValue::with_labels("queue_messages_ready", labels, quantity("25"));
+        assert_eq!(
+            value,
+            ExternalMetricValue {
+                timestamp,
+                window_seconds: Some(30),
+                ..other
+            }
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let value = ExternalMetricValue::<QueueMessagesReady>::builder("queue_messages_ready")
+            .label("queue", "worker_tasks")
+            .value(quantity("25"))
+            .build();
+        let text = json::to_string(&value).unwrap();
+        assert_eq!(
+            json::from_str::<ExternalMetricValue<_>>(&text).unwrap(),
+            value
+        );
+    }
+
+    fn quantity(value: &str) -> resource::Quantity {
+        resource::Quantity(value.to_string())
+    }
+}
@@ -0,0 +1,150 @@
+use super::*;
+
+/// Marker for `ExternalMetricValue` of a metric only known at runtime
+///
+/// The actual metric is described by `ExternalMetricResource`,
+/// much like kube's `DynamicObject` is described by `ApiResource`.
+/// It is deliberately not `ExternalMetric`, so `DynamicExternalMetricValue` is not
+/// a `k8s::Resource` and is requested through `ExternalMetricsApi::list` instead.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DynamicExternalMetric;
+
+pub type DynamicExternalMetricValue = ExternalMetricValue<DynamicExternalMetric>;
+
+/// List of `DynamicExternalMetricValue`
+///
+pub type DynamicExternalMetricValueList = DynamicList<DynamicExternalMetricValue>;
+
+impl DynamicListItem for DynamicExternalMetricValue {
+    const LIST_API_VERSION: &'static str = API_VERSION;
+    const LIST_KIND: &'static str = LIST_KIND;
+}
+
+/// Runtime description of an external metric
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExternalMetricResource {
+    /// the name of the metric as used in the request path
+    ///
+    pub metric_name: String,
+}
+
+impl ExternalMetricResource {
+    pub fn new(metric_name: impl ToString) -> Self {
+        let metric_name = metric_name.to_string();
+        Self { metric_name }
+    }
+
+    /// Describe statically known external metric `M`
+    ///
+    pub fn erase<M: ExternalMetric>() -> Self {
+        Self::new(M::URL_PATH_SEGMENT)
+    }
+
+    /// URL path of the metric values in `namespace`, the metric name is percent-encoded
+    ///
+    pub fn url_path(&self, namespace: &str) -> String {
+        let metric_name = percent_encoding::utf8_percent_encode(&self.metric_name, PATH_SEGMENT);
+        format!("/apis/{API_VERSION}/namespaces/{namespace}/{metric_name}")
+    }
+
+    /// URL of the metric values in `namespace` limited to the time series selected by `metric_selector`
+    ///
+    pub fn list_url(
+        &self,
+        namespace: &str,
+        metric_selector: &metav1::LabelSelector,
+    ) -> Result<String, SelectorParseError> {
+        let path = self.url_path(namespace);
+        let selector = metric_selector.format()?;
+        let url = if selector.is_empty() {
+            path
+        } else {
+            let query = form_urlencoded::Serializer::new(String::new())
+                .append_pair("labelSelector", &selector)
+                .finish();
+            format!("{path}?{query}")
+        };
+        Ok(url)
+    }
+
+    /// Create new value of this metric produced right now
+    ///
+    pub fn value(&self, value: impl ToQuantity) -> DynamicExternalMetricValue {
+        DynamicExternalMetricValue::new(&self.metric_name, value.to_quantity())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    #[derive(Debug)]
+    struct QueueMessagesReady;
+
+    impl ExternalMetric for QueueMessagesReady {
+        const KIND: &'static str = "ExternalMetricValue";
+        const URL_PATH_SEGMENT: &'static str = "queue_messages_ready";
+    }
+
+    #[test]
+    fn url_path() {
+        let resource = ExternalMetricResource::new("s0-rabbitmq-worker_tasks");
+        assert_eq!(
+            resource.url_path("default"),
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/s0-rabbitmq-worker_tasks"
+        );
+        assert_eq!(
+            ExternalMetricValue::<QueueMessagesReady>::url_path("default"),
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/queue_messages_ready"
+        );
+        assert_eq!(
+            ExternalMetricResource::new("jobs/queue depth?").url_path("default"),
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/jobs%2Fqueue%20depth%3F"
+        );
+    }
+
+    #[test]
+    fn list_url() {
+        let resource = ExternalMetricResource::erase::<QueueMessagesReady>();
+        let selector = metav1::LabelSelector::parse("queue=worker_tasks").unwrap();
+        assert_eq!(
+            resource.list_url("default", &selector).unwrap(),
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/default/queue_messages_ready?labelSelector=queue%3Dworker_tasks"
+        );
+        assert_eq!(
+            resource.list_url("default", &default()).unwrap(),
+            resource.url_path("default")
+        );
+    }
+
+    #[test]
+    fn list() {
+        let text = r#"{
+  "kind": "ExternalMetricValueList",
+  "apiVersion": "external.metrics.k8s.io/v1beta1",
+  "metadata": {},
+  "items": [
+    {
+      "metricName": "s0-rabbitmq-worker_tasks",
+      "metricLabels": null,
+      "timestamp": "2024-03-12T09:16:07Z",
+      "value": "18"
+    }
+  ]
+}"#;
+        let list: DynamicExternalMetricValueList = json::from_str(text).unwrap();
+        let resource = ExternalMetricResource::new(&list.items[0].metric_name);
+        assert_eq!(resource.metric_name, "s0-rabbitmq-worker_tasks");
+
+        let value = resource.value(18);
+        assert_eq!(value.metric_name, list.items[0].metric_name);
+        assert_eq!(value.value, list.items[0].value);
+
+        let list = json::to_value(&list).unwrap();
+        assert_eq!(list["kind"], "ExternalMetricValueList");
+        assert_eq!(list["apiVersion"], "external.metrics.k8s.io/v1beta1");
+    }
+}
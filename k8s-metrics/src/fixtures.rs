@@ -0,0 +1,64 @@
+//! Pod and node metrics shared by the unit tests
+
+use std::collections::BTreeMap;
+
+use super::*;
+
+use v1beta1::{Container, NodeMetrics, PodMetrics, Usage};
+
+/// Unix time of the fixtures, 2024-03-12T09:16:15Z
+///
+pub(crate) const TIMESTAMP: i64 = 1_710_234_975;
+
+pub(crate) fn time() -> metav1::Time {
+    metav1::Time(DateTime::from_timestamp(TIMESTAMP, 0).unwrap())
+}
+
+pub(crate) fn usage(cpu: &str, memory: &str) -> Usage {
+    Usage {
+        cpu: resource::Quantity(cpu.to_string()),
+        memory: resource::Quantity(memory.to_string()),
+    }
+}
+
+pub(crate) fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+    labels
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Pod `name` in `namespace` with `(name, cpu, memory)` containers, observed at `time()` over 15s
+///
+pub(crate) fn pod(namespace: &str, name: &str, containers: &[(&str, &str, &str)]) -> PodMetrics {
+    PodMetrics {
+        metadata: metav1::ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some(namespace.to_string()),
+            ..default()
+        },
+        containers: containers
+            .iter()
+            .map(|(name, cpu, memory)| Container {
+                name: name.to_string(),
+                usage: usage(cpu, memory),
+            })
+            .collect(),
+        timestamp: time(),
+        window: time::Duration::from_secs(15),
+    }
+}
+
+/// Node `name` using `cpu` and `memory`, observed at `time()` over 15s
+///
+pub(crate) fn node(name: &str, cpu: &str, memory: &str) -> NodeMetrics {
+    NodeMetrics {
+        metadata: metav1::ObjectMeta {
+            name: Some(name.to_string()),
+            ..default()
+        },
+        timestamp: time(),
+        window: time::Duration::from_secs(15),
+        usage: usage(cpu, memory),
+    }
+}
@@ -0,0 +1,1049 @@
+//! Prediction of the replica count chosen by the HorizontalPodAutoscaler
+//!
+//! `ReplicaCalculator` follows the replica calculator of kube-controller-manager: every metric
+//! of the HPA proposes a replica count from the observed values and the largest proposal wins.
+//! Values are compared in milli units with the same integer arithmetic as the controller,
+//! so the rounding matches as well.
+//!
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use k8s::api::autoscaling::v2 as autoscalingv2;
+use k8s::chrono::TimeDelta;
+
+use super::*;
+
+use custom_metrics::v1beta2::{DynamicMetricValue, MetricValue};
+use external_metrics::v1beta1::{DynamicExternalMetricValue, ExternalMetricValue};
+use quantity::milli;
+use v1beta1::PodMetrics;
+
+pub mod simulation;
+
+/// Default of `--horizontal-pod-autoscaler-tolerance`
+///
+pub const DEFAULT_TOLERANCE: f64 = 0.1;
+
+/// Default of `--horizontal-pod-autoscaler-cpu-initialization-period`
+///
+pub const DEFAULT_CPU_INITIALIZATION_PERIOD: time::Duration = time::Duration::from_secs(300);
+
+/// Default of `--horizontal-pod-autoscaler-initial-readiness-delay`
+///
+pub const DEFAULT_INITIAL_READINESS_DELAY: time::Duration = time::Duration::from_secs(30);
+
+/// Utilization target of the CPU metric assumed when the HPA has no metrics
+///
+pub const DEFAULT_CPU_UTILIZATION: i32 = 80;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HpaError {
+    #[error("No pods returned by selector while calculating replica count")]
+    NoPods,
+
+    #[error("Did not receive metrics for targeted pods (pods might be unready)")]
+    NoPodMetrics,
+
+    #[error("No metrics returned matched known pods")]
+    NoMatchingMetrics,
+
+    #[error("No metrics returned for {0}")]
+    MissingMetric(String),
+
+    #[error("Missing request for {resource} in container {container} of Pod {pod}")]
+    MissingRequest {
+        resource: String,
+        container: String,
+        pod: String,
+    },
+
+    #[error("Container {container} not present in metrics for Pod {pod}")]
+    MissingContainer { container: String, pod: String },
+
+    #[error("Unsupported resource {0}")]
+    UnsupportedResource(String),
+
+    #[error("Invalid metric source: {0}")]
+    InvalidMetricSource(String),
+
+    #[error("Invalid metrics ({invalid} invalid out of {total}), first error is: {first}")]
+    InvalidMetrics {
+        invalid: usize,
+        total: usize,
+        first: Box<Self>,
+    },
+
+    #[error(transparent)]
+    Quantity(#[from] QuantityParseError),
+
+    #[error(transparent)]
+    Selector(#[from] SelectorParseError),
+}
+
+/// State of the scale target observed by the HPA
+///
+#[derive(Clone, Debug, Default)]
+pub struct Observation {
+    /// `spec.replicas` of the scale subresource
+    ///
+    pub spec_replicas: i32,
+    /// `status.replicas` of the scale subresource, used by `AverageValue` targets of
+    /// `Object` and `External` metrics
+    ///
+    pub status_replicas: i32,
+    /// pods selected by the scale target
+    ///
+    pub pods: Vec<corev1::Pod>,
+    /// usage of the selected pods, for `Resource` and `ContainerResource` metrics
+    ///
+    pub pod_metrics: Vec<PodMetrics>,
+    /// custom metric values, for `Pods` and `Object` metrics
+    ///
+    pub custom_metrics: Vec<DynamicMetricValue>,
+    /// external metric values, for `External` metrics
+    ///
+    /// Values are matched by metric name and selector; values without labels are
+    /// matched by name only, as adapters (e.g. KEDA) may not return any labels.
+    ///
+    pub external_metrics: Vec<DynamicExternalMetricValue>,
+}
+
+impl Observation {
+    /// Observe scale target both desiring and running `replicas`
+    ///
+    pub fn new(replicas: i32) -> Self {
+        Self {
+            spec_replicas: replicas,
+            status_replicas: replicas,
+            ..default()
+        }
+    }
+
+    pub fn status_replicas(mut self, replicas: i32) -> Self {
+        self.status_replicas = replicas;
+        self
+    }
+
+    pub fn pods(mut self, pods: impl IntoIterator<Item = corev1::Pod>) -> Self {
+        self.pods.extend(pods);
+        self
+    }
+
+    pub fn pod_metrics(mut self, metrics: impl IntoIterator<Item = PodMetrics>) -> Self {
+        self.pod_metrics.extend(metrics);
+        self
+    }
+
+    /// Add custom metric values, e.g. the items of `MetricValueList<corev1::Pod>`
+    ///
+    pub fn custom_metrics<M>(mut self, values: impl IntoIterator<Item = MetricValue<M>>) -> Self {
+        let values = values.into_iter().map(MetricValue::into_dynamic);
+        self.custom_metrics.extend(values);
+        self
+    }
+
+    /// Add external metric values, e.g. the items of `ExternalMetricValueList`
+    ///
+    pub fn external_metrics<M>(
+        mut self,
+        values: impl IntoIterator<Item = ExternalMetricValue<M>>,
+    ) -> Self {
+        let values = values.into_iter().map(ExternalMetricValue::into_dynamic);
+        self.external_metrics.extend(values);
+        self
+    }
+}
+
+/// Replica count recommended by the metrics of an HPA
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recommendation {
+    /// desired replicas, within `minReplicas` and `maxReplicas`
+    ///
+    pub replicas: i32,
+    /// the largest proposal of the metrics before the replica bounds are applied
+    ///
+    pub metric_replicas: i32,
+    /// description of the metric the proposal came from, e.g.
+    /// `cpu resource utilization (percentage of request)`,
+    /// `None` when the current replicas are out of bounds and metrics are not consulted
+    ///
+    pub metric: Option<String>,
+    /// when the values of that metric were produced
+    ///
+    pub timestamp: Option<DateTime<Utc>>,
+    /// current values of the metrics as reported in `status.currentMetrics`,
+    /// empty status for metrics which could not be evaluated
+    ///
+    pub statuses: Vec<autoscalingv2::MetricStatus>,
+}
+
+/// Calculator of the desired replicas of an HPA
+///
+/// Rate limits and stabilization of the HPA `behavior` are not applied,
+/// see `simulation::Simulator` for those.
+///
+#[derive(Clone, Debug)]
+pub struct ReplicaCalculator {
+    tolerance: f64,
+    cpu_initialization_period: time::Duration,
+    initial_readiness_delay: time::Duration,
+    now: Option<DateTime<Utc>>,
+}
+
+impl Default for ReplicaCalculator {
+    fn default() -> Self {
+        Self {
+            tolerance: DEFAULT_TOLERANCE,
+            cpu_initialization_period: DEFAULT_CPU_INITIALIZATION_PERIOD,
+            initial_readiness_delay: DEFAULT_INITIAL_READINESS_DELAY,
+            now: None,
+        }
+    }
+}
+
+/// Milli value of a metric for single pod
+///
+#[derive(Clone, Copy, Debug, Default)]
+struct PodMetric {
+    timestamp: DateTime<Utc>,
+    window: time::Duration,
+    value: i64,
+}
+
+impl PodMetric {
+    fn assumed(value: i64) -> Self {
+        Self { value, ..default() }
+    }
+}
+
+type PodMetricsInfo = BTreeMap<String, PodMetric>;
+
+/// Pods sorted by how their metrics are taken into account
+///
+#[derive(Debug, Default)]
+struct PodGroups {
+    ready: usize,
+    unready: BTreeSet<String>,
+    missing: BTreeSet<String>,
+    ignored: BTreeSet<String>,
+}
+
+#[derive(Debug)]
+struct Proposal {
+    replicas: i32,
+    metric: String,
+    timestamp: Option<DateTime<Utc>>,
+    status: autoscalingv2::MetricStatus,
+}
+
+impl ReplicaCalculator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ratio of usage to target within which no scaling happens
+    ///
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Period after pod start when CPU samples are ignored unless the pod is ready
+    /// and the sample was collected after the pod became ready
+    ///
+    pub fn cpu_initialization_period(mut self, period: time::Duration) -> Self {
+        self.cpu_initialization_period = period;
+        self
+    }
+
+    /// Period after pod start when a pod not ready yet is considered to be starting up
+    ///
+    pub fn initial_readiness_delay(mut self, delay: time::Duration) -> Self {
+        self.initial_readiness_delay = delay;
+        self
+    }
+
+    /// Evaluate pod readiness at `now` instead of the current time
+    ///
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.now = Some(now);
+        self
+    }
+
+    /// Compute the replicas desired by the HPA `spec` for the `observation`
+    ///
+    /// When the current replicas are out of the HPA bounds the nearest bound is desired,
+    /// when the scale target is scaled to zero (and `minReplicas` is not zero) autoscaling
+    /// is disabled. Otherwise all the metrics are evaluated (CPU utilization of 80% when
+    /// there are none) and the largest proposal is taken. Metrics which can't be evaluated
+    /// are skipped, unless they all fail or the rest would scale down.
+    ///
+    pub fn recommend(
+        &self,
+        spec: &autoscalingv2::HorizontalPodAutoscalerSpec,
+        observation: &Observation,
+    ) -> Result<Recommendation, HpaError> {
+        let current = observation.spec_replicas;
+        let min = spec.min_replicas.unwrap_or(1);
+        let max = spec.max_replicas;
+
+        let bound = if current == 0 && min != 0 {
+            Some(0)
+        } else if current > max {
+            Some(max)
+        } else if current < min {
+            Some(min)
+        } else {
+            None
+        };
+        if let Some(replicas) = bound {
+            return Ok(Recommendation {
+                replicas,
+                metric_replicas: replicas,
+                metric: None,
+                timestamp: None,
+                statuses: Vec::new(),
+            });
+        }
+
+        let metrics = match spec.metrics.as_deref() {
+            Some(metrics) if !metrics.is_empty() => metrics.to_vec(),
+            _ => vec![default_cpu_metric()],
+        };
+
+        let mut best: Option<Proposal> = None;
+        let mut statuses = Vec::with_capacity(metrics.len());
+        let mut invalid = 0;
+        let mut first_error = None;
+        for metric in &metrics {
+            match self.propose(metric, observation) {
+                Ok(proposal) => {
+                    statuses.push(proposal.status.clone());
+                    let replicas = best.as_ref().map_or(0, |best| best.replicas);
+                    if replicas == 0 || proposal.replicas > replicas {
+                        best = Some(proposal);
+                    }
+                }
+                Err(err) => {
+                    statuses.push(default());
+                    invalid += 1;
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+
+        let metric_replicas = best.as_ref().map_or(0, |best| best.replicas);
+        if let Some(first) = first_error {
+            if invalid >= metrics.len() || metric_replicas < current {
+                return Err(HpaError::InvalidMetrics {
+                    invalid,
+                    total: metrics.len(),
+                    first: Box::new(first),
+                });
+            }
+        }
+
+        let replicas = if metric_replicas < min {
+            min
+        } else if metric_replicas > max {
+            max
+        } else {
+            metric_replicas
+        };
+        Ok(Recommendation {
+            replicas,
+            metric_replicas,
+            metric: best.as_ref().map(|best| best.metric.clone()),
+            timestamp: best.and_then(|best| best.timestamp),
+            statuses,
+        })
+    }
+
+    fn propose(
+        &self,
+        metric: &autoscalingv2::MetricSpec,
+        observation: &Observation,
+    ) -> Result<Proposal, HpaError> {
+        let missing_source = || HpaError::InvalidMetricSource(format!("{} not set", metric.type_));
+        match metric.type_.as_str() {
+            "Resource" => {
+                let source = metric.resource.as_ref().ok_or_else(missing_source)?;
+                self.resource_proposal(&source.name, None, &source.target, observation)
+            }
+            "ContainerResource" => {
+                let source = metric
+                    .container_resource
+                    .as_ref()
+                    .ok_or_else(missing_source)?;
+                let container = Some(source.container.as_str());
+                self.resource_proposal(&source.name, container, &source.target, observation)
+            }
+            "Pods" => {
+                let source = metric.pods.as_ref().ok_or_else(missing_source)?;
+                self.pods_proposal(source, observation)
+            }
+            "Object" => {
+                let source = metric.object.as_ref().ok_or_else(missing_source)?;
+                self.object_proposal(source, observation)
+            }
+            "External" => {
+                let source = metric.external.as_ref().ok_or_else(missing_source)?;
+                self.external_proposal(source, observation)
+            }
+            other => Err(HpaError::InvalidMetricSource(format!(
+                "unknown metric source type {other:?}"
+            ))),
+        }
+    }
+
+    fn resource_proposal(
+        &self,
+        resource: &str,
+        container: Option<&str>,
+        target: &autoscalingv2::MetricTarget,
+        observation: &Observation,
+    ) -> Result<Proposal, HpaError> {
+        let current = observation.spec_replicas;
+        let (metrics, timestamp) = resource_metrics(observation, resource, container)?;
+        let kind = if container.is_some() {
+            "container resource"
+        } else {
+            "resource"
+        };
+        let (replicas, metric, current) = if let Some(average_value) = &target.average_value {
+            let target = milli(average_value)?;
+            let (replicas, usage) =
+                self.plain_replicas(metrics, current, target, Some(resource), observation)?;
+            let current = autoscalingv2::MetricValueStatus {
+                average_value: Some(quantity::canonical(usage.into(), -3)),
+                ..default()
+            };
+            (replicas, format!("{resource} {kind}"), current)
+        } else if let Some(target) = target.average_utilization {
+            let requests = pod_requests(&observation.pods, resource, container)?;
+            let (replicas, utilization, raw) = self.utilization_replicas(
+                metrics,
+                &requests,
+                current,
+                target,
+                resource,
+                observation,
+            )?;
+            let current = autoscalingv2::MetricValueStatus {
+                average_utilization: Some(utilization),
+                average_value: Some(quantity::canonical(raw.into(), -3)),
+                ..default()
+            };
+            let metric = format!("{resource} {kind} utilization (percentage of request)");
+            (replicas, metric, current)
+        } else {
+            return Err(HpaError::InvalidMetricSource(
+                "neither an average utilization target nor an average value target was set"
+                    .to_string(),
+            ));
+        };
+
+        let status = match container {
+            Some(container) => autoscalingv2::MetricStatus {
+                type_: "ContainerResource".to_string(),
+                container_resource: Some(autoscalingv2::ContainerResourceMetricStatus {
+                    name: resource.to_string(),
+                    container: container.to_string(),
+                    current,
+                }),
+                ..default()
+            },
+            None => autoscalingv2::MetricStatus {
+                type_: "Resource".to_string(),
+                resource: Some(autoscalingv2::ResourceMetricStatus {
+                    name: resource.to_string(),
+                    current,
+                }),
+                ..default()
+            },
+        };
+        Ok(Proposal {
+            replicas,
+            metric,
+            timestamp,
+            status,
+        })
+    }
+
+    fn pods_proposal(
+        &self,
+        source: &autoscalingv2::PodsMetricSource,
+        observation: &Observation,
+    ) -> Result<Proposal, HpaError> {
+        let metric = format!("pods metric {}", source.metric.name);
+        let target = source.target.average_value.as_ref().ok_or_else(|| {
+            HpaError::InvalidMetricSource(format!("{metric} has no average value target"))
+        })?;
+        let target = milli(target)?;
+
+        let values = observation.custom_metrics.iter().filter(|value| {
+            value.metric.name == source.metric.name
+                && value.described_object.kind.as_deref() == Some("Pod")
+        });
+        let mut metrics = PodMetricsInfo::new();
+        let mut timestamp = None;
+        for value in values {
+            timestamp.get_or_insert(value.timestamp.0);
+            let metric = PodMetric {
+                timestamp: value.timestamp.0,
+                window: seconds(value.window_seconds),
+                value: milli(&value.value)?,
+            };
+            let name = value.described_object.name.clone().unwrap_or_default();
+            metrics.insert(name, metric);
+        }
+        if metrics.is_empty() {
+            return Err(HpaError::MissingMetric(metric));
+        }
+
+        let current = observation.spec_replicas;
+        let (replicas, usage) = self.plain_replicas(metrics, current, target, None, observation)?;
+        let status = autoscalingv2::MetricStatus {
+            type_: "Pods".to_string(),
+            pods: Some(autoscalingv2::PodsMetricStatus {
+                metric: source.metric.clone(),
+                current: autoscalingv2::MetricValueStatus {
+                    average_value: Some(quantity::canonical(usage.into(), -3)),
+                    ..default()
+                },
+            }),
+            ..default()
+        };
+        Ok(Proposal {
+            replicas,
+            metric,
+            timestamp,
+            status,
+        })
+    }
+
+    fn object_proposal(
+        &self,
+        source: &autoscalingv2::ObjectMetricSource,
+        observation: &Observation,
+    ) -> Result<Proposal, HpaError> {
+        let object = &source.described_object;
+        let metric = format!("{} metric {}", object.kind, source.metric.name);
+        let value = observation
+            .custom_metrics
+            .iter()
+            .find(|value| {
+                value.metric.name == source.metric.name
+                    && value.described_object.kind.as_deref() == Some(object.kind.as_str())
+                    && value.described_object.name.as_deref() == Some(object.name.as_str())
+            })
+            .ok_or_else(|| HpaError::MissingMetric(format!("{metric} of {}", object.name)))?;
+        let usage = milli(&value.value)?;
+
+        let target = &source.target;
+        let (replicas, current) =
+            match (target.type_.as_str(), &target.value, &target.average_value) {
+                ("Value", Some(target), _) => {
+                    let ratio = usage as f64 / milli(target)? as f64;
+                    let replicas = self.usage_ratio_replicas(ratio, observation)?;
+                    let current = autoscalingv2::MetricValueStatus {
+                        value: Some(quantity::canonical(usage.into(), -3)),
+                        ..default()
+                    };
+                    (replicas, current)
+                }
+                ("AverageValue", _, Some(target)) => {
+                    let (replicas, usage) =
+                        self.per_pod_replicas(usage, milli(target)?, observation);
+                    let current = autoscalingv2::MetricValueStatus {
+                        average_value: Some(quantity::canonical(usage.into(), -3)),
+                        ..default()
+                    };
+                    (replicas, current)
+                }
+                _ => {
+                    return Err(HpaError::InvalidMetricSource(
+                        "neither a value target nor an average value target was set".to_string(),
+                    ))
+                }
+            };
+
+        let status = autoscalingv2::MetricStatus {
+            type_: "Object".to_string(),
+            object: Some(autoscalingv2::ObjectMetricStatus {
+                metric: source.metric.clone(),
+                described_object: object.clone(),
+                current,
+            }),
+            ..default()
+        };
+        Ok(Proposal {
+            replicas,
+            metric,
+            timestamp: Some(value.timestamp.0),
+            status,
+        })
+    }
+
+    fn external_proposal(
+        &self,
+        source: &autoscalingv2::ExternalMetricSource,
+        observation: &Observation,
+    ) -> Result<Proposal, HpaError> {
+        let selector = source.metric.selector.clone().unwrap_or_default();
+        let metric = format!(
+            "external metric {}({})",
+            source.metric.name,
+            selector.format()?
+        );
+        let values = observation
+            .external_metrics
+            .iter()
+            .filter(|value| value.metric_name == source.metric.name)
+            .filter(|value| {
+                value.metric_labels.is_empty() || selector.matches(&value.metric_labels)
+            })
+            .collect::<Vec<_>>();
+        let Some(first) = values.first() else {
+            return Err(HpaError::MissingMetric(metric));
+        };
+        let timestamp = Some(first.timestamp.0);
+        let usage = values
+            .iter()
+            .map(|value| milli(&value.value))
+            .sum::<Result<i64, _>>()?;
+
+        let target = &source.target;
+        let (replicas, current) = if let Some(target) = &target.average_value {
+            let (replicas, usage) = self.per_pod_replicas(usage, milli(target)?, observation);
+            let current = autoscalingv2::MetricValueStatus {
+                average_value: Some(quantity::canonical(usage.into(), -3)),
+                ..default()
+            };
+            (replicas, current)
+        } else if let Some(target) = &target.value {
+            let ratio = usage as f64 / milli(target)? as f64;
+            let replicas = self.usage_ratio_replicas(ratio, observation)?;
+            let current = autoscalingv2::MetricValueStatus {
+                value: Some(quantity::canonical(usage.into(), -3)),
+                ..default()
+            };
+            (replicas, current)
+        } else {
+            return Err(HpaError::InvalidMetricSource(
+                "neither a value target nor an average value target was set".to_string(),
+            ));
+        };
+
+        let status = autoscalingv2::MetricStatus {
+            type_: "External".to_string(),
+            external: Some(autoscalingv2::ExternalMetricStatus {
+                metric: source.metric.clone(),
+                current,
+            }),
+            ..default()
+        };
+        Ok(Proposal {
+            replicas,
+            metric,
+            timestamp,
+            status,
+        })
+    }
+
+    /// Replicas for average utilization of requested resource, with the utilization
+    /// and the raw average usage
+    ///
+    fn utilization_replicas(
+        &self,
+        mut metrics: PodMetricsInfo,
+        requests: &BTreeMap<String, i64>,
+        current: i32,
+        target: i32,
+        resource: &str,
+        observation: &Observation,
+    ) -> Result<(i32, i32, i64), HpaError> {
+        if observation.pods.is_empty() {
+            return Err(HpaError::NoPods);
+        }
+        let groups = self.group_pods(&observation.pods, &metrics, Some(resource));
+        for name in groups.ignored.iter().chain(&groups.unready) {
+            metrics.remove(name);
+        }
+        if metrics.is_empty() {
+            return Err(HpaError::NoPodMetrics);
+        }
+
+        let (ratio, utilization, raw) = utilization_ratio(&metrics, requests, target)?;
+        let scale_up_with_unready = !groups.unready.is_empty() && ratio > 1.0;
+        if !scale_up_with_unready && groups.missing.is_empty() {
+            if self.within_tolerance(ratio) {
+                return Ok((current, utilization, raw));
+            }
+            let replicas = (ratio * groups.ready as f64).ceil() as i32;
+            return Ok((replicas, utilization, raw));
+        }
+
+        if ratio < 1.0 {
+            // on scale down missing pods are assumed to use all of their request,
+            // or the target for targets above 100%
+            let fallback = i64::from(target.max(100));
+            for name in &groups.missing {
+                let request = requests.get(name).copied().unwrap_or_default();
+                metrics.insert(name.clone(), PodMetric::assumed(request * fallback / 100));
+            }
+        } else if ratio > 1.0 {
+            // on scale up missing pods are assumed to use nothing
+            for name in &groups.missing {
+                metrics.insert(name.clone(), PodMetric::assumed(0));
+            }
+        }
+        if scale_up_with_unready {
+            for name in &groups.unready {
+                metrics.insert(name.clone(), PodMetric::assumed(0));
+            }
+        }
+
+        let (new_ratio, _, _) = utilization_ratio(&metrics, requests, target)?;
+        let replicas = self.rebalanced_replicas(current, ratio, new_ratio, metrics.len());
+        Ok((replicas, utilization, raw))
+    }
+
+    /// Replicas for average value of a metric per pod, with the average value
+    ///
+    fn plain_replicas(
+        &self,
+        mut metrics: PodMetricsInfo,
+        current: i32,
+        target: i64,
+        resource: Option<&str>,
+        observation: &Observation,
+    ) -> Result<(i32, i64), HpaError> {
+        if observation.pods.is_empty() {
+            return Err(HpaError::NoPods);
+        }
+        let groups = self.group_pods(&observation.pods, &metrics, resource);
+        for name in groups.ignored.iter().chain(&groups.unready) {
+            metrics.remove(name);
+        }
+        if metrics.is_empty() {
+            return Err(HpaError::NoPodMetrics);
+        }
+
+        let (ratio, usage) = usage_ratio(&metrics, target);
+        let scale_up_with_unready = !groups.unready.is_empty() && ratio > 1.0;
+        if !scale_up_with_unready && groups.missing.is_empty() {
+            if self.within_tolerance(ratio) {
+                return Ok((current, usage));
+            }
+            let replicas = (ratio * groups.ready as f64).ceil() as i32;
+            return Ok((replicas, usage));
+        }
+
+        // on scale down missing pods are assumed to be exactly at the target,
+        // on scale up to use nothing
+        let fallback = if ratio < 1.0 { target } else { 0 };
+        for name in &groups.missing {
+            metrics.insert(name.clone(), PodMetric::assumed(fallback));
+        }
+        if scale_up_with_unready {
+            for name in &groups.unready {
+                metrics.insert(name.clone(), PodMetric::assumed(0));
+            }
+        }
+
+        let (new_ratio, _) = usage_ratio(&metrics, target);
+        let replicas = self.rebalanced_replicas(current, ratio, new_ratio, metrics.len());
+        Ok((replicas, usage))
+    }
+
+    /// Replicas after the assumed values of missing and unready pods were taken into account,
+    /// no scaling happens when the assumptions change the direction of scaling
+    ///
+    fn rebalanced_replicas(&self, current: i32, ratio: f64, new_ratio: f64, pods: usize) -> i32 {
+        if self.within_tolerance(new_ratio)
+            || (ratio < 1.0 && new_ratio > 1.0)
+            || (ratio > 1.0 && new_ratio < 1.0)
+        {
+            return current;
+        }
+        let replicas = (new_ratio * pods as f64).ceil() as i32;
+        if (new_ratio < 1.0 && replicas > current) || (new_ratio > 1.0 && replicas < current) {
+            current
+        } else {
+            replicas
+        }
+    }
+
+    /// Replicas for ratio of total value to target, scaling the ready pods
+    ///
+    fn usage_ratio_replicas(&self, ratio: f64, observation: &Observation) -> Result<i32, HpaError> {
+        let current = observation.spec_replicas;
+        if current == 0 {
+            return Ok(ratio.ceil() as i32);
+        }
+        if self.within_tolerance(ratio) {
+            return Ok(current);
+        }
+        if observation.pods.is_empty() {
+            return Err(HpaError::NoPods);
+        }
+        let ready = observation
+            .pods
+            .iter()
+            .filter(|pod| phase(pod) == Some("Running") && is_ready(pod))
+            .count();
+        Ok((ratio * ready as f64).ceil() as i32)
+    }
+
+    /// Replicas for total value divided among the pods, with the average value
+    ///
+    fn per_pod_replicas(&self, usage: i64, target: i64, observation: &Observation) -> (i32, i64) {
+        let current = observation.status_replicas;
+        let ratio = usage as f64 / (target as f64 * f64::from(current));
+        let replicas = if self.within_tolerance(ratio) {
+            current
+        } else {
+            (usage as f64 / target as f64).ceil() as i32
+        };
+        let average = (usage as f64 / f64::from(current)).ceil() as i64;
+        (replicas, average)
+    }
+
+    fn group_pods(
+        &self,
+        pods: &[corev1::Pod],
+        metrics: &PodMetricsInfo,
+        resource: Option<&str>,
+    ) -> PodGroups {
+        let now = self.now.unwrap_or_else(|| now().0);
+        let mut groups = PodGroups::default();
+        for pod in pods {
+            let name = pod.metadata.name.clone().unwrap_or_default();
+            if pod.metadata.deletion_timestamp.is_some() || phase(pod) == Some("Failed") {
+                groups.ignored.insert(name);
+                continue;
+            }
+            if phase(pod) == Some("Pending") {
+                groups.unready.insert(name);
+                continue;
+            }
+            let Some(metric) = metrics.get(&name) else {
+                groups.missing.insert(name);
+                continue;
+            };
+
+            if resource == Some("cpu") {
+                let start_time = pod
+                    .status
+                    .as_ref()
+                    .and_then(|status| status.start_time.as_ref());
+                let unready = match (ready_condition(pod), start_time) {
+                    (Some(condition), Some(start_time)) => {
+                        let transition = condition
+                            .last_transition_time
+                            .as_ref()
+                            .map(|time| time.0)
+                            .unwrap_or_default();
+                        let not_ready = condition.status == "False";
+                        if start_time.0 + delta(self.cpu_initialization_period) > now {
+                            // still initializing, the sample must be collected while ready
+                            not_ready || metric.timestamp < transition + delta(metric.window)
+                        } else {
+                            // unready pods which have never been ready
+                            not_ready
+                                && start_time.0 + delta(self.initial_readiness_delay) > transition
+                        }
+                    }
+                    _ => true,
+                };
+                if unready {
+                    groups.unready.insert(name);
+                    continue;
+                }
+            }
+            groups.ready += 1;
+        }
+        groups
+    }
+
+    fn within_tolerance(&self, ratio: f64) -> bool {
+        (1.0 - ratio).abs() <= self.tolerance
+    }
+}
+
+/// Milli usage of `resource` by the pods (or only by their `container`) with the time of the first sample
+///
+fn resource_metrics(
+    observation: &Observation,
+    resource: &str,
+    container: Option<&str>,
+) -> Result<(PodMetricsInfo, Option<DateTime<Utc>>), HpaError> {
+    let mut metrics = PodMetricsInfo::new();
+    for pod in &observation.pod_metrics {
+        let containers = pod
+            .containers
+            .iter()
+            .filter(|c| container.is_none_or(|container| c.name == container))
+            .collect::<Vec<_>>();
+        if containers.is_empty() {
+            let Some(container) = container else {
+                // pods without any containers are treated as missing
+                continue;
+            };
+            return Err(HpaError::MissingContainer {
+                container: container.to_string(),
+                pod: format!(
+                    "{}/{}",
+                    pod.metadata.namespace.as_deref().unwrap_or_default(),
+                    pod.metadata.name.as_deref().unwrap_or_default()
+                ),
+            });
+        }
+        let mut value = 0;
+        for c in containers {
+            let usage = match resource {
+                "cpu" => &c.usage.cpu,
+                "memory" => &c.usage.memory,
+                other => return Err(HpaError::UnsupportedResource(other.to_string())),
+            };
+            value += milli(usage)?;
+        }
+        let metric = PodMetric {
+            timestamp: pod.timestamp.0,
+            window: pod.window,
+            value,
+        };
+        metrics.insert(pod.metadata.name.clone().unwrap_or_default(), metric);
+    }
+
+    if metrics.is_empty() {
+        let metric = match container {
+            Some(container) => format!("{resource} of container {container}"),
+            None => format!("{resource} resource"),
+        };
+        return Err(HpaError::MissingMetric(metric));
+    }
+    let timestamp = observation.pod_metrics.first().map(|pod| pod.timestamp.0);
+    Ok((metrics, timestamp))
+}
+
+/// Milli requests of `resource` by the pods (or only by their `container`)
+///
+/// Sidecars, i.e. init containers restarted always, are counted in.
+///
+fn pod_requests(
+    pods: &[corev1::Pod],
+    resource: &str,
+    container: Option<&str>,
+) -> Result<BTreeMap<String, i64>, HpaError> {
+    let mut requests = BTreeMap::new();
+    for pod in pods {
+        let name = pod.metadata.name.clone().unwrap_or_default();
+        let spec = pod.spec.as_ref();
+        let sidecars = spec
+            .and_then(|spec| spec.init_containers.as_deref())
+            .unwrap_or_default()
+            .iter()
+            .filter(|c| c.restart_policy.as_deref() == Some("Always"));
+        let containers = spec
+            .map(|spec| spec.containers.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .chain(sidecars)
+            .filter(|c| container.is_none_or(|container| c.name == container));
+
+        let mut sum = 0;
+        for c in containers {
+            let request = c
+                .resources
+                .as_ref()
+                .and_then(|resources| resources.requests.as_ref())
+                .and_then(|requests| requests.get(resource))
+                .ok_or_else(|| HpaError::MissingRequest {
+                    resource: resource.to_string(),
+                    container: c.name.clone(),
+                    pod: name.clone(),
+                })?;
+            sum += milli(request)?;
+        }
+        requests.insert(name, sum);
+    }
+    Ok(requests)
+}
+
+/// Ratio of utilization to target, the utilization in percents and the average usage
+///
+fn utilization_ratio(
+    metrics: &PodMetricsInfo,
+    requests: &BTreeMap<String, i64>,
+    target: i32,
+) -> Result<(f64, i32, i64), HpaError> {
+    let mut metrics_total = 0;
+    let mut requests_total = 0;
+    let mut entries = 0;
+    for (name, metric) in metrics {
+        if let Some(request) = requests.get(name) {
+            metrics_total += metric.value;
+            requests_total += request;
+            entries += 1;
+        }
+    }
+    if requests_total == 0 {
+        return Err(HpaError::NoMatchingMetrics);
+    }
+
+    let utilization = (metrics_total * 100 / requests_total) as i32;
+    let ratio = f64::from(utilization) / f64::from(target);
+    Ok((ratio, utilization, metrics_total / entries))
+}
+
+/// Ratio of the average value to target and the average value
+///
+fn usage_ratio(metrics: &PodMetricsInfo, target: i64) -> (f64, i64) {
+    let total = metrics.values().map(|metric| metric.value).sum::<i64>();
+    let usage = total / metrics.len() as i64;
+    (usage as f64 / target as f64, usage)
+}
+
+fn default_cpu_metric() -> autoscalingv2::MetricSpec {
+    autoscalingv2::MetricSpec {
+        type_: "Resource".to_string(),
+        resource: Some(autoscalingv2::ResourceMetricSource {
+            name: "cpu".to_string(),
+            target: autoscalingv2::MetricTarget {
+                type_: "Utilization".to_string(),
+                average_utilization: Some(DEFAULT_CPU_UTILIZATION),
+                ..default()
+            },
+        }),
+        ..default()
+    }
+}
+
+fn phase(pod: &corev1::Pod) -> Option<&str> {
+    pod.status.as_ref()?.phase.as_deref()
+}
+
+fn ready_condition(pod: &corev1::Pod) -> Option<&corev1::PodCondition> {
+    let conditions = pod.status.as_ref()?.conditions.as_ref()?;
+    conditions
+        .iter()
+        .find(|condition| condition.type_ == "Ready")
+}
+
+fn is_ready(pod: &corev1::Pod) -> bool {
+    ready_condition(pod).is_some_and(|condition| condition.status == "True")
+}
+
+fn seconds(window_seconds: Option<i64>) -> time::Duration {
+    time::Duration::from_secs(window_seconds.unwrap_or_default().max(0) as u64)
+}
+
+fn delta(duration: time::Duration) -> TimeDelta {
+    TimeDelta::from_std(duration).unwrap_or(TimeDelta::MAX)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,663 @@
+//! Replay of metrics through the scaling behavior of the HPA
+//!
+//! `Simulator` keeps the state the horizontal pod autoscaler controller keeps between
+//! reconciliations of an HPA: the past recommendations for the stabilization windows and the
+//! past scale events for the scaling policies. Every sample is evaluated at its own timestamp
+//! and the scale target is assumed to reach the desired replicas right away.
+//!
+
+use super::*;
+
+/// Default of `--horizontal-pod-autoscaler-downscale-stabilization`
+///
+pub const DEFAULT_DOWNSCALE_STABILIZATION: time::Duration = time::Duration::from_secs(300);
+
+/// Limit keeping the replicas from the stabilized recommendation,
+/// the reasons of the `ScalingLimited` condition
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    /// scale-up policies (or the legacy doubling rule) don't allow more replicas
+    ///
+    ScaleUp,
+    /// scale-down policies don't allow less replicas
+    ///
+    ScaleDown,
+    /// `maxReplicas` reached
+    ///
+    TooManyReplicas,
+    /// `minReplicas` reached
+    ///
+    TooFewReplicas,
+}
+
+impl Limit {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::ScaleUp => "ScaleUpLimit",
+            Self::ScaleDown => "ScaleDownLimit",
+            Self::TooManyReplicas => "TooManyReplicas",
+            Self::TooFewReplicas => "TooFewReplicas",
+        }
+    }
+}
+
+/// Single reconciliation of the HPA
+///
+#[derive(Debug)]
+pub struct Step {
+    pub timestamp: DateTime<Utc>,
+    /// replicas before the step
+    ///
+    pub current_replicas: i32,
+    /// recommendation of the metrics, no scaling happens when it failed
+    ///
+    pub recommendation: Result<Recommendation, HpaError>,
+    /// the metric recommendation after the stabilization windows,
+    /// `None` when the metrics were not consulted
+    ///
+    pub stabilized_replicas: Option<i32>,
+    /// replicas after the step
+    ///
+    pub replicas: i32,
+    /// limit applied to the stabilized recommendation
+    ///
+    pub limit: Option<Limit>,
+}
+
+/// Simulated horizontal pod autoscaler controller for a single HPA
+///
+#[derive(Clone, Debug)]
+pub struct Simulator {
+    spec: autoscalingv2::HorizontalPodAutoscalerSpec,
+    calculator: ReplicaCalculator,
+    downscale_stabilization: time::Duration,
+    replicas: i32,
+    recommendations: Vec<TimestampedRecommendation>,
+    scale_up_events: Vec<ScaleEvent>,
+    scale_down_events: Vec<ScaleEvent>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TimestampedRecommendation {
+    replicas: i32,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ScaleEvent {
+    change: i32,
+    timestamp: DateTime<Utc>,
+    outdated: bool,
+}
+
+/// Scaling rules with the defaults of the API filled in
+///
+#[derive(Debug)]
+struct Rules {
+    stabilization: TimeDelta,
+    select_policy: String,
+    policies: Vec<autoscalingv2::HPAScalingPolicy>,
+}
+
+impl Simulator {
+    /// Simulate HPA `spec` of scale target currently running `replicas`
+    ///
+    pub fn new(spec: autoscalingv2::HorizontalPodAutoscalerSpec, replicas: i32) -> Self {
+        Self {
+            spec,
+            calculator: default(),
+            downscale_stabilization: DEFAULT_DOWNSCALE_STABILIZATION,
+            replicas,
+            recommendations: Vec::new(),
+            scale_up_events: Vec::new(),
+            scale_down_events: Vec::new(),
+        }
+    }
+
+    /// Calculate the recommendations with `calculator`, its time is set to the sample timestamps
+    ///
+    pub fn calculator(mut self, calculator: ReplicaCalculator) -> Self {
+        self.calculator = calculator;
+        self
+    }
+
+    /// Scale-down stabilization window used when the HPA `behavior` doesn't set one
+    ///
+    pub fn downscale_stabilization(mut self, window: time::Duration) -> Self {
+        self.downscale_stabilization = window;
+        self
+    }
+
+    /// Current replicas of the scale target
+    ///
+    pub fn replicas(&self) -> i32 {
+        self.replicas
+    }
+
+    /// Reconcile the HPA with `observation` made at `timestamp`
+    ///
+    /// The replicas of the scale target are replaced by the simulated ones,
+    /// the pods and metric values are used as observed.
+    ///
+    pub fn step(&mut self, timestamp: DateTime<Utc>, mut observation: Observation) -> Step {
+        let current = self.replicas;
+        observation.spec_replicas = current;
+        observation.status_replicas = current;
+
+        let recommendation = self
+            .calculator
+            .clone()
+            .at(timestamp)
+            .recommend(&self.spec, &observation);
+        let (stabilized_replicas, replicas, limit) = match &recommendation {
+            Ok(recommendation) if recommendation.metric.is_some() => {
+                let (stabilized, replicas, limit) =
+                    self.normalize(timestamp, current, recommendation.metric_replicas);
+                (Some(stabilized), replicas, limit)
+            }
+            Ok(recommendation) => (None, recommendation.replicas, None),
+            Err(_) => (None, current, None),
+        };
+
+        if replicas != current {
+            self.store_scale_event(timestamp, current, replicas);
+            self.replicas = replicas;
+        }
+        Step {
+            timestamp,
+            current_replicas: current,
+            recommendation,
+            stabilized_replicas,
+            replicas,
+            limit,
+        }
+    }
+
+    /// Replay timestamped observations in order, returning the timeline of the replicas
+    ///
+    pub fn run(
+        &mut self,
+        samples: impl IntoIterator<Item = (DateTime<Utc>, Observation)>,
+    ) -> Vec<Step> {
+        samples
+            .into_iter()
+            .map(|(timestamp, observation)| self.step(timestamp, observation))
+            .collect()
+    }
+
+    /// Stabilize and limit the `desired` replicas, returning the stabilized and the final replicas
+    ///
+    fn normalize(
+        &mut self,
+        timestamp: DateTime<Utc>,
+        current: i32,
+        desired: i32,
+    ) -> (i32, i32, Option<Limit>) {
+        let min = self.spec.min_replicas.unwrap_or(1);
+        let max = self.spec.max_replicas;
+
+        let Some((scale_up, scale_down)) = self.rules() else {
+            let stabilized = self.stabilize(timestamp, desired);
+            // no more than double the replicas at once
+            let scale_up_limit = (2 * current).max(4);
+            let (maximum, limit) = if max > scale_up_limit {
+                (scale_up_limit, Limit::ScaleUp)
+            } else {
+                (max, Limit::TooManyReplicas)
+            };
+            return if stabilized < min {
+                (stabilized, min, Some(Limit::TooFewReplicas))
+            } else if stabilized > maximum {
+                (stabilized, maximum, Some(limit))
+            } else {
+                (stabilized, stabilized, None)
+            };
+        };
+
+        let stabilized =
+            self.stabilize_with_behavior(timestamp, current, desired, &scale_up, &scale_down);
+        if stabilized > current {
+            let scale_up_limit = self
+                .scale_up_limit(timestamp, current, &scale_up)
+                .max(current);
+            let (maximum, limit) = if max > scale_up_limit {
+                (scale_up_limit, Limit::ScaleUp)
+            } else {
+                (max, Limit::TooManyReplicas)
+            };
+            if stabilized > maximum {
+                return (stabilized, maximum, Some(limit));
+            }
+        } else if stabilized < current {
+            let scale_down_limit = self
+                .scale_down_limit(timestamp, current, &scale_down)
+                .min(current);
+            let (minimum, limit) = if min < scale_down_limit {
+                (scale_down_limit, Limit::ScaleDown)
+            } else {
+                (min, Limit::TooFewReplicas)
+            };
+            if stabilized < minimum {
+                return (stabilized, minimum, Some(limit));
+            }
+        }
+        (stabilized, stabilized, None)
+    }
+
+    /// Scale-up and scale-down rules of the HPA `behavior`, `None` for the legacy behavior
+    ///
+    fn rules(&self) -> Option<(Rules, Rules)> {
+        let behavior = self.spec.behavior.as_ref()?;
+        let scale_up = Rules {
+            stabilization: TimeDelta::zero(),
+            select_policy: "Max".to_string(),
+            policies: vec![policy("Pods", 4, 15), policy("Percent", 100, 15)],
+        };
+        let scale_down = Rules {
+            stabilization: delta(self.downscale_stabilization),
+            select_policy: "Max".to_string(),
+            policies: vec![policy("Percent", 100, 15)],
+        };
+        Some((
+            scale_up.overridden(behavior.scale_up.as_ref()),
+            scale_down.overridden(behavior.scale_down.as_ref()),
+        ))
+    }
+
+    /// The largest recommendation within the downscale stabilization window
+    ///
+    fn stabilize(&mut self, timestamp: DateTime<Utc>, desired: i32) -> i32 {
+        let cutoff = timestamp - delta(self.downscale_stabilization);
+        let mut stabilized = desired;
+        let mut outdated = None;
+        for (index, recommendation) in self.recommendations.iter().enumerate() {
+            if recommendation.timestamp < cutoff {
+                outdated = Some(index);
+            } else {
+                stabilized = stabilized.max(recommendation.replicas);
+            }
+        }
+        self.record(outdated, timestamp, desired);
+        stabilized
+    }
+
+    /// The current replicas kept within the smallest recommendation of the scale-up window
+    /// and the largest recommendation of the scale-down window
+    ///
+    fn stabilize_with_behavior(
+        &mut self,
+        timestamp: DateTime<Utc>,
+        current: i32,
+        desired: i32,
+        scale_up: &Rules,
+        scale_down: &Rules,
+    ) -> i32 {
+        let up_cutoff = timestamp - scale_up.stabilization;
+        let down_cutoff = timestamp - scale_down.stabilization;
+        let mut up = desired;
+        let mut down = desired;
+        let mut outdated = None;
+        for (index, recommendation) in self.recommendations.iter().enumerate() {
+            if recommendation.timestamp > up_cutoff {
+                up = up.min(recommendation.replicas);
+            }
+            if recommendation.timestamp > down_cutoff {
+                down = down.max(recommendation.replicas);
+            }
+            if recommendation.timestamp < up_cutoff && recommendation.timestamp < down_cutoff {
+                outdated = Some(index);
+            }
+        }
+        self.record(outdated, timestamp, desired);
+        current.max(up).min(down)
+    }
+
+    /// Remember the recommendation, reusing the slot of an outdated one
+    ///
+    fn record(&mut self, outdated: Option<usize>, timestamp: DateTime<Utc>, replicas: i32) {
+        let recommendation = TimestampedRecommendation {
+            replicas,
+            timestamp,
+        };
+        match outdated {
+            Some(index) => self.recommendations[index] = recommendation,
+            None => self.recommendations.push(recommendation),
+        }
+    }
+
+    fn scale_up_limit(&self, timestamp: DateTime<Utc>, current: i32, rules: &Rules) -> i32 {
+        let (mut limit, select): (i32, fn(i32, i32) -> i32) = match rules.select_policy.as_str() {
+            "Disabled" => return current,
+            "Min" => (i32::MAX, i32::min),
+            _ => (i32::MIN, i32::max),
+        };
+        for policy in &rules.policies {
+            let start = self.period_start_replicas(timestamp, current, policy);
+            let proposed = match policy.type_.as_str() {
+                "Pods" => start + policy.value,
+                // rounded up, otherwise small targets would never scale up
+                "Percent" => {
+                    (f64::from(start) * (1.0 + f64::from(policy.value) / 100.0)).ceil() as i32
+                }
+                _ => continue,
+            };
+            limit = select(limit, proposed);
+        }
+        limit
+    }
+
+    fn scale_down_limit(&self, timestamp: DateTime<Utc>, current: i32, rules: &Rules) -> i32 {
+        let (mut limit, select): (i32, fn(i32, i32) -> i32) = match rules.select_policy.as_str() {
+            "Disabled" => return current,
+            "Min" => (i32::MIN, i32::max),
+            _ => (i32::MAX, i32::min),
+        };
+        for policy in &rules.policies {
+            let start = self.period_start_replicas(timestamp, current, policy);
+            let proposed = match policy.type_.as_str() {
+                "Pods" => start - policy.value,
+                "Percent" => (f64::from(start) * (1.0 - f64::from(policy.value) / 100.0)) as i32,
+                _ => continue,
+            };
+            limit = select(limit, proposed);
+        }
+        limit
+    }
+
+    /// Replicas at the start of the period of `policy`
+    ///
+    fn period_start_replicas(
+        &self,
+        timestamp: DateTime<Utc>,
+        current: i32,
+        policy: &autoscalingv2::HPAScalingPolicy,
+    ) -> i32 {
+        let cutoff = timestamp - TimeDelta::seconds(policy.period_seconds.into());
+        let change = |events: &[ScaleEvent]| {
+            events
+                .iter()
+                .filter(|event| event.timestamp > cutoff)
+                .map(|event| event.change)
+                .sum::<i32>()
+        };
+        current - change(&self.scale_up_events) + change(&self.scale_down_events)
+    }
+
+    /// Remember the scale event for the scaling policies, reusing the slot of an event
+    /// older than the longest policy period
+    ///
+    fn store_scale_event(&mut self, timestamp: DateTime<Utc>, previous: i32, replicas: i32) {
+        let Some((scale_up, scale_down)) = self.rules() else {
+            return;
+        };
+        let (events, rules) = if replicas > previous {
+            (&mut self.scale_up_events, scale_up)
+        } else {
+            (&mut self.scale_down_events, scale_down)
+        };
+
+        let longest = rules
+            .policies
+            .iter()
+            .map(|policy| policy.period_seconds)
+            .max()
+            .unwrap_or_default();
+        let cutoff = timestamp - TimeDelta::seconds(longest.into());
+        for event in events.iter_mut() {
+            if event.timestamp < cutoff {
+                event.outdated = true;
+            }
+        }
+
+        let event = ScaleEvent {
+            change: (replicas - previous).abs(),
+            timestamp,
+            outdated: false,
+        };
+        match events.iter_mut().find(|event| event.outdated) {
+            Some(outdated) => *outdated = event,
+            None => events.push(event),
+        }
+    }
+}
+
+impl Rules {
+    /// Replace the defaults with the values set in `rules`
+    ///
+    fn overridden(mut self, rules: Option<&autoscalingv2::HPAScalingRules>) -> Self {
+        if let Some(rules) = rules {
+            if let Some(seconds) = rules.stabilization_window_seconds {
+                self.stabilization = TimeDelta::seconds(seconds.into());
+            }
+            if let Some(select_policy) = &rules.select_policy {
+                self.select_policy.clone_from(select_policy);
+            }
+            if let Some(policies) = &rules.policies {
+                self.policies.clone_from(policies);
+            }
+        }
+        self
+    }
+}
+
+fn policy(type_: &str, value: i32, period_seconds: i32) -> autoscalingv2::HPAScalingPolicy {
+    autoscalingv2::HPAScalingPolicy {
+        type_: type_.to_string(),
+        value,
+        period_seconds,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_710_234_975 + seconds, 0).unwrap()
+    }
+
+    /// Observation of a queue worth `load` replicas
+    ///
+    fn load(load: i32) -> Observation {
+        let value = DynamicExternalMetricValue::builder("queue_messages")
+            .value(load)
+            .build();
+        Observation::default().external_metrics([value])
+    }
+
+    /// Observation of three ready pods requesting one CPU each, each using `cpu`
+    ///
+    fn cpu(cpu: &str) -> Observation {
+        let started = Some(metav1::Time(at(-3600)));
+        let names = ["web-0", "web-1", "web-2"];
+        let pods = names.map(|name| corev1::Pod {
+            metadata: metav1::ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some("default".to_string()),
+                ..default()
+            },
+            spec: Some(corev1::PodSpec {
+                containers: vec![corev1::Container {
+                    name: "app".to_string(),
+                    resources: Some(corev1::ResourceRequirements {
+                        requests: Some(
+                            [("cpu".to_string(), resource::Quantity("1".to_string()))].into(),
+                        ),
+                        ..default()
+                    }),
+                    ..default()
+                }],
+                ..default()
+            }),
+            status: Some(corev1::PodStatus {
+                phase: Some("Running".to_string()),
+                start_time: started.clone(),
+                conditions: Some(vec![corev1::PodCondition {
+                    type_: "Ready".to_string(),
+                    status: "True".to_string(),
+                    last_transition_time: started.clone(),
+                    ..default()
+                }]),
+                ..default()
+            }),
+        });
+        let metrics = names.map(|name| fixtures::pod("default", name, &[("app", cpu, "64Mi")]));
+        Observation::default().pods(pods).pod_metrics(metrics)
+    }
+
+    fn hpa(
+        behavior: Option<autoscalingv2::HorizontalPodAutoscalerBehavior>,
+    ) -> autoscalingv2::HorizontalPodAutoscalerSpec {
+        let metric = autoscalingv2::MetricSpec {
+            type_: "External".to_string(),
+            external: Some(autoscalingv2::ExternalMetricSource {
+                metric: autoscalingv2::MetricIdentifier {
+                    name: "queue_messages".to_string(),
+                    selector: None,
+                },
+                target: autoscalingv2::MetricTarget {
+                    type_: "AverageValue".to_string(),
+                    average_value: Some(resource::Quantity("1".to_string())),
+                    ..default()
+                },
+            }),
+            ..default()
+        };
+        autoscalingv2::HorizontalPodAutoscalerSpec {
+            min_replicas: Some(1),
+            max_replicas: 20,
+            metrics: Some(vec![metric]),
+            behavior,
+            ..default()
+        }
+    }
+
+    fn rules(
+        stabilization: i32,
+        select_policy: &str,
+        policies: Vec<autoscalingv2::HPAScalingPolicy>,
+    ) -> Option<autoscalingv2::HPAScalingRules> {
+        Some(autoscalingv2::HPAScalingRules {
+            stabilization_window_seconds: Some(stabilization),
+            select_policy: Some(select_policy.to_string()),
+            policies: Some(policies),
+            ..default()
+        })
+    }
+
+    fn replicas(steps: &[Step]) -> Vec<i32> {
+        steps.iter().map(|step| step.replicas).collect()
+    }
+
+    #[test]
+    fn legacy_behavior() {
+        let mut simulator = Simulator::new(hpa(None), 1);
+        let steps = simulator.run([
+            (at(0), load(10)),
+            (at(15), load(10)),
+            (at(30), load(10)),
+            (at(60), load(2)),
+            (at(400), load(2)),
+        ]);
+        // doubling at most, scaling down only after five minutes
+        assert_eq!(replicas(&steps), [4, 8, 10, 10, 2]);
+        assert_eq!(steps[0].limit, Some(Limit::ScaleUp));
+        assert_eq!(steps[3].stabilized_replicas, Some(10));
+        assert_eq!(simulator.replicas(), 2);
+    }
+
+    #[test]
+    fn resource_utilization() {
+        let metric = autoscalingv2::MetricSpec {
+            type_: "Resource".to_string(),
+            resource: Some(autoscalingv2::ResourceMetricSource {
+                name: "cpu".to_string(),
+                target: autoscalingv2::MetricTarget {
+                    type_: "Utilization".to_string(),
+                    average_utilization: Some(50),
+                    ..default()
+                },
+            }),
+            ..default()
+        };
+        let spec = autoscalingv2::HorizontalPodAutoscalerSpec {
+            metrics: Some(vec![metric]),
+            ..hpa(None)
+        };
+        let mut simulator = Simulator::new(spec, 3);
+        let steps = simulator.run([
+            (at(0), cpu("900m")),
+            (at(15), cpu("900m")),
+            (at(30), cpu("300m")),
+            (at(400), cpu("300m")),
+        ]);
+        // the recorded three pods are used as observed, even after scaling to six replicas
+        assert_eq!(replicas(&steps), [6, 6, 6, 2]);
+        let recommendation = steps[1].recommendation.as_ref().unwrap();
+        assert_eq!(recommendation.metric_replicas, 6);
+        assert_eq!(steps[2].stabilized_replicas, Some(6));
+    }
+
+    #[test]
+    fn scaling_policies() {
+        let behavior = autoscalingv2::HorizontalPodAutoscalerBehavior {
+            scale_up: rules(0, "Max", vec![policy("Pods", 2, 60)]),
+            scale_down: rules(60, "Max", vec![policy("Percent", 50, 60)]),
+        };
+        let mut simulator = Simulator::new(hpa(Some(behavior)), 2);
+        let steps = simulator.run([
+            (at(0), load(10)),
+            (at(30), load(10)),
+            (at(61), load(10)),
+            (at(120), load(1)),
+            (at(200), load(1)),
+            (at(230), load(1)),
+            (at(261), load(1)),
+        ]);
+        assert_eq!(replicas(&steps), [4, 4, 6, 6, 3, 3, 1]);
+        assert_eq!(
+            steps[1].limit.map(|limit| limit.reason()),
+            Some("ScaleUpLimit")
+        );
+        assert_eq!(steps[3].stabilized_replicas, Some(6));
+        assert_eq!(steps[4].limit, Some(Limit::ScaleDown));
+        assert_eq!(steps[6].limit, None);
+    }
+
+    #[test]
+    fn select_policy() {
+        let policies = vec![policy("Pods", 1, 60), policy("Percent", 100, 60)];
+        let behavior = |select_policy| autoscalingv2::HorizontalPodAutoscalerBehavior {
+            scale_up: rules(0, select_policy, policies.clone()),
+            scale_down: rules(0, "Disabled", vec![]),
+        };
+
+        let mut simulator = Simulator::new(hpa(Some(behavior("Max"))), 3);
+        assert_eq!(simulator.step(at(0), load(10)).replicas, 6);
+        let mut simulator = Simulator::new(hpa(Some(behavior("Min"))), 3);
+        assert_eq!(simulator.step(at(0), load(10)).replicas, 4);
+
+        let mut simulator = Simulator::new(hpa(Some(behavior("Disabled"))), 3);
+        let step = simulator.step(at(0), load(10));
+        assert_eq!(step.replicas, 3);
+        assert_eq!(step.limit, Some(Limit::ScaleUp));
+        let step = simulator.step(at(15), load(1));
+        assert_eq!(step.replicas, 3);
+        assert_eq!(step.limit, Some(Limit::ScaleDown));
+    }
+
+    #[test]
+    fn failed_recommendation() {
+        let mut simulator = Simulator::new(hpa(Some(default())), 3);
+        let step = simulator.step(at(0), Observation::default());
+        assert!(step.recommendation.is_err());
+        assert_eq!(step.replicas, 3);
+
+        // out of bounds replicas are corrected without consulting the metrics
+        let mut simulator = Simulator::new(hpa(Some(default())), 30);
+        let step = simulator.step(at(0), Observation::default());
+        assert_eq!(step.replicas, 20);
+        assert_eq!(step.stabilized_replicas, None);
+    }
+}
@@ -0,0 +1,451 @@
+use super::*;
+
+use k8s::api::networking::v1 as networkingv1;
+use v1beta1::{Container, Usage};
+
+fn now() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_710_234_975, 0).unwrap()
+}
+
+fn ago(seconds: i64) -> metav1::Time {
+    metav1::Time(now() - TimeDelta::seconds(seconds))
+}
+
+fn calculator() -> ReplicaCalculator {
+    ReplicaCalculator::new().at(now())
+}
+
+/// Running pod requesting one CPU, with readiness `ready` since `transition` seconds ago
+///
+fn pod(name: &str, ready: &str, started: i64, transition: i64) -> corev1::Pod {
+    let container = corev1::Container {
+        name: "app".to_string(),
+        resources: Some(corev1::ResourceRequirements {
+            requests: Some([("cpu".to_string(), resource::Quantity("1".to_string()))].into()),
+            ..default()
+        }),
+        ..default()
+    };
+    let condition = corev1::PodCondition {
+        type_: "Ready".to_string(),
+        status: ready.to_string(),
+        last_transition_time: Some(ago(transition)),
+        ..default()
+    };
+    corev1::Pod {
+        metadata: metav1::ObjectMeta {
+            name: Some(name.to_string()),
+            namespace: Some("default".to_string()),
+            ..default()
+        },
+        spec: Some(corev1::PodSpec {
+            containers: vec![container],
+            ..default()
+        }),
+        status: Some(corev1::PodStatus {
+            phase: Some("Running".to_string()),
+            start_time: Some(ago(started)),
+            conditions: Some(vec![condition]),
+            ..default()
+        }),
+    }
+}
+
+fn ready_pods(count: usize) -> Vec<corev1::Pod> {
+    (0..count)
+        .map(|i| pod(&format!("web-{i}"), "True", 600, 540))
+        .collect()
+}
+
+fn usage(cpu: &[&str]) -> Vec<PodMetrics> {
+    cpu.iter()
+        .enumerate()
+        .map(|(i, cpu)| PodMetrics {
+            metadata: metav1::ObjectMeta {
+                name: Some(format!("web-{i}")),
+                namespace: Some("default".to_string()),
+                ..default()
+            },
+            containers: vec![Container {
+                name: "app".to_string(),
+                usage: Usage {
+                    cpu: resource::Quantity(cpu.to_string()),
+                    memory: resource::Quantity("64Mi".to_string()),
+                },
+            }],
+            timestamp: ago(10),
+            window: time::Duration::from_secs(30),
+        })
+        .collect()
+}
+
+fn hpa(metrics: Vec<autoscalingv2::MetricSpec>) -> autoscalingv2::HorizontalPodAutoscalerSpec {
+    autoscalingv2::HorizontalPodAutoscalerSpec {
+        min_replicas: Some(1),
+        max_replicas: 10,
+        metrics: Some(metrics),
+        ..default()
+    }
+}
+
+fn cpu_utilization(target: i32) -> autoscalingv2::MetricSpec {
+    autoscalingv2::MetricSpec {
+        type_: "Resource".to_string(),
+        resource: Some(autoscalingv2::ResourceMetricSource {
+            name: "cpu".to_string(),
+            target: autoscalingv2::MetricTarget {
+                type_: "Utilization".to_string(),
+                average_utilization: Some(target),
+                ..default()
+            },
+        }),
+        ..default()
+    }
+}
+
+fn pods_metric(name: &str, target: &str) -> autoscalingv2::MetricSpec {
+    autoscalingv2::MetricSpec {
+        type_: "Pods".to_string(),
+        pods: Some(autoscalingv2::PodsMetricSource {
+            metric: autoscalingv2::MetricIdentifier {
+                name: name.to_string(),
+                selector: None,
+            },
+            target: autoscalingv2::MetricTarget {
+                type_: "AverageValue".to_string(),
+                average_value: Some(resource::Quantity(target.to_string())),
+                ..default()
+            },
+        }),
+        ..default()
+    }
+}
+
+fn utilization(recommendation: &Recommendation) -> Option<i32> {
+    let status = recommendation.statuses[0].resource.as_ref()?;
+    status.current.average_utilization
+}
+
+#[test]
+fn scale_up_utilization() {
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .pod_metrics(usage(&["300m", "500m", "700m"]));
+    let recommendation = calculator()
+        .recommend(&hpa(vec![cpu_utilization(30)]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 5);
+    assert_eq!(
+        recommendation.metric.as_deref(),
+        Some("cpu resource utilization (percentage of request)")
+    );
+    assert_eq!(recommendation.timestamp, Some(ago(10).0));
+    let current = &recommendation.statuses[0]
+        .resource
+        .as_ref()
+        .unwrap()
+        .current;
+    assert_eq!(current.average_utilization, Some(50));
+    assert_eq!(
+        current.average_value,
+        Some(resource::Quantity("500m".to_string()))
+    );
+}
+
+#[test]
+fn tolerance() {
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .pod_metrics(usage(&["310m", "320m", "330m"]));
+    let spec = hpa(vec![cpu_utilization(30)]);
+    let recommendation = calculator().recommend(&spec, &observation).unwrap();
+    assert_eq!(recommendation.replicas, 3);
+    assert_eq!(utilization(&recommendation), Some(32));
+
+    let recommendation = calculator()
+        .tolerance(0.0)
+        .recommend(&spec, &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 4);
+}
+
+#[test]
+fn unready_pods_scale_up() {
+    let mut pods = ready_pods(3);
+    // started ten minutes ago and never ready
+    pods[0] = pod("web-0", "False", 600, 600);
+    let observation = Observation::new(3)
+        .pods(pods)
+        .pod_metrics(usage(&["300m", "500m", "700m"]));
+    let recommendation = calculator()
+        .recommend(&hpa(vec![cpu_utilization(30)]), &observation)
+        .unwrap();
+    // the unready pod is assumed to use nothing
+    assert_eq!(recommendation.replicas, 4);
+    assert_eq!(utilization(&recommendation), Some(60));
+}
+
+#[test]
+fn missing_pods_scale_down() {
+    let observation = Observation::new(4)
+        .pods(ready_pods(4))
+        .pod_metrics(usage(&["100m", "100m", "100m"]));
+    let recommendation = calculator()
+        .recommend(&hpa(vec![cpu_utilization(50)]), &observation)
+        .unwrap();
+    // the pod without metrics is assumed to use all of its request
+    assert_eq!(recommendation.replicas, 3);
+    assert_eq!(utilization(&recommendation), Some(10));
+}
+
+#[test]
+fn pods_metric_average_value() {
+    let values = [20, 10, 30].into_iter().enumerate().map(|(i, value)| {
+        MetricValue::<corev1::Pod>::builder("qps")
+            .object("default", format!("web-{i}"))
+            .value(value)
+            .build()
+    });
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .custom_metrics(values);
+    let recommendation = calculator()
+        .recommend(&hpa(vec![pods_metric("qps", "15")]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 4);
+    assert_eq!(recommendation.metric.as_deref(), Some("pods metric qps"));
+    let current = &recommendation.statuses[0].pods.as_ref().unwrap().current;
+    assert_eq!(
+        current.average_value,
+        Some(resource::Quantity("20".to_string()))
+    );
+}
+
+#[test]
+fn object_and_external_metrics() {
+    let object = autoscalingv2::MetricSpec {
+        type_: "Object".to_string(),
+        object: Some(autoscalingv2::ObjectMetricSource {
+            described_object: autoscalingv2::CrossVersionObjectReference {
+                api_version: Some("networking.k8s.io/v1".to_string()),
+                kind: "Ingress".to_string(),
+                name: "main".to_string(),
+            },
+            metric: autoscalingv2::MetricIdentifier {
+                name: "requests".to_string(),
+                selector: None,
+            },
+            target: autoscalingv2::MetricTarget {
+                type_: "Value".to_string(),
+                value: Some(resource::Quantity("10".to_string())),
+                ..default()
+            },
+        }),
+        ..default()
+    };
+    let external = autoscalingv2::MetricSpec {
+        type_: "External".to_string(),
+        external: Some(autoscalingv2::ExternalMetricSource {
+            metric: autoscalingv2::MetricIdentifier {
+                name: "queue_messages".to_string(),
+                selector: Some(metav1::LabelSelector::parse("queue=jobs").unwrap()),
+            },
+            target: autoscalingv2::MetricTarget {
+                type_: "AverageValue".to_string(),
+                average_value: Some(resource::Quantity("2150m".to_string())),
+                ..default()
+            },
+        }),
+        ..default()
+    };
+
+    let requests = MetricValue::<networkingv1::Ingress>::builder("requests")
+        .object("default", "main")
+        .value(20)
+        .build();
+    let messages = [("jobs", 8.6), ("mail", 100.0)].map(|(queue, value)| {
+        DynamicExternalMetricValue::builder("queue_messages")
+            .label("queue", queue)
+            .value(value)
+            .build()
+    });
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .custom_metrics([requests])
+        .external_metrics(messages);
+
+    // 20 / 10 of the three ready pods
+    let recommendation = calculator()
+        .recommend(&hpa(vec![object.clone()]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 6);
+    assert_eq!(
+        recommendation.metric.as_deref(),
+        Some("Ingress metric requests")
+    );
+
+    // 8.6 / 2.15 per pod
+    let recommendation = calculator()
+        .recommend(&hpa(vec![external.clone()]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 4);
+    assert_eq!(
+        recommendation.metric.as_deref(),
+        Some("external metric queue_messages(queue=jobs)")
+    );
+    let current = &recommendation.statuses[0]
+        .external
+        .as_ref()
+        .unwrap()
+        .current;
+    assert_eq!(
+        current.average_value,
+        Some(resource::Quantity("2867m".to_string()))
+    );
+
+    let recommendation = calculator()
+        .recommend(&hpa(vec![external, object]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 6);
+    assert_eq!(recommendation.statuses.len(), 2);
+}
+
+#[test]
+fn external_metric_without_labels() {
+    let external = autoscalingv2::MetricSpec {
+        type_: "External".to_string(),
+        external: Some(autoscalingv2::ExternalMetricSource {
+            metric: autoscalingv2::MetricIdentifier {
+                name: "s0-rabbitmq-worker_tasks".to_string(),
+                selector: Some(
+                    metav1::LabelSelector::parse("scaledobject.keda.sh/name=worker").unwrap(),
+                ),
+            },
+            target: autoscalingv2::MetricTarget {
+                type_: "AverageValue".to_string(),
+                average_value: Some(resource::Quantity("5".to_string())),
+                ..default()
+            },
+        }),
+        ..default()
+    };
+    // KEDA answers with `"metricLabels": null`
+    let tasks = DynamicExternalMetricValue::new("s0-rabbitmq-worker_tasks", 18.to_quantity());
+    assert!(tasks.metric_labels.is_empty());
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .external_metrics([tasks]);
+    let recommendation = calculator()
+        .recommend(&hpa(vec![external]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.replicas, 4);
+}
+
+#[test]
+fn pod_metrics_without_containers() {
+    let mut metrics = usage(&["300m", "500m", "700m"]);
+    metrics[2].containers.clear();
+    let observation = Observation::new(3).pods(ready_pods(3)).pod_metrics(metrics);
+    let recommendation = calculator()
+        .recommend(&hpa(vec![cpu_utilization(30)]), &observation)
+        .unwrap();
+    // web-2 is missing rather than idle
+    assert_eq!(utilization(&recommendation), Some(40));
+    assert_eq!(recommendation.replicas, 3);
+}
+
+#[test]
+fn container_resource() {
+    let spec = hpa(vec![autoscalingv2::MetricSpec {
+        type_: "ContainerResource".to_string(),
+        container_resource: Some(autoscalingv2::ContainerResourceMetricSource {
+            container: "app".to_string(),
+            name: "cpu".to_string(),
+            target: autoscalingv2::MetricTarget {
+                type_: "Utilization".to_string(),
+                average_utilization: Some(30),
+                ..default()
+            },
+        }),
+        ..default()
+    }]);
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .pod_metrics(usage(&["300m", "500m", "700m"]));
+    let recommendation = calculator().recommend(&spec, &observation).unwrap();
+    assert_eq!(recommendation.replicas, 5);
+    let status = recommendation.statuses[0].container_resource.as_ref();
+    assert_eq!(
+        status.and_then(|status| status.current.average_utilization),
+        Some(50)
+    );
+
+    let mut metrics = usage(&["300m", "500m", "700m"]);
+    metrics[1].containers[0].name = "sidecar".to_string();
+    let observation = Observation::new(3).pods(ready_pods(3)).pod_metrics(metrics);
+    let err = calculator().recommend(&spec, &observation).unwrap_err();
+    let HpaError::InvalidMetrics { first, .. } = err else {
+        panic!("unexpected error {err}");
+    };
+    assert_eq!(
+        first.to_string(),
+        "Container app not present in metrics for Pod default/web-1"
+    );
+}
+
+#[test]
+fn invalid_metrics() {
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .pod_metrics(usage(&["300m", "500m", "700m"]));
+
+    let spec = hpa(vec![cpu_utilization(30), pods_metric("qps", "15")]);
+    let recommendation = calculator().recommend(&spec, &observation).unwrap();
+    assert_eq!(recommendation.replicas, 5);
+    assert_eq!(recommendation.statuses[1], default());
+
+    let spec = hpa(vec![pods_metric("qps", "15")]);
+    let err = calculator().recommend(&spec, &observation).unwrap_err();
+    assert!(matches!(
+        err,
+        HpaError::InvalidMetrics {
+            invalid: 1,
+            total: 1,
+            ..
+        }
+    ));
+
+    // scaling down is not allowed while some metrics are invalid
+    let spec = hpa(vec![cpu_utilization(90), pods_metric("qps", "15")]);
+    let err = calculator().recommend(&spec, &observation).unwrap_err();
+    assert!(matches!(
+        err,
+        HpaError::InvalidMetrics {
+            invalid: 1,
+            total: 2,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn replica_bounds() {
+    let observation = Observation::new(3)
+        .pods(ready_pods(3))
+        .pod_metrics(usage(&["900m", "900m", "900m"]));
+    let recommendation = calculator()
+        .recommend(&hpa(vec![cpu_utilization(10)]), &observation)
+        .unwrap();
+    assert_eq!(recommendation.metric_replicas, 27);
+    assert_eq!(recommendation.replicas, 10);
+
+    let mut spec = hpa(vec![cpu_utilization(10)]);
+    spec.min_replicas = Some(4);
+    let recommendation = calculator().recommend(&spec, &observation).unwrap();
+    assert_eq!(recommendation.replicas, 4);
+    assert_eq!(recommendation.metric, None);
+
+    let disabled = Observation::new(0);
+    let recommendation = calculator().recommend(&spec, &disabled).unwrap();
+    assert_eq!(recommendation.replicas, 0);
+}
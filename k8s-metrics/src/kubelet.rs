@@ -0,0 +1,7 @@
+//! Kubelet APIs metrics-server derives resource metrics from
+//!
+
+use super::*;
+
+pub mod resource_metrics;
+pub mod summary;
@@ -0,0 +1,578 @@
+//! Kubelet resource metrics endpoint (`/metrics/resource`)
+//!
+//! The endpoint exposes cumulative CPU usage and current working set of the node and
+//! of every container in the Prometheus text format. Usage rates are computed from two
+//! consecutive scrapes the way metrics-server does it.
+//!
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use super::*;
+
+use quantity::{binary, canonical};
+use v1beta1::{Container, NodeMetrics, PodMetrics, Usage};
+
+const NODE_CPU: &str = "node_cpu_usage_seconds_total";
+const NODE_MEMORY: &str = "node_memory_working_set_bytes";
+const CONTAINER_CPU: &str = "container_cpu_usage_seconds_total";
+const CONTAINER_MEMORY: &str = "container_memory_working_set_bytes";
+const CONTAINER_START_TIME: &str = "container_start_time_seconds";
+
+/// Minimal age of a container started since the previous scrape
+/// for its usage to be computed from its start time
+///
+const FRESH_CONTAINER_MIN_RESOLUTION: time::Duration = time::Duration::from_secs(10);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid resource metrics: {0}")]
+pub struct ResourceMetricsParseError(String);
+
+impl ResourceMetricsParseError {
+    fn new(line: &str) -> Self {
+        Self(line.to_string())
+    }
+}
+
+/// Single sample of the node or of a container
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetricsPoint {
+    /// When the container was started, unknown for the node
+    ///
+    pub start_time: Option<DateTime<Utc>>,
+    /// When the CPU usage was sampled
+    ///
+    pub timestamp: DateTime<Utc>,
+    /// CPU time consumed since the start, in nanoseconds
+    ///
+    pub cumulative_cpu_used: u64,
+    /// Working set, in bytes
+    ///
+    pub memory_usage: u64,
+}
+
+/// Points of a single `/metrics/resource` scrape
+///
+/// Like metrics-server, points missing either the CPU or the memory usage are dropped.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetricsBatch {
+    pub node: Option<MetricsPoint>,
+    /// Points of the containers by pod `(namespace, name)` and container name
+    ///
+    pub pods: BTreeMap<(String, String), BTreeMap<String, MetricsPoint>>,
+}
+
+/// Usage of the node or of a container between two points
+///
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Rate {
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) window: time::Duration,
+    pub(crate) usage: Usage,
+}
+
+/// Sample of the Prometheus text format
+///
+#[derive(Debug, PartialEq)]
+struct Sample<'a> {
+    name: &'a str,
+    labels: Vec<(&'a str, String)>,
+    value: f64,
+    /// Milliseconds since the epoch
+    ///
+    timestamp: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+struct PartialPoint {
+    start_time: Option<DateTime<Utc>>,
+    timestamp: Option<DateTime<Utc>>,
+    cumulative_cpu_used: u64,
+    memory_usage: u64,
+}
+
+impl MetricsBatch {
+    /// Parse the response of the kubelet `/metrics/resource` endpoint
+    ///
+    pub fn parse(text: &str) -> Result<Self, ResourceMetricsParseError> {
+        let mut node = PartialPoint::default();
+        let mut pods = BTreeMap::<_, BTreeMap<_, PartialPoint>>::new();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let sample = Sample::parse(line).ok_or_else(|| ResourceMetricsParseError::new(line))?;
+            match sample.name {
+                NODE_CPU => node.cpu(&sample),
+                NODE_MEMORY => node.memory(&sample),
+                CONTAINER_CPU | CONTAINER_MEMORY | CONTAINER_START_TIME => {
+                    let (Some(namespace), Some(pod), Some(container)) = (
+                        sample.label("namespace"),
+                        sample.label("pod"),
+                        sample.label("container"),
+                    ) else {
+                        continue;
+                    };
+                    let point = pods
+                        .entry((namespace.to_string(), pod.to_string()))
+                        .or_default()
+                        .entry(container.to_string())
+                        .or_default();
+                    match sample.name {
+                        CONTAINER_CPU => point.cpu(&sample),
+                        CONTAINER_MEMORY => point.memory(&sample),
+                        _ => point.start_time(&sample),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let node = node.complete();
+        let pods = pods
+            .into_iter()
+            .map(|(pod, containers)| {
+                let containers = containers
+                    .into_iter()
+                    .filter_map(|(name, point)| Some((name, point.complete()?)))
+                    .collect::<BTreeMap<_, _>>();
+                (pod, containers)
+            })
+            .filter(|(_, containers)| !containers.is_empty())
+            .collect();
+
+        Ok(Self { node, pods })
+    }
+
+    /// Metrics of the node `name` computed against the `prev` scrape
+    ///
+    /// `None` if the node was not sampled in either scrape or its CPU usage decreased.
+    ///
+    pub fn node_metrics(&self, name: &str, prev: &Self) -> Option<NodeMetrics> {
+        let last = self.node.as_ref()?;
+        let prev = prev
+            .node
+            .as_ref()
+            .filter(|prev| prev.timestamp < last.timestamp)?;
+        let Rate {
+            timestamp,
+            window,
+            usage,
+        } = last.rate(prev)?;
+        let metadata = metav1::ObjectMeta {
+            name: Some(name.to_string()),
+            ..default()
+        };
+
+        Some(NodeMetrics {
+            metadata,
+            timestamp: metav1::Time(timestamp),
+            window,
+            usage,
+        })
+    }
+
+    /// Metrics of the pods computed against the `prev` scrape
+    ///
+    /// As in metrics-server, a container restarted since the `prev` scrape has its usage
+    /// computed from its start time, provided it runs for at least 10 seconds.
+    /// A pod is skipped if any of its containers has no usable previous point.
+    /// A container whose CPU usage decreased is left out of its pod.
+    /// The pod timestamp and window are those of its earliest container point.
+    ///
+    pub fn pod_metrics(&self, prev: &Self) -> Vec<PodMetrics> {
+        let scraped = prev.latest();
+        self.pods
+            .iter()
+            .filter_map(|((namespace, name), containers)| {
+                let prev_containers = prev.pods.get(&(namespace.clone(), name.clone()));
+                let rates = containers
+                    .iter()
+                    .map(|(container, last)| {
+                        let prev = prev_containers.and_then(|prev| prev.get(container));
+                        last.previous(prev, scraped)
+                            .map(|prev| (container, last.rate(&prev)))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                let rates = rates
+                    .into_iter()
+                    .filter_map(|(container, rate)| Some((container, rate?)))
+                    .collect::<Vec<_>>();
+                let earliest = rates
+                    .iter()
+                    .map(|(_, rate)| rate)
+                    .min_by_key(|rate| rate.timestamp)?;
+                let timestamp = metav1::Time(earliest.timestamp);
+                let window = earliest.window;
+                let containers = rates
+                    .into_iter()
+                    .map(|(container, rate)| Container {
+                        name: container.clone(),
+                        usage: rate.usage,
+                    })
+                    .collect();
+                let metadata = metav1::ObjectMeta {
+                    name: Some(name.clone()),
+                    namespace: Some(namespace.clone()),
+                    ..default()
+                };
+
+                Some(PodMetrics {
+                    metadata,
+                    containers,
+                    timestamp,
+                    window,
+                })
+            })
+            .collect()
+    }
+
+    /// Timestamp of the latest point in the scrape
+    ///
+    fn latest(&self) -> Option<DateTime<Utc>> {
+        self.pods
+            .values()
+            .flat_map(BTreeMap::values)
+            .chain(&self.node)
+            .map(|point| point.timestamp)
+            .max()
+    }
+}
+
+impl FromStr for MetricsBatch {
+    type Err = ResourceMetricsParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse(text)
+    }
+}
+
+impl MetricsPoint {
+    /// Point to compute container usage against, given its `prev` point and when the previous scrape was `scraped`
+    ///
+    fn previous(&self, prev: Option<&Self>, scraped: Option<DateTime<Utc>>) -> Option<Self> {
+        let restarted = |prev: &Self| {
+            self.start_time
+                .is_some_and(|start_time| start_time >= prev.timestamp)
+        };
+        match prev {
+            Some(prev) if !restarted(prev) => {
+                (prev.timestamp < self.timestamp).then(|| prev.clone())
+            }
+            _ => {
+                // The container started since the previous scrape, its usage started at zero
+                let start_time = self.start_time?;
+                let age = (self.timestamp - start_time).to_std().ok()?;
+                let fresh = scraped.is_some_and(|scraped| start_time > scraped)
+                    && age >= FRESH_CONTAINER_MIN_RESOLUTION;
+                fresh.then(|| Self {
+                    timestamp: start_time,
+                    cumulative_cpu_used: 0,
+                    ..self.clone()
+                })
+            }
+        }
+    }
+
+    /// Usage since `prev`, `None` if the CPU usage decreased
+    ///
+    pub(crate) fn rate(&self, prev: &Self) -> Option<Rate> {
+        let cpu_used = self
+            .cumulative_cpu_used
+            .checked_sub(prev.cumulative_cpu_used)?;
+        let window = (self.timestamp - prev.timestamp).to_std().ok()?;
+        let nano_cores = cpu_used as f64 / window.as_secs_f64();
+        let usage = Usage {
+            cpu: canonical(i128::from(nano_cores as u64), -9),
+            memory: binary(i128::from(self.memory_usage)),
+        };
+
+        Some(Rate {
+            timestamp: self.timestamp,
+            window,
+            usage,
+        })
+    }
+}
+
+impl PartialPoint {
+    fn cpu(&mut self, sample: &Sample<'_>) {
+        self.cumulative_cpu_used = (sample.value * 1e9) as u64;
+        self.timestamp = sample.timestamp.and_then(DateTime::from_timestamp_millis);
+    }
+
+    fn memory(&mut self, sample: &Sample<'_>) {
+        self.memory_usage = sample.value as u64;
+    }
+
+    fn start_time(&mut self, sample: &Sample<'_>) {
+        self.start_time = DateTime::from_timestamp_millis((sample.value * 1e3).round() as i64);
+    }
+
+    fn complete(self) -> Option<MetricsPoint> {
+        let timestamp = self.timestamp?;
+        (self.cumulative_cpu_used != 0 && self.memory_usage != 0).then_some(MetricsPoint {
+            start_time: self.start_time,
+            timestamp,
+            cumulative_cpu_used: self.cumulative_cpu_used,
+            memory_usage: self.memory_usage,
+        })
+    }
+}
+
+impl<'a> Sample<'a> {
+    /// Parse `name{label="value",...} value [timestamp]`
+    ///
+    fn parse(line: &'a str) -> Option<Self> {
+        let end = line
+            .find(|c: char| c == '{' || c.is_whitespace())
+            .unwrap_or(line.len());
+        let (name, mut rest) = line.split_at(end);
+        let mut labels = Vec::new();
+
+        if let Some(mut text) = rest.strip_prefix('{') {
+            loop {
+                text = text.trim_start();
+                if let Some(text) = text.strip_prefix('}') {
+                    rest = text;
+                    break;
+                }
+                let (label, value) = text.split_once('=')?;
+                let (value, remainder) = unquote(value.trim_start())?;
+                labels.push((label.trim(), value));
+                text = remainder.trim_start();
+                text = text.strip_prefix(',').unwrap_or(text);
+            }
+        }
+
+        let mut fields = rest.split_whitespace();
+        let value = fields.next()?.parse().ok()?;
+        let timestamp = fields.next().map(str::parse).transpose().ok()?;
+        if name.is_empty() || fields.next().is_some() {
+            return None;
+        }
+
+        Some(Self {
+            name,
+            labels,
+            value,
+            timestamp,
+        })
+    }
+
+    fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find_map(|(label, value)| (*label == name).then_some(value.as_str()))
+    }
+}
+
+/// Unescape the quoted label value at the start of `text`, returning the text after it
+///
+fn unquote(text: &str) -> Option<(String, &str)> {
+    let text = text.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[index + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Captured from kubelet, abbreviated
+    ///
+    const SCRAPE_1: &str = r#"
+# HELP container_cpu_usage_seconds_total [STABLE] Cumulative cpu time consumed by the container in core-seconds
+# TYPE container_cpu_usage_seconds_total counter
+container_cpu_usage_seconds_total{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 4.25 1710234960123
+container_cpu_usage_seconds_total{container="envoy",namespace="default",pod="web-0"} 1 1710234960400
+container_cpu_usage_seconds_total{container="nginx",namespace="default",pod="web-0"} 12.5 1710234960500
+# HELP container_memory_working_set_bytes [STABLE] Current working set of the container in bytes
+# TYPE container_memory_working_set_bytes gauge
+container_memory_working_set_bytes{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 1.572864e+07 1710234960123
+container_memory_working_set_bytes{container="envoy",namespace="default",pod="web-0"} 1.048576e+07 1710234960400
+container_memory_working_set_bytes{container="nginx",namespace="default",pod="web-0"} 2.2806528e+07 1710234960500
+# HELP container_start_time_seconds [STABLE] Start time of the container since unix epoch in seconds
+# TYPE container_start_time_seconds gauge
+container_start_time_seconds{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 1.710230473e+09 1710230473000
+container_start_time_seconds{container="envoy",namespace="default",pod="web-0"} 1.71023e+09 1710230000000
+container_start_time_seconds{container="nginx",namespace="default",pod="web-0"} 1.71023e+09 1710230000000
+# HELP node_cpu_usage_seconds_total [STABLE] Cumulative cpu time consumed by the node in core-seconds
+# TYPE node_cpu_usage_seconds_total counter
+node_cpu_usage_seconds_total 357.5 1710234960213
+# HELP node_memory_working_set_bytes [STABLE] Current working set of the node in bytes
+# TYPE node_memory_working_set_bytes gauge
+node_memory_working_set_bytes 1.073741824e+09 1710234960213
+# HELP pod_cpu_usage_seconds_total [STABLE] Cumulative cpu time consumed by the pod in core-seconds
+# TYPE pod_cpu_usage_seconds_total counter
+pod_cpu_usage_seconds_total{namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 4.25 1710234960123
+# HELP resource_scrape_error [STABLE] 1 if there was an error while getting container metrics, 0 otherwise
+# TYPE resource_scrape_error gauge
+resource_scrape_error 0
+"#;
+
+    /// Scraped 15 seconds later, nginx restarted in between
+    ///
+    const SCRAPE_2: &str = r#"
+container_cpu_usage_seconds_total{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 4.296875 1710234975123
+container_cpu_usage_seconds_total{container="envoy",namespace="default",pod="web-0"} 1.1875 1710234975400
+container_cpu_usage_seconds_total{container="nginx",namespace="default",pod="web-0"} 0.625 1710234975500
+container_memory_working_set_bytes{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 1.572864e+07 1710234975123
+container_memory_working_set_bytes{container="envoy",namespace="default",pod="web-0"} 1.048576e+07 1710234975400
+container_memory_working_set_bytes{container="nginx",namespace="default",pod="web-0"} 2.2806528e+07 1710234975500
+container_start_time_seconds{container="coredns",namespace="kube-system",pod="coredns-5d78c9869d-8v4kt"} 1.710230473e+09 1710230473000
+container_start_time_seconds{container="envoy",namespace="default",pod="web-0"} 1.71023e+09 1710230000000
+container_start_time_seconds{container="nginx",namespace="default",pod="web-0"} 1.710234963e+09 1710234963000
+node_cpu_usage_seconds_total 360.5 1710234975213
+node_memory_working_set_bytes 1.073741824e+09 1710234975213
+resource_scrape_error 0
+"#;
+
+    fn scrapes(scrape_2: &str) -> (MetricsBatch, MetricsBatch) {
+        (SCRAPE_1.parse().unwrap(), scrape_2.parse().unwrap())
+    }
+
+    fn time(millis: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp_millis(millis).unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let batch = MetricsBatch::parse(SCRAPE_1).unwrap();
+        let node = batch.node.as_ref().unwrap();
+        assert_eq!(node.cumulative_cpu_used, 357_500_000_000);
+        assert_eq!(node.memory_usage, 1 << 30);
+        assert_eq!(node.timestamp, time(1710234960213));
+        assert_eq!(node.start_time, None);
+
+        assert_eq!(batch.pods.len(), 2);
+        let web = &batch.pods[&("default".to_string(), "web-0".to_string())];
+        assert_eq!(web.keys().collect::<Vec<_>>(), ["envoy", "nginx"]);
+        let nginx = &web["nginx"];
+        assert_eq!(nginx.cumulative_cpu_used, 12_500_000_000);
+        assert_eq!(nginx.memory_usage, 22806528);
+        assert_eq!(nginx.start_time, Some(time(1710230000000)));
+        assert_eq!(nginx.timestamp, time(1710234960500));
+    }
+
+    #[test]
+    fn parse_incomplete() {
+        let text = r#"
+container_cpu_usage_seconds_total{container="nginx",namespace="default",pod="web-0"} 12.5 1710234960500
+container_cpu_usage_seconds_total{container="envoy",namespace="default",pod="web-0"} 0 1710234960400
+container_memory_working_set_bytes{container="envoy",namespace="default",pod="web-0"} 1.048576e+07 1710234960400
+node_cpu_usage_seconds_total 357.5
+node_memory_working_set_bytes 1.073741824e+09
+"#;
+        assert_eq!(MetricsBatch::parse(text).unwrap(), MetricsBatch::default());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for text in [
+            "node_cpu_usage_seconds_total",
+            "node_cpu_usage_seconds_total one",
+            "node_cpu_usage_seconds_total 1 2 3",
+            r#"container_cpu_usage_seconds_total{container="nginx" 1"#,
+            r#"container_cpu_usage_seconds_total{container=nginx} 1"#,
+            "{} 1",
+        ] {
+            assert!(MetricsBatch::parse(text).is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn sample() {
+        let sample = Sample::parse(r#"metric{a="x\"y\\z\n", b = "",} +Inf 1710234960500"#).unwrap();
+        assert_eq!(sample.name, "metric");
+        assert_eq!(sample.label("a"), Some("x\"y\\z\n"));
+        assert_eq!(sample.label("b"), Some(""));
+        assert_eq!(sample.value, f64::INFINITY);
+        assert_eq!(sample.timestamp, Some(1710234960500));
+        let sample = Sample::parse("resource_scrape_error 0").unwrap();
+        assert_eq!(sample.labels, []);
+        assert_eq!(sample.timestamp, None);
+    }
+
+    #[test]
+    fn node_metrics() {
+        let (prev, last) = scrapes(SCRAPE_2);
+        let node = last.node_metrics("kind-control-plane", &prev).unwrap();
+        assert_eq!(node.metadata.name.as_deref(), Some("kind-control-plane"));
+        assert_eq!(node.usage.cpu.0, "200m");
+        assert_eq!(node.usage.memory.0, "1Gi");
+        assert_eq!(node.timestamp.0, time(1710234975213));
+        assert_eq!(node.window, time::Duration::from_secs(15));
+
+        assert_eq!(prev.node_metrics("kind-control-plane", &last), None);
+        assert_eq!(last.node_metrics("kind-control-plane", &last), None);
+    }
+
+    #[test]
+    fn pod_metrics() {
+        let (prev, last) = scrapes(SCRAPE_2);
+        let pods = last.pod_metrics(&prev);
+        assert_eq!(pods.len(), 2);
+
+        let web = &pods[0];
+        assert_eq!(web.metadata.namespace.as_deref(), Some("default"));
+        assert_eq!(web.metadata.name.as_deref(), Some("web-0"));
+        assert_eq!(web.containers[0].name, "envoy");
+        assert_eq!(web.containers[0].usage.cpu.0, "12500u");
+        assert_eq!(web.containers[0].usage.memory.0, "10Mi");
+        // nginx restarted since the previous scrape, its usage starts at its start time
+        assert_eq!(web.containers[1].name, "nginx");
+        assert_eq!(web.containers[1].usage.cpu.0, "50m");
+        assert_eq!(web.containers[1].usage.memory.0, "22272Ki");
+        // earliest container point wins
+        assert_eq!(web.timestamp.0, time(1710234975400));
+        assert_eq!(web.window, time::Duration::from_secs(15));
+
+        let coredns = &pods[1];
+        assert_eq!(coredns.containers[0].usage.cpu.0, "3125u");
+        assert_eq!(coredns.containers[0].usage.memory.0, "15Mi");
+        assert_eq!(coredns.timestamp.0, time(1710234975123));
+    }
+
+    #[test]
+    fn pod_metrics_restarted() {
+        // nginx restarted less than 10 seconds ago, web-0 has no usage yet
+        let (prev, last) = scrapes(&SCRAPE_2.replace("1.710234963e+09", "1.71023497e+09"));
+        let pods = last.pod_metrics(&prev);
+        assert_eq!(pods.len(), 1);
+        assert_eq!(
+            pods[0].metadata.name.as_deref(),
+            Some("coredns-5d78c9869d-8v4kt")
+        );
+
+        // no previous scrape to tell whether nginx started since
+        let pods = last.pod_metrics(&MetricsBatch::default());
+        assert_eq!(pods, []);
+    }
+
+    #[test]
+    fn pod_metrics_cpu_decreased() {
+        let (prev, last) = scrapes(&SCRAPE_2.replace("1.1875", "0.5"));
+        let pods = last.pod_metrics(&prev);
+        let web = &pods[0];
+        assert_eq!(web.containers.len(), 1);
+        assert_eq!(web.containers[0].name, "nginx");
+        assert_eq!(web.timestamp.0, time(1710234975500));
+        assert_eq!(web.window, time::Duration::from_millis(12500));
+    }
+}
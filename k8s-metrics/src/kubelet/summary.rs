@@ -0,0 +1,555 @@
+//! Kubelet Summary API (`/stats/summary`, `stats/v1alpha1`)
+//!
+//! Only the statistics relevant to resource metrics are modelled,
+//! anything else (accelerators, swap, process and rlimit stats, ...) is ignored.
+//!
+
+use super::*;
+
+use quantity::{binary, canonical};
+use v1beta1::{Container, NodeMetrics, PodMetrics, Usage};
+
+/// Response of the kubelet `/stats/summary` endpoint
+///
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub node: NodeStats,
+    #[serde(default)]
+    pub pods: Vec<PodStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeStats {
+    pub node_name: String,
+    /// stats of the system daemons, e.g. `kubelet` and `runtime`
+    ///
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub system_containers: Vec<ContainerStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<metav1::Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs: Option<FsStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runtime: Option<RuntimeStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodStats {
+    pub pod_ref: PodReference,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<metav1::Time>,
+    #[serde(default)]
+    pub containers: Vec<ContainerStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkStats>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volume: Vec<VolumeStats>,
+    #[serde(
+        default,
+        rename = "ephemeral-storage",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub ephemeral_storage: Option<FsStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PodReference {
+    pub name: String,
+    pub namespace: String,
+    pub uid: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerStats {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<metav1::Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rootfs: Option<FsStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logs: Option<FsStats>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuStats {
+    pub time: metav1::Time,
+    /// average usage over the kubelet sampling interval, in nanocores
+    ///
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_nano_cores: Option<u64>,
+    /// cumulative usage since the object creation, in core-nanoseconds
+    ///
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_core_nano_seconds: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    pub time: metav1::Time,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage_bytes: Option<u64>,
+    /// usage minus inactive file cache, the memory usage reported by metrics-server
+    ///
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub working_set_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_faults: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub major_page_faults: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkStats {
+    pub time: metav1::Time,
+    /// stats of the default interface
+    ///
+    #[serde(flatten)]
+    pub default: InterfaceStats,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub interfaces: Vec<InterfaceStats>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterfaceStats {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rx_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rx_errors: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_errors: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FsStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<metav1::Time>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capacity_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub used_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inodes_free: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inodes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inodes_used: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeStats {
+    pub name: String,
+    #[serde(flatten)]
+    pub fs: FsStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pvc_ref: Option<PvcReference>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PvcReference {
+    pub name: String,
+    pub namespace: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_fs: Option<FsStats>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_fs: Option<FsStats>,
+}
+
+impl Summary {
+    /// Node metrics reported over `window`, see `NodeStats::to_metrics()`
+    ///
+    pub fn node_metrics(&self, window: time::Duration) -> Option<NodeMetrics> {
+        self.node.to_metrics(window)
+    }
+
+    /// Metrics of the pods reported over `window`, see `PodStats::to_metrics()`
+    ///
+    pub fn pod_metrics(&self, window: time::Duration) -> Vec<PodMetrics> {
+        self.pods
+            .iter()
+            .filter_map(|pod| pod.to_metrics(window))
+            .collect()
+    }
+}
+
+impl NodeStats {
+    /// Node metrics as metrics-server derives them from the summary
+    ///
+    /// CPU usage is `usageNanoCores`, memory usage is `workingSetBytes` and the
+    /// timestamp is the time CPU usage was sampled. `None` if either usage is missing.
+    ///
+    pub fn to_metrics(&self, window: time::Duration) -> Option<NodeMetrics> {
+        let (timestamp, usage) = usage(self.cpu.as_ref(), self.memory.as_ref())?;
+        let metadata = metav1::ObjectMeta {
+            name: Some(self.node_name.clone()),
+            ..default()
+        };
+
+        Some(NodeMetrics {
+            metadata,
+            timestamp,
+            window,
+            usage,
+        })
+    }
+}
+
+impl PodStats {
+    /// Pod metrics as metrics-server derives them from the summary
+    ///
+    /// Like metrics-server, the pod is skipped (`None`) as a whole if any of its containers
+    /// misses CPU or memory usage. The timestamp is the latest container timestamp.
+    ///
+    pub fn to_metrics(&self, window: time::Duration) -> Option<PodMetrics> {
+        let containers = self
+            .containers
+            .iter()
+            .map(ContainerStats::to_metrics)
+            .collect::<Option<Vec<_>>>()?;
+        let timestamp = containers
+            .iter()
+            .map(|(timestamp, _)| timestamp)
+            .max()?
+            .clone();
+        let containers = containers
+            .into_iter()
+            .map(|(_, container)| container)
+            .collect();
+        let metadata = metav1::ObjectMeta {
+            name: Some(self.pod_ref.name.clone()),
+            namespace: Some(self.pod_ref.namespace.clone()),
+            ..default()
+        };
+
+        Some(PodMetrics {
+            metadata,
+            containers,
+            timestamp,
+            window,
+        })
+    }
+}
+
+impl ContainerStats {
+    fn to_metrics(&self) -> Option<(metav1::Time, Container)> {
+        let (timestamp, usage) = usage(self.cpu.as_ref(), self.memory.as_ref())?;
+        let name = self.name.clone();
+        Some((timestamp, Container { name, usage }))
+    }
+}
+
+fn usage(cpu: Option<&CpuStats>, memory: Option<&MemoryStats>) -> Option<(metav1::Time, Usage)> {
+    let cpu_stats = cpu?;
+    let cpu = canonical(i128::from(cpu_stats.usage_nano_cores?), -9);
+    let memory = binary(i128::from(memory?.working_set_bytes?));
+    Some((cpu_stats.time.clone(), Usage { cpu, memory }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s::serde_json as json;
+
+    /// Captured from kubelet, abbreviated
+    ///
+    const SUMMARY: &str = r#"{
+  "node": {
+    "nodeName": "kind-control-plane",
+    "systemContainers": [
+      {
+        "name": "kubelet",
+        "startTime": "2024-03-12T08:01:13Z",
+        "cpu": {
+          "time": "2024-03-12T09:16:05Z",
+          "usageNanoCores": 31465497,
+          "usageCoreNanoSeconds": 118234000000
+        },
+        "memory": {
+          "time": "2024-03-12T09:16:05Z",
+          "usageBytes": 61194240,
+          "workingSetBytes": 58245120,
+          "rssBytes": 45039616,
+          "pageFaults": 32931,
+          "majorPageFaults": 0
+        }
+      }
+    ],
+    "startTime": "2024-03-12T08:00:58Z",
+    "cpu": {
+      "time": "2024-03-12T09:16:06Z",
+      "usageNanoCores": 196382978,
+      "usageCoreNanoSeconds": 932187000000
+    },
+    "memory": {
+      "time": "2024-03-12T09:16:06Z",
+      "availableBytes": 7163469824,
+      "usageBytes": 1207779328,
+      "workingSetBytes": 1073741824,
+      "rssBytes": 494108672,
+      "pageFaults": 120,
+      "majorPageFaults": 0
+    },
+    "network": {
+      "time": "2024-03-12T09:16:06Z",
+      "name": "eth0",
+      "rxBytes": 128937126,
+      "rxErrors": 0,
+      "txBytes": 12031992,
+      "txErrors": 0,
+      "interfaces": [
+        {
+          "name": "eth0",
+          "rxBytes": 128937126,
+          "rxErrors": 0,
+          "txBytes": 12031992,
+          "txErrors": 0
+        }
+      ]
+    },
+    "fs": {
+      "time": "2024-03-12T09:16:06Z",
+      "availableBytes": 37470466048,
+      "capacityBytes": 62671097856,
+      "usedBytes": 22003417088,
+      "inodesFree": 3565412,
+      "inodes": 3907584,
+      "inodesUsed": 342172
+    },
+    "runtime": {
+      "imageFs": {
+        "time": "2024-03-12T09:16:06Z",
+        "availableBytes": 37470466048,
+        "capacityBytes": 62671097856,
+        "usedBytes": 1039114240
+      }
+    },
+    "rlimit": {
+      "time": "2024-03-12T09:16:08Z",
+      "maxpid": 4194304,
+      "curproc": 412
+    }
+  },
+  "pods": [
+    {
+      "podRef": {
+        "name": "coredns-5d78c9869d-8v4kt",
+        "namespace": "kube-system",
+        "uid": "0a3c71f5-6b4b-4b3d-9d74-3f0a8d1d3a51"
+      },
+      "startTime": "2024-03-12T08:01:30Z",
+      "containers": [
+        {
+          "name": "coredns",
+          "startTime": "2024-03-12T08:01:31Z",
+          "cpu": {
+            "time": "2024-03-12T09:16:02Z",
+            "usageNanoCores": 2000000,
+            "usageCoreNanoSeconds": 9318000000
+          },
+          "memory": {
+            "time": "2024-03-12T09:16:02Z",
+            "usageBytes": 18014208,
+            "workingSetBytes": 15728640,
+            "rssBytes": 12582912,
+            "pageFaults": 2811,
+            "majorPageFaults": 0
+          },
+          "rootfs": {
+            "time": "2024-03-12T09:16:02Z",
+            "availableBytes": 37470466048,
+            "capacityBytes": 62671097856,
+            "usedBytes": 49152,
+            "inodesFree": 3565412,
+            "inodes": 3907584,
+            "inodesUsed": 12
+          },
+          "logs": {
+            "time": "2024-03-12T09:16:02Z",
+            "usedBytes": 28672
+          }
+        }
+      ],
+      "cpu": {
+        "time": "2024-03-12T09:16:03Z",
+        "usageNanoCores": 2138421,
+        "usageCoreNanoSeconds": 9754000000
+      },
+      "memory": {
+        "time": "2024-03-12T09:16:03Z",
+        "usageBytes": 18300928,
+        "workingSetBytes": 16015360
+      },
+      "network": {
+        "time": "2024-03-12T09:16:03Z",
+        "name": "eth0",
+        "rxBytes": 1826451,
+        "txBytes": 1532087
+      },
+      "volume": [
+        {
+          "time": "2024-03-12T09:15:21Z",
+          "availableBytes": 178241536,
+          "capacityBytes": 178253824,
+          "usedBytes": 12288,
+          "inodesFree": 43507,
+          "inodes": 43517,
+          "inodesUsed": 10,
+          "name": "kube-api-access-x7k2q"
+        }
+      ],
+      "ephemeral-storage": {
+        "time": "2024-03-12T09:16:02Z",
+        "usedBytes": 77824
+      }
+    },
+    {
+      "podRef": {
+        "name": "web-0",
+        "namespace": "default",
+        "uid": "c0f1e6d2-2a5b-4ff0-9d0e-6d7b8c2e1f00"
+      },
+      "containers": [
+        {
+          "name": "web",
+          "cpu": {
+            "time": "2024-03-12T09:16:04Z",
+            "usageNanoCores": 5100000
+          },
+          "memory": {
+            "time": "2024-03-12T09:16:04Z",
+            "workingSetBytes": 10000000
+          }
+        },
+        {
+          "name": "init-not-running",
+          "cpu": {
+            "time": "2024-03-12T09:16:04Z"
+          },
+          "memory": {
+            "time": "2024-03-12T09:16:04Z"
+          }
+        }
+      ]
+    }
+  ]
+}"#;
+
+    fn summary() -> Summary {
+        json::from_str(SUMMARY).unwrap()
+    }
+
+    #[test]
+    fn decode() {
+        let summary = summary();
+        let node = &summary.node;
+        assert_eq!(node.node_name, "kind-control-plane");
+        assert_eq!(node.system_containers[0].name, "kubelet");
+        let network = node.network.as_ref().unwrap();
+        assert_eq!(network.default.name, "eth0");
+        assert_eq!(network.default.rx_bytes, Some(128937126));
+        assert_eq!(network.interfaces.len(), 1);
+        let fs = node.fs.as_ref().unwrap();
+        assert_eq!(fs.capacity_bytes, Some(62671097856));
+        let image_fs = node.runtime.as_ref().unwrap().image_fs.as_ref().unwrap();
+        assert_eq!(image_fs.used_bytes, Some(1039114240));
+
+        let pod = &summary.pods[0];
+        assert_eq!(pod.pod_ref.namespace, "kube-system");
+        assert_eq!(pod.volume[0].name, "kube-api-access-x7k2q");
+        assert_eq!(pod.volume[0].fs.used_bytes, Some(12288));
+        assert_eq!(
+            pod.ephemeral_storage.as_ref().unwrap().used_bytes,
+            Some(77824)
+        );
+        let container = &pod.containers[0];
+        assert_eq!(container.logs.as_ref().unwrap().used_bytes, Some(28672));
+        assert_eq!(
+            summary.pods[1].containers[1]
+                .cpu
+                .as_ref()
+                .unwrap()
+                .usage_nano_cores,
+            None
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let summary = summary();
+        let text = json::to_string(&summary).unwrap();
+        assert_eq!(json::from_str::<Summary>(&text).unwrap(), summary);
+    }
+
+    #[test]
+    fn node_metrics() {
+        let window = time::Duration::from_secs(10);
+        let node = summary().node_metrics(window).unwrap();
+        assert_eq!(node.metadata.name.as_deref(), Some("kind-control-plane"));
+        assert_eq!(node.usage.cpu.0, "196382978n");
+        assert_eq!(node.usage.memory.0, "1Gi");
+        assert_eq!(node.timestamp.0.to_rfc3339(), "2024-03-12T09:16:06+00:00");
+        assert_eq!(node.window, window);
+    }
+
+    #[test]
+    fn pod_metrics() {
+        let pods = summary().pod_metrics(time::Duration::from_secs(10));
+        // web-0 is skipped as one of its containers has no usage
+        assert_eq!(pods.len(), 1);
+        let pod = &pods[0];
+        assert_eq!(
+            pod.metadata.name.as_deref(),
+            Some("coredns-5d78c9869d-8v4kt")
+        );
+        assert_eq!(pod.metadata.namespace.as_deref(), Some("kube-system"));
+        assert_eq!(pod.containers[0].usage.cpu.0, "2m");
+        assert_eq!(pod.containers[0].usage.memory.0, "15Mi");
+        assert_eq!(pod.cpu().unwrap(), 0.002);
+        assert_eq!(pod.timestamp.0.to_rfc3339(), "2024-03-12T09:16:02+00:00");
+    }
+
+    #[test]
+    fn missing_usage() {
+        let mut node = summary().node;
+        node.memory = None;
+        assert_eq!(node.to_metrics(default()), None);
+        let pod = PodStats::default();
+        assert_eq!(pod.to_metrics(default()), None);
+    }
+}
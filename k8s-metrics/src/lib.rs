@@ -18,6 +18,7 @@ use std::time;
 
 use k8s_openapi as k8s;
 
+use percent_encoding::{AsciiSet, CONTROLS};
 use serde::{Deserialize, Serialize};
 
 use k8s::api::core::v1 as corev1;
@@ -25,14 +26,54 @@ use k8s::apimachinery::pkg::api::resource;
 use k8s::apimachinery::pkg::apis::meta::v1 as metav1;
 use k8s::chrono::{DateTime, Utc};
 
+use list::{DynamicList, DynamicListItem};
+
 pub use metrics::v1beta1;
-pub use quantity::{QuantityExt, QuantityParseError};
+pub use quantity::{QuantityExt, QuantityParseError, ToQuantity};
+pub use selector::{FieldSelector, LabelSelectorExt, SelectorParseError};
 
+pub mod cgroup;
 pub mod custom_metrics;
+pub mod discovery;
+pub mod export;
 pub mod external_metrics;
+pub mod hpa;
+pub mod kubelet;
+mod list;
 pub mod metrics;
 pub mod quantity;
+pub mod selector;
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(test)]
+mod fixtures;
+
+/// Characters escaped in a URL path segment
+///
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
 
 fn default<T: Default>() -> T {
     T::default()
 }
+
+/// Current time truncated to whole seconds, as `metav1::Time` is serialized
+///
+fn now() -> metav1::Time {
+    let now = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let now = DateTime::from_timestamp(now.as_secs() as i64, 0).unwrap_or_default();
+    metav1::Time(now)
+}
@@ -0,0 +1,59 @@
+use serde::ser::SerializeStruct as _;
+
+use super::*;
+
+/// Metric value of kind only known at runtime, listed in a `DynamicList`
+///
+pub trait DynamicListItem {
+    /// `apiVersion` of the list
+    ///
+    const LIST_API_VERSION: &'static str;
+    /// `kind` of the list
+    ///
+    const LIST_KIND: &'static str;
+}
+
+/// List of dynamic metric values
+///
+/// `k8s::List` only holds `k8s::ListableResource` items, which dynamic values are not,
+/// so `apiVersion` and `kind` of the list are taken from `DynamicListItem` instead.
+///
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DynamicList<T> {
+    #[serde(default)]
+    pub metadata: metav1::ListMeta,
+
+    #[serde(default = "Vec::new")]
+    pub items: Vec<T>,
+}
+
+impl<T> Default for DynamicList<T> {
+    fn default() -> Self {
+        Self {
+            metadata: default(),
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<T: DynamicListItem> DynamicList<T> {
+    pub const API_VERSION: &'static str = T::LIST_API_VERSION;
+    pub const KIND: &'static str = T::LIST_KIND;
+}
+
+impl<T> Serialize for DynamicList<T>
+where
+    T: DynamicListItem + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut state = serializer.serialize_struct(Self::KIND, 4)?;
+        state.serialize_field("apiVersion", Self::API_VERSION)?;
+        state.serialize_field("kind", Self::KIND)?;
+        state.serialize_field("metadata", &self.metadata)?;
+        state.serialize_field("items", &self.items)?;
+        state.end()
+    }
+}
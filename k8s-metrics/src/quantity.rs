@@ -7,16 +7,25 @@ pub trait QuantityExt {
 
 impl QuantityExt for resource::Quantity {
     fn to_memory(&self) -> Result<i64, QuantityParseError> {
-        if let Some((number, _unit)) = self.0.split_once("Ki") {
-            number.parse::<i64>().map(|n| n * 1024)
+        let (number, factor) = if let Some((number, _unit)) = self.0.split_once("Ki") {
+            (number, 1 << 10)
         } else if let Some((number, _unit)) = self.0.split_once("Mi") {
-            number.parse::<i64>().map(|n| n * 1024 * 1024)
+            (number, 1 << 20)
         } else if let Some((number, _unit)) = self.0.split_once("Gi") {
-            number.parse::<i64>().map(|n| n * 1024 * 1024 * 1024)
+            (number, 1 << 30)
+        } else if let Some((number, _unit)) = self.0.split_once("Ti") {
+            (number, 1 << 40)
+        } else if let Some((number, _unit)) = self.0.split_once("Pi") {
+            (number, 1 << 50)
         } else {
-            self.0.parse::<i64>()
-        }
-        .map_err(|_e| QuantityParseError::new(&self.0))
+            (self.0.as_str(), 1)
+        };
+
+        number
+            .parse::<i64>()
+            .ok()
+            .and_then(|n| n.checked_mul(factor))
+            .ok_or_else(|| QuantityParseError::new(&self.0))
     }
 
     fn to_f64(&self) -> Result<f64, QuantityParseError> {
@@ -26,6 +35,8 @@ impl QuantityExt for resource::Quantity {
             number.parse::<f64>().map(|f| f / 1_000_000_f64)
         } else if let Some((number, _unit)) = self.0.split_once('m') {
             number.parse::<f64>().map(|f| f / 1_000_f64)
+        } else if let Some((number, factor)) = decimal_suffix(&self.0) {
+            number.parse::<f64>().map(|f| f * factor)
         } else {
             self.0.parse::<f64>()
         };
@@ -34,6 +45,169 @@ impl QuantityExt for resource::Quantity {
     }
 }
 
+/// Conversion of numeric values into `resource::Quantity` in canonical form
+///
+/// The canonical form uses the largest decimal SI suffix keeping the number integral,
+/// e.g. `0.5` becomes `500m`, `1500` stays `1500` and `2000` becomes `2k`.
+/// Fractional values are rounded to nano units.
+///
+pub trait ToQuantity {
+    fn to_quantity(&self) -> resource::Quantity;
+}
+
+impl ToQuantity for resource::Quantity {
+    fn to_quantity(&self) -> resource::Quantity {
+        self.clone()
+    }
+}
+
+macro_rules! integer_to_quantity {
+    ($($ty:ty),*) => {
+        $(
+            impl ToQuantity for $ty {
+                fn to_quantity(&self) -> resource::Quantity {
+                    canonical(i128::from(*self), 0)
+                }
+            }
+        )*
+    };
+}
+
+integer_to_quantity!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl ToQuantity for f64 {
+    fn to_quantity(&self) -> resource::Quantity {
+        let nanos = if self.is_finite() {
+            (self * 1e9).round() as i128
+        } else {
+            0
+        };
+        canonical(nanos, -9)
+    }
+}
+
+impl ToQuantity for f32 {
+    fn to_quantity(&self) -> resource::Quantity {
+        f64::from(*self).to_quantity()
+    }
+}
+
+const DECIMAL_SUFFIXES: [(i32, &str); 10] = [
+    (-9, "n"),
+    (-6, "u"),
+    (-3, "m"),
+    (0, ""),
+    (3, "k"),
+    (6, "M"),
+    (9, "G"),
+    (12, "T"),
+    (15, "P"),
+    (18, "E"),
+];
+
+const BINARY_SUFFIXES: [&str; 5] = ["Ki", "Mi", "Gi", "Ti", "Pi"];
+
+/// Format `mantissa * 10^exponent` with the largest suffix keeping the mantissa integral
+///
+pub(crate) fn canonical(mut mantissa: i128, mut exponent: i32) -> resource::Quantity {
+    if mantissa == 0 {
+        return resource::Quantity("0".to_string());
+    }
+
+    while mantissa % 1000 == 0 && exponent < 18 {
+        mantissa /= 1000;
+        exponent += 3;
+    }
+
+    let suffix = DECIMAL_SUFFIXES
+        .iter()
+        .find_map(|(suffix_exponent, suffix)| (*suffix_exponent == exponent).then_some(*suffix))
+        .unwrap_or_default();
+
+    resource::Quantity(format!("{mantissa}{suffix}"))
+}
+
+/// Format `bytes` with the largest binary SI suffix keeping the number integral,
+/// the way memory quantities are reported, e.g. `22806528` becomes `22272Ki`
+///
+pub(crate) fn binary(mut bytes: i128) -> resource::Quantity {
+    let mut suffix = "";
+    for binary_suffix in BINARY_SUFFIXES {
+        if bytes == 0 || bytes % 1024 != 0 {
+            break;
+        }
+        bytes /= 1024;
+        suffix = binary_suffix;
+    }
+    resource::Quantity(format!("{bytes}{suffix}"))
+}
+
+/// Exact value of `quantity` in milli units rounded away from zero, like `Quantity.MilliValue()` in Go
+///
+pub(crate) fn milli(quantity: &resource::Quantity) -> Result<i64, QuantityParseError> {
+    let invalid = || QuantityParseError::new(&quantity.0);
+    let text = quantity.0.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, suffix) = text.split_at(split);
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.len() + fraction.len() == 0 || whole.len() + fraction.len() > 30 {
+        return Err(invalid());
+    }
+
+    let digits = format!("{whole}{fraction}");
+    let mut numerator = digits
+        .parse::<i128>()
+        .ok()
+        .and_then(|digits| digits.checked_mul(1000))
+        .ok_or_else(invalid)?;
+    let mut denominator = 10_i128.pow(fraction.len() as u32);
+    let exponent = if let Some(power) = BINARY_SUFFIXES.iter().position(|s| *s == suffix) {
+        let shift = 10 * (power as u32 + 1);
+        if numerator > i128::MAX >> shift {
+            return Err(invalid());
+        }
+        numerator <<= shift;
+        0
+    } else if let Some((exponent, _suffix)) = DECIMAL_SUFFIXES.iter().find(|(_, s)| *s == suffix) {
+        *exponent
+    } else if let Some(exponent) = suffix.strip_prefix(['e', 'E']) {
+        exponent.parse::<i32>().map_err(|_e| invalid())?
+    } else {
+        return Err(invalid());
+    };
+    if exponent.unsigned_abs() > 30 {
+        return Err(invalid());
+    }
+    if exponent >= 0 {
+        numerator = numerator
+            .checked_mul(10_i128.pow(exponent as u32))
+            .ok_or_else(invalid)?;
+    } else {
+        // A saturated denominator still exceeds the numerator, so the value rounds up to 1m
+        denominator = denominator.saturating_mul(10_i128.pow(exponent.unsigned_abs()));
+    }
+
+    let milli = numerator / denominator + i128::from(numerator % denominator != 0);
+    let milli = if negative { -milli } else { milli };
+    i64::try_from(milli).map_err(|_e| invalid())
+}
+
+fn decimal_suffix(text: &str) -> Option<(&str, f64)> {
+    DECIMAL_SUFFIXES
+        .iter()
+        .filter(|(exponent, _suffix)| *exponent > 0)
+        .find_map(|(exponent, suffix)| {
+            text.strip_suffix(suffix)
+                .map(|number| (number, 10_f64.powi(*exponent)))
+        })
+}
+
 #[derive(Debug, thiserror::Error)]
 #[error("Unexpected format: {0}")]
 pub struct QuantityParseError(String);
@@ -68,6 +242,20 @@ mod tests {
         assert_eq!(q, 3221225472);
     }
 
+    #[test]
+    fn memory_Ti() {
+        let q = quantity("2Ti").to_memory().unwrap();
+        assert_eq!(q, 2199023255552);
+    }
+
+    #[test]
+    fn memory_overflow() {
+        assert_eq!(quantity("8191Pi").to_memory().unwrap(), 8191 << 50);
+        for text in ["8192Pi", "-8193Pi", "8388608Ti", "9223372036854775807Ki"] {
+            quantity(text).to_memory().unwrap_err();
+        }
+    }
+
     #[test]
     fn leading_zeros() {
         let q = quantity("0004Ki").to_memory().unwrap();
@@ -92,6 +280,84 @@ mod tests {
         assert_eq!(q, 3.491);
     }
 
+    #[test]
+    fn cpu_kilo() {
+        let q = quantity("2k").to_f64().unwrap();
+        assert_eq!(q, 2000.0);
+    }
+
+    #[test]
+    fn canonical_integer() {
+        assert_eq!(0.to_quantity(), quantity("0"));
+        assert_eq!(25.to_quantity(), quantity("25"));
+        assert_eq!(1500_u32.to_quantity(), quantity("1500"));
+        assert_eq!(2000_i64.to_quantity(), quantity("2k"));
+        assert_eq!((-3_000_000_i32).to_quantity(), quantity("-3M"));
+    }
+
+    #[test]
+    fn canonical_fraction() {
+        assert_eq!(0.5.to_quantity(), quantity("500m"));
+        assert_eq!(1.5.to_quantity(), quantity("1500m"));
+        assert_eq!(0.1.to_quantity(), quantity("100m"));
+        assert_eq!(0.000303.to_quantity(), quantity("303u"));
+        assert_eq!(0.196382978.to_quantity(), quantity("196382978n"));
+        assert_eq!(3.0.to_quantity(), quantity("3"));
+        assert_eq!(f64::NAN.to_quantity(), quantity("0"));
+    }
+
+    #[test]
+    fn canonical_roundtrip() {
+        for value in [0.066, 12.5, 4000.0, 0.000000257] {
+            assert_eq!(value.to_quantity().to_f64().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn binary_memory() {
+        assert_eq!(binary(0), quantity("0"));
+        assert_eq!(binary(1000), quantity("1000"));
+        assert_eq!(binary(22806528), quantity("22272Ki"));
+        assert_eq!(binary(3 << 30), quantity("3Gi"));
+        assert_eq!(binary(3 << 40), quantity("3Ti"));
+        for bytes in [1536, 68157440, 5 << 50] {
+            assert_eq!(binary(bytes).to_memory().unwrap(), bytes as i64);
+        }
+    }
+
+    #[test]
+    fn milli_value() {
+        for (text, expected) in [
+            ("0", 0),
+            ("1", 1000),
+            ("250m", 250),
+            ("1.5", 1500),
+            ("100n", 1),
+            ("-100n", -1),
+            ("-1500u", -2),
+            ("1500u", 2),
+            ("2k", 2_000_000),
+            ("1Ki", 1_024_000),
+            ("1e3", 1_000_000),
+            ("5E-3", 5),
+            ("1P", 1_000_000_000_000_000_000),
+            (".000000000000000000000000000001e-30", 1),
+        ] {
+            assert_eq!(milli(&quantity(text)).ok(), Some(expected), "{text}");
+        }
+        for text in [
+            "",
+            "m",
+            "1x",
+            "10E",
+            "1e40",
+            "9999999999999999999999999999e30",
+            "1234567890123456789012345Pi",
+        ] {
+            assert!(milli(&quantity(text)).is_err(), "{text}");
+        }
+    }
+
     fn quantity(v: &str) -> resource::Quantity {
         resource::Quantity(v.to_string())
     }
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use super::*;
+
+pub use field::{
+    FieldOperator, FieldRequirement, FieldSelector, METADATA_NAME, METADATA_NAMESPACE,
+};
+pub use label::LabelSelectorExt;
+
+mod field;
+mod label;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid selector: {0}")]
+pub struct SelectorParseError(String);
+
+impl SelectorParseError {
+    fn new(text: &str) -> Self {
+        Self(text.to_string())
+    }
+}
+
+/// Labels of the object, empty if the object has no labels at all
+///
+fn labels<K>(object: &K) -> &BTreeMap<String, String>
+where
+    K: k8s::Metadata<Ty = metav1::ObjectMeta>,
+{
+    static EMPTY: BTreeMap<String, String> = BTreeMap::new();
+    object.metadata().labels.as_ref().unwrap_or(&EMPTY)
+}
+
+/// Split selector text into individual requirements on top level commas,
+/// i.e. ignoring commas inside `in (...)` and `notin (...)` value sets
+///
+fn requirements(text: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0_usize;
+    text.split(move |c| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        c == ',' && depth == 0
+    })
+    .map(str::trim)
+    .filter(|requirement| !requirement.is_empty())
+}
@@ -0,0 +1,321 @@
+use std::fmt;
+use std::str;
+
+use super::*;
+
+pub const METADATA_NAME: &str = "metadata.name";
+pub const METADATA_NAMESPACE: &str = "metadata.namespace";
+
+/// Field selector as accepted by the `fieldSelector` query parameter
+///
+/// The same selector can be sent to the server (via its `Display` form)
+/// and evaluated locally against already fetched objects. metrics-server
+/// supports `metadata.name` and `metadata.namespace` fields for both pods
+/// and nodes; like metrics-server, any other field evaluates as an empty string.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FieldSelector {
+    requirements: Vec<FieldRequirement>,
+}
+
+/// Single `field=value` or `field!=value` requirement
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldRequirement {
+    pub field: String,
+    pub operator: FieldOperator,
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldOperator {
+    Equals,
+    NotEquals,
+}
+
+impl FieldSelector {
+    /// Create empty selector, which selects everything
+    ///
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `field` to be equal to `value`
+    ///
+    pub fn equals(self, field: impl ToString, value: impl ToString) -> Self {
+        self.requirement(field, FieldOperator::Equals, value)
+    }
+
+    /// Require `field` to be not equal to `value`
+    ///
+    pub fn not_equals(self, field: impl ToString, value: impl ToString) -> Self {
+        self.requirement(field, FieldOperator::NotEquals, value)
+    }
+
+    /// Require object name to be `name`
+    ///
+    pub fn name(self, name: impl ToString) -> Self {
+        self.equals(METADATA_NAME, name)
+    }
+
+    /// Require object name to be anything but `name`
+    ///
+    pub fn not_name(self, name: impl ToString) -> Self {
+        self.not_equals(METADATA_NAME, name)
+    }
+
+    /// Require object namespace to be `namespace`
+    ///
+    pub fn namespace(self, namespace: impl ToString) -> Self {
+        self.equals(METADATA_NAMESPACE, namespace)
+    }
+
+    /// Require object namespace to be anything but `namespace`
+    ///
+    pub fn not_namespace(self, namespace: impl ToString) -> Self {
+        self.not_equals(METADATA_NAMESPACE, namespace)
+    }
+
+    pub fn requirements(&self) -> &[FieldRequirement] {
+        &self.requirements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    /// Check whether `object` (e.g. `PodMetrics` or `NodeMetrics`) is selected
+    ///
+    pub fn matches<K>(&self, object: &K) -> bool
+    where
+        K: k8s::Metadata<Ty = metav1::ObjectMeta>,
+    {
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.matches(object))
+    }
+
+    fn requirement(
+        mut self,
+        field: impl ToString,
+        operator: FieldOperator,
+        value: impl ToString,
+    ) -> Self {
+        let field = field.to_string();
+        let value = value.to_string();
+        self.requirements.push(FieldRequirement {
+            field,
+            operator,
+            value,
+        });
+        self
+    }
+}
+
+impl FieldRequirement {
+    pub fn matches<K>(&self, object: &K) -> bool
+    where
+        K: k8s::Metadata<Ty = metav1::ObjectMeta>,
+    {
+        let field = field(object.metadata(), &self.field);
+        match self.operator {
+            FieldOperator::Equals => field == self.value,
+            FieldOperator::NotEquals => field != self.value,
+        }
+    }
+}
+
+impl fmt::Display for FieldSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, requirement) in self.requirements.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(",")?;
+            }
+            requirement.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldRequirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operator = match self.operator {
+            FieldOperator::Equals => "=",
+            FieldOperator::NotEquals => "!=",
+        };
+        write!(f, "{}{operator}{}", self.field, escape(&self.value))
+    }
+}
+
+impl str::FromStr for FieldSelector {
+    type Err = SelectorParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        split_unescaped(text, ',')
+            .into_iter()
+            .map(str::trim)
+            .filter(|requirement| !requirement.is_empty())
+            .try_fold(Self::new(), |selector, requirement| {
+                let (field, operator, value) = parse_requirement(requirement)
+                    .ok_or_else(|| SelectorParseError::new(requirement))?;
+                Ok(selector.requirement(field, operator, value))
+            })
+    }
+}
+
+fn parse_requirement(text: &str) -> Option<(&str, FieldOperator, String)> {
+    let position = text.find(['=', '!'])?;
+    let (field, rest) = text.split_at(position);
+    let (operator, value) = if let Some(value) = rest.strip_prefix("!=") {
+        (FieldOperator::NotEquals, value)
+    } else if let Some(value) = rest.strip_prefix("==") {
+        (FieldOperator::Equals, value)
+    } else {
+        (FieldOperator::Equals, rest.strip_prefix('=')?)
+    };
+    let field = field.trim();
+    (!field.is_empty()).then_some((field, operator, unescape(value.trim())?))
+}
+
+fn field<'a>(metadata: &'a metav1::ObjectMeta, field: &str) -> &'a str {
+    match field {
+        METADATA_NAME => metadata.name.as_deref(),
+        METADATA_NAMESPACE => metadata.namespace.as_deref(),
+        _ => None,
+    }
+    .unwrap_or_default()
+}
+
+/// Escape characters having special meaning in field selector values
+///
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ',' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next()? {
+                c @ ('\\' | ',' | '=') => unescaped.push(c),
+                _ => return None,
+            },
+            ',' | '=' => return None,
+            c => unescaped.push(c),
+        }
+    }
+    Some(unescaped)
+}
+
+fn split_unescaped(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == separator => {
+                parts.push(&text[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::pod;
+
+    #[test]
+    fn builder() {
+        let selector = FieldSelector::new()
+            .namespace("kube-system")
+            .not_name("coredns");
+        assert_eq!(
+            selector.to_string(),
+            "metadata.namespace=kube-system,metadata.name!=coredns"
+        );
+        assert_eq!(FieldSelector::new().to_string(), "");
+    }
+
+    #[test]
+    fn parse() {
+        let selector = "metadata.name==web-0, metadata.namespace!=default"
+            .parse::<FieldSelector>()
+            .unwrap();
+        assert_eq!(
+            selector,
+            FieldSelector::new().name("web-0").not_namespace("default")
+        );
+        assert!("".parse::<FieldSelector>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for text in [
+            "metadata.name",
+            "=web",
+            "metadata.name=a=b",
+            "metadata.name=a\\b",
+            "metadata.name=a\\!b",
+        ] {
+            let err = text.parse::<FieldSelector>().unwrap_err();
+            assert!(err.to_string().contains(text), "{text}: {err}");
+        }
+    }
+
+    #[test]
+    fn escaping() {
+        let selector = FieldSelector::new().equals("spec.weird", "a,b=c!d\\e");
+        let text = selector.to_string();
+        assert_eq!(text, r"spec.weird=a\,b\=c!d\\e");
+        assert_eq!(text.parse::<FieldSelector>().unwrap(), selector);
+    }
+
+    #[test]
+    fn literal_exclamation_mark() {
+        let selector = FieldSelector::new().name("web!").not_namespace("!ops");
+        let text = selector.to_string();
+        assert_eq!(text, "metadata.name=web!,metadata.namespace!=!ops");
+        assert_eq!(text.parse::<FieldSelector>().unwrap(), selector);
+    }
+
+    #[test]
+    fn pod_metrics() {
+        let selector = FieldSelector::new().namespace("default").not_name("web-1");
+        assert!(selector.matches(&pod("default", "web-0", &[])));
+        assert!(!selector.matches(&pod("default", "web-1", &[])));
+        assert!(!selector.matches(&pod("kube-system", "web-0", &[])));
+        assert!(FieldSelector::new().matches(&pod("default", "web-1", &[])));
+    }
+
+    #[test]
+    fn node_metrics() {
+        let node = v1beta1::NodeMetrics {
+            metadata: metav1::ObjectMeta {
+                name: Some("node-1".to_string()),
+                ..default()
+            },
+            ..default()
+        };
+        assert!(FieldSelector::new().name("node-1").matches(&node));
+        assert!(FieldSelector::new().namespace("").matches(&node));
+        assert!(!FieldSelector::new().not_name("node-1").matches(&node));
+        assert!(FieldSelector::new()
+            .equals("spec.unschedulable", "")
+            .matches(&node));
+    }
+}
@@ -0,0 +1,377 @@
+use super::*;
+
+const IN: &str = "In";
+const NOT_IN: &str = "NotIn";
+const EXISTS: &str = "Exists";
+const DOES_NOT_EXIST: &str = "DoesNotExist";
+
+/// Local evaluation and string conversion of `metav1::LabelSelector`
+///
+/// The string form is the one accepted by the `labelSelector` query parameter,
+/// e.g. `app=web,tier in (frontend,backend),!canary`.
+///
+pub trait LabelSelectorExt {
+    /// Parse label selector from its string form
+    ///
+    /// `key=value` and `key==value` become `matchLabels` entries, everything else
+    /// (`key!=value`, `key in (..)`, `key notin (..)`, `key` and `!key`) becomes
+    /// `matchExpressions` with `In`, `NotIn`, `Exists` and `DoesNotExist` operators.
+    ///
+    fn parse(text: &str) -> Result<Self, SelectorParseError>
+    where
+        Self: Sized;
+
+    /// Format label selector in its string form
+    ///
+    /// Requirements are sorted by key, values in the sets are sorted as well.
+    /// Empty selector is formatted as empty string. Requirements that have no
+    /// string form (`In` or `NotIn` without values, unknown operators) are rejected.
+    ///
+    fn format(&self) -> Result<String, SelectorParseError>;
+
+    /// Check whether given `labels` are selected
+    ///
+    /// Empty selector selects everything, requirements with unknown operator select nothing.
+    ///
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool;
+
+    /// Check whether `object` (e.g. `PodMetrics` or `NodeMetrics`) is selected by its labels
+    ///
+    fn matches_object<K>(&self, object: &K) -> bool
+    where
+        K: k8s::Metadata<Ty = metav1::ObjectMeta>,
+    {
+        self.matches(labels(object))
+    }
+}
+
+impl LabelSelectorExt for metav1::LabelSelector {
+    fn parse(text: &str) -> Result<Self, SelectorParseError> {
+        let mut match_labels = BTreeMap::new();
+        let mut match_expressions = Vec::new();
+
+        for requirement in requirements(text) {
+            match parse_requirement(requirement)? {
+                Requirement::Equals(key, value) if !match_labels.contains_key(&key) => {
+                    match_labels.insert(key, value);
+                }
+                Requirement::Equals(key, value) => {
+                    match_expressions.push(expression(key, IN, Some(vec![value])));
+                }
+                Requirement::Expression(expression) => match_expressions.push(expression),
+            }
+        }
+
+        let match_labels = (!match_labels.is_empty()).then_some(match_labels);
+        let match_expressions = (!match_expressions.is_empty()).then_some(match_expressions);
+
+        Ok(Self {
+            match_labels,
+            match_expressions,
+        })
+    }
+
+    fn format(&self) -> Result<String, SelectorParseError> {
+        let labels = self
+            .match_labels
+            .iter()
+            .flatten()
+            .map(|(key, value)| Ok((key.as_str(), format!("{key}={value}"))));
+        let expressions =
+            self.match_expressions.iter().flatten().map(|requirement| {
+                Ok((requirement.key.as_str(), format_requirement(requirement)?))
+            });
+
+        let mut requirements = labels
+            .chain(expressions)
+            .collect::<Result<Vec<_>, SelectorParseError>>()?;
+        requirements.sort_by(|lhs, rhs| lhs.0.cmp(rhs.0));
+
+        let text = requirements
+            .into_iter()
+            .map(|(_key, requirement)| requirement)
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(text)
+    }
+
+    fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        let match_labels = self
+            .match_labels
+            .iter()
+            .flatten()
+            .all(|(key, value)| labels.get(key) == Some(value));
+        let match_expressions = self
+            .match_expressions
+            .iter()
+            .flatten()
+            .all(|requirement| requirement_matches(requirement, labels));
+
+        match_labels && match_expressions
+    }
+}
+
+enum Requirement {
+    Equals(String, String),
+    Expression(metav1::LabelSelectorRequirement),
+}
+
+fn parse_requirement(text: &str) -> Result<Requirement, SelectorParseError> {
+    let error = || SelectorParseError::new(text);
+
+    if let Some(key) = text.strip_prefix('!') {
+        let key = valid_key(key.trim()).ok_or_else(error)?;
+        return Ok(Requirement::Expression(expression(
+            key,
+            DOES_NOT_EXIST,
+            None,
+        )));
+    }
+
+    let end = text
+        .find(|c: char| c.is_whitespace() || "=!<>(".contains(c))
+        .unwrap_or(text.len());
+    let (key, rest) = text.split_at(end);
+    let key = valid_key(key).ok_or_else(error)?;
+    let rest = rest.trim_start();
+
+    let requirement = if rest.is_empty() {
+        Requirement::Expression(expression(key, EXISTS, None))
+    } else if let Some(value) = rest.strip_prefix("==").or_else(|| rest.strip_prefix('=')) {
+        let value = valid_value(value.trim()).ok_or_else(error)?;
+        Requirement::Equals(key, value)
+    } else if let Some(value) = rest.strip_prefix("!=") {
+        let value = valid_value(value.trim()).ok_or_else(error)?;
+        Requirement::Expression(expression(key, NOT_IN, Some(vec![value])))
+    } else if let Some(values) = rest.strip_prefix("notin") {
+        let values = value_set(values).ok_or_else(error)?;
+        Requirement::Expression(expression(key, NOT_IN, Some(values)))
+    } else if let Some(values) = rest.strip_prefix("in") {
+        let values = value_set(values).ok_or_else(error)?;
+        Requirement::Expression(expression(key, IN, Some(values)))
+    } else {
+        return Err(error());
+    };
+
+    Ok(requirement)
+}
+
+fn value_set(text: &str) -> Option<Vec<String>> {
+    text.trim()
+        .strip_prefix('(')?
+        .strip_suffix(')')?
+        .split(',')
+        .map(|value| valid_value(value.trim()))
+        .collect::<Option<Vec<_>>>()
+        .filter(|values| values.iter().any(|value| !value.is_empty()))
+}
+
+fn valid_key(key: &str) -> Option<String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "-_./".contains(c);
+    (!key.is_empty() && key.chars().all(valid)).then(|| key.to_string())
+}
+
+fn valid_value(value: &str) -> Option<String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || "-_.".contains(c);
+    value.chars().all(valid).then(|| value.to_string())
+}
+
+fn expression(
+    key: String,
+    operator: &str,
+    values: Option<Vec<String>>,
+) -> metav1::LabelSelectorRequirement {
+    metav1::LabelSelectorRequirement {
+        key,
+        operator: operator.to_string(),
+        values,
+    }
+}
+
+fn format_requirement(
+    requirement: &metav1::LabelSelectorRequirement,
+) -> Result<String, SelectorParseError> {
+    let key = &requirement.key;
+    let operator = requirement.operator.as_str();
+    let mut values = requirement.values.clone().unwrap_or_default();
+    values.sort();
+    let values = values.join(",");
+
+    let text = match operator {
+        IN | NOT_IN if values.is_empty() => {
+            return Err(SelectorParseError::new(&format!("{key} {operator} ()")));
+        }
+        IN => format!("{key} in ({values})"),
+        NOT_IN => format!("{key} notin ({values})"),
+        EXISTS => key.clone(),
+        DOES_NOT_EXIST => format!("!{key}"),
+        other => {
+            return Err(SelectorParseError::new(&format!(
+                "{key} {other} ({values})"
+            )))
+        }
+    };
+    Ok(text)
+}
+
+fn requirement_matches(
+    requirement: &metav1::LabelSelectorRequirement,
+    labels: &BTreeMap<String, String>,
+) -> bool {
+    let label = labels.get(&requirement.key);
+    let values = requirement.values.as_deref().unwrap_or_default();
+
+    match requirement.operator.as_str() {
+        IN => label.is_some_and(|label| values.contains(label)),
+        NOT_IN => label.is_none_or(|label| !values.contains(label)),
+        EXISTS => label.is_some(),
+        DOES_NOT_EXIST => label.is_none(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::labels;
+
+    fn selector(text: &str) -> metav1::LabelSelector {
+        metav1::LabelSelector::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parse_equals() {
+        let selector = selector("app=web, tier==frontend");
+        let match_labels = labels(&[("app", "web"), ("tier", "frontend")]);
+        assert_eq!(selector.match_labels, Some(match_labels));
+        assert_eq!(selector.match_expressions, None);
+    }
+
+    #[test]
+    fn parse_expressions() {
+        let selector = selector("env in (prod, staging),tier notin (cache),!canary,track,app!=db");
+        let expressions = selector.match_expressions.unwrap();
+        let operators = expressions
+            .iter()
+            .map(|requirement| (requirement.key.as_str(), requirement.operator.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            operators,
+            [
+                ("env", IN),
+                ("tier", NOT_IN),
+                ("canary", DOES_NOT_EXIST),
+                ("track", EXISTS),
+                ("app", NOT_IN)
+            ]
+        );
+        assert_eq!(
+            expressions[0].values.as_deref(),
+            Some(&["prod".to_string(), "staging".to_string()][..])
+        );
+        assert_eq!(expressions[2].values, None);
+        assert_eq!(expressions[4].values, Some(vec!["db".to_string()]));
+    }
+
+    #[test]
+    fn parse_empty() {
+        assert_eq!(selector(""), metav1::LabelSelector::default());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for text in [
+            "=web",
+            "app in ()",
+            "app in prod",
+            "app>1",
+            "app=a b",
+            "!",
+            "app notin (a",
+        ] {
+            let err = metav1::LabelSelector::parse(text).unwrap_err();
+            assert!(err.to_string().contains(text), "{text}: {err}");
+        }
+    }
+
+    #[test]
+    fn format() {
+        let selector =
+            selector("tier notin (web,cache),app=web,!canary,env in (staging,prod),track");
+        assert_eq!(
+            selector.format().unwrap(),
+            "app=web,!canary,env in (prod,staging),tier notin (cache,web),track"
+        );
+        assert_eq!(metav1::LabelSelector::default().format().unwrap(), "");
+    }
+
+    #[test]
+    fn format_invalid() {
+        for (operator, values) in [
+            (IN, None),
+            (NOT_IN, Some(vec![])),
+            ("Gt", Some(vec!["1".to_string()])),
+        ] {
+            let selector = metav1::LabelSelector {
+                match_expressions: Some(vec![expression("app".to_string(), operator, values)]),
+                ..default()
+            };
+            let err = selector.format().unwrap_err();
+            assert!(err.to_string().contains(operator), "{err}");
+        }
+    }
+
+    #[test]
+    fn format_roundtrip() {
+        let selector = selector("app=web,!canary,env in (prod,staging)");
+        assert_eq!(
+            metav1::LabelSelector::parse(&selector.format().unwrap()).unwrap(),
+            selector
+        );
+    }
+
+    #[test]
+    fn matches() {
+        let labels = labels(&[("app", "web"), ("env", "prod")]);
+        assert!(selector("").matches(&labels));
+        assert!(selector("app=web").matches(&labels));
+        assert!(selector("app=web,env in (prod,staging)").matches(&labels));
+        assert!(selector("tier notin (cache)").matches(&labels));
+        assert!(selector("!canary,env").matches(&labels));
+        assert!(!selector("app=db").matches(&labels));
+        assert!(!selector("app!=web").matches(&labels));
+        assert!(!selector("env notin (prod)").matches(&labels));
+        assert!(!selector("canary").matches(&labels));
+        assert!(!selector("!app").matches(&labels));
+    }
+
+    #[test]
+    fn unknown_operator() {
+        let selector = metav1::LabelSelector {
+            match_expressions: Some(vec![expression("app".to_string(), "Gt", None)]),
+            ..default()
+        };
+        assert!(!selector.matches(&labels(&[("app", "web")])));
+    }
+
+    #[test]
+    fn pod_metrics() {
+        let pod = v1beta1::PodMetrics {
+            metadata: metav1::ObjectMeta {
+                name: Some("web-0".to_string()),
+                labels: Some(labels(&[("app", "web")])),
+                ..default()
+            },
+            ..default()
+        };
+        assert!(selector("app in (web,api)").matches_object(&pod));
+        assert!(!selector("app=api").matches_object(&pod));
+    }
+
+    #[test]
+    fn node_metrics_without_labels() {
+        let node = v1beta1::NodeMetrics::default();
+        assert!(selector("!node-role.kubernetes.io/control-plane").matches_object(&node));
+        assert!(!selector("kubernetes.io/os=linux").matches_object(&node));
+    }
+}
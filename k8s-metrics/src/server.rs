@@ -0,0 +1,494 @@
+//! Metrics API server framework
+//!
+//! Rust counterpart of Go's `custom-metrics-apiserver` and of metrics-server's API:
+//! implement `CustomMetricsProvider` and/or `ResourceMetricsProvider`, serve
+//! `custom_metrics_router` and/or `resource_metrics_router` (they can be merged)
+//! with `axum::serve`. The routers speak plain HTTP, terminate TLS in front of them
+//! when registering them with the aggregator as `APIService`.
+//!
+
+use std::sync::Arc;
+
+use axum::extract::{Path, RawQuery, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use http::StatusCode;
+
+use super::*;
+
+use k8s::serde_json as json;
+
+use custom_metrics::v1beta2::{DynamicMetricValue, DynamicMetricValueList};
+use discovery::DiscoveredMetric;
+use external_metrics::v1beta1::DynamicExternalMetricValueList;
+
+pub use error::MetricsError;
+#[cfg(feature = "fake")]
+pub use fake::{FakeMetricsServer, Scenario};
+pub use provider::{CustomMetricsProvider, ResourceMetricsProvider};
+
+use table::Table;
+
+mod custom;
+mod error;
+mod external;
+#[cfg(feature = "fake")]
+mod fake;
+mod provider;
+mod resource_metrics;
+mod table;
+
+/// Router serving `custom.metrics.k8s.io/v1beta2` and `external.metrics.k8s.io/v1beta1`
+/// discovery and metrics backed by `provider`
+///
+pub fn custom_metrics_router<P: CustomMetricsProvider>(provider: P) -> Router {
+    Router::new()
+        .route("/apis/custom.metrics.k8s.io", get(custom::group))
+        .route(
+            "/apis/custom.metrics.k8s.io/v1beta2",
+            get(custom::resources::<P>),
+        )
+        .route(
+            "/apis/custom.metrics.k8s.io/v1beta2/{*path}",
+            get(custom::metric::<P>),
+        )
+        .route("/apis/external.metrics.k8s.io", get(external::group))
+        .route(
+            "/apis/external.metrics.k8s.io/v1beta1",
+            get(external::resources::<P>),
+        )
+        .route(
+            "/apis/external.metrics.k8s.io/v1beta1/namespaces/{namespace}/{metric}",
+            get(external::metric::<P>),
+        )
+        .with_state(Arc::new(provider))
+}
+
+/// Router serving `metrics.k8s.io/v1beta1` discovery, pod and node metrics backed by `provider`
+///
+pub fn resource_metrics_router<P: ResourceMetricsProvider>(provider: P) -> Router {
+    Router::new()
+        .route("/apis/metrics.k8s.io", get(resource_metrics::group))
+        .route(
+            "/apis/metrics.k8s.io/v1beta1",
+            get(resource_metrics::resources),
+        )
+        .route(
+            "/apis/metrics.k8s.io/v1beta1/nodes",
+            get(resource_metrics::nodes::<P>),
+        )
+        .route(
+            "/apis/metrics.k8s.io/v1beta1/nodes/{name}",
+            get(resource_metrics::node::<P>),
+        )
+        .route(
+            "/apis/metrics.k8s.io/v1beta1/pods",
+            get(resource_metrics::pods::<P>),
+        )
+        .route(
+            "/apis/metrics.k8s.io/v1beta1/namespaces/{namespace}/pods",
+            get(resource_metrics::namespaced_pods::<P>),
+        )
+        .route(
+            "/apis/metrics.k8s.io/v1beta1/namespaces/{namespace}/pods/{name}",
+            get(resource_metrics::pod::<P>),
+        )
+        .with_state(Arc::new(provider))
+}
+
+fn respond<T: Serialize>(status: StatusCode, value: &T) -> Response {
+    match k8s::serde_json::to_vec(value) {
+        Ok(body) => (
+            status,
+            [(http::header::CONTENT_TYPE, "application/json")],
+            body,
+        )
+            .into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// Discovery of the API group serving only `api_version`, e.g. `custom.metrics.k8s.io/v1beta2`
+///
+fn api_group(api_version: &str) -> metav1::APIGroup {
+    let (name, version) = api_version.split_once('/').unwrap_or_default();
+    let version = metav1::GroupVersionForDiscovery {
+        group_version: api_version.to_string(),
+        version: version.to_string(),
+    };
+
+    metav1::APIGroup {
+        name: name.to_string(),
+        preferred_version: Some(version.clone()),
+        versions: vec![version],
+        ..default()
+    }
+}
+
+fn api_resource_list(
+    api_version: &str,
+    kind: &str,
+    metrics: &[DiscoveredMetric],
+) -> metav1::APIResourceList {
+    metav1::APIResourceList {
+        group_version: api_version.to_string(),
+        resources: metrics
+            .iter()
+            .map(|metric| metric.api_resource(kind))
+            .collect(),
+    }
+}
+
+/// Label selector passed in the `key` query parameter, empty if there is none
+///
+fn selector(query: Option<&str>, key: &str) -> Result<metav1::LabelSelector, MetricsError> {
+    let selector = query_param(query, key)
+        .map(|value| metav1::LabelSelector::parse(&value))
+        .transpose()?
+        .unwrap_or_default();
+    Ok(selector)
+}
+
+fn query_param(query: Option<&str>, key: &str) -> Option<String> {
+    let query = query.unwrap_or_default().as_bytes();
+    form_urlencoded::parse(query)
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Client of the `router` served on a random localhost port
+///
+#[cfg(test)]
+async fn serve(router: Router) -> kube::Client {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    kube::Client::try_from(kube::Config::new(url.parse().unwrap())).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    use custom_metrics::v1beta2::{CustomMetricResource, MetricValue};
+    use custom_metrics::{MetricParams, Version};
+    use discovery::MetricCatalogue;
+    use external_metrics::v1beta1::{DynamicExternalMetricValue, ExternalMetricResource};
+
+    /// Pods `web-0` (`app=web`) and `db-0` (`app=db`) in `default` namespace
+    /// with `http_requests` metric, `default` namespace with `pods_running` metric
+    /// and external `queue_depth` metric per `queue` label
+    ///
+    #[derive(Debug)]
+    struct Provider;
+
+    impl Provider {
+        const PODS: [(&'static str, &'static str, i64); 2] =
+            [("web-0", "web", 42), ("db-0", "db", 7)];
+
+        fn pod(name: &str, metric: &DiscoveredMetric) -> Result<DynamicMetricValue, MetricsError> {
+            let (name, _, value) = Self::PODS
+                .into_iter()
+                .find(|(pod, ..)| *pod == name)
+                .ok_or_else(|| MetricsError::metric_not_found_for("pods", &metric.metric, name))?;
+            let pods = CustomMetricResource::new("v1", "Pod", "pods", true);
+            let mut metric = DynamicMetricValue::new(&pods, &metric.metric, Some("default"), name);
+            metric.value = value.to_quantity();
+            Ok(metric)
+        }
+    }
+
+    impl CustomMetricsProvider for Provider {
+        fn list_all_metrics(&self) -> Vec<DiscoveredMetric> {
+            vec![
+                DiscoveredMetric {
+                    resource: Some("pods".to_string()),
+                    metric: "http_requests".to_string(),
+                    namespaced: true,
+                },
+                DiscoveredMetric {
+                    resource: Some("namespaces".to_string()),
+                    metric: "pods_running".to_string(),
+                    namespaced: false,
+                },
+            ]
+        }
+
+        async fn get_metric_by_name(
+            &self,
+            namespace: Option<&str>,
+            name: &str,
+            info: &DiscoveredMetric,
+            _metric_selector: &metav1::LabelSelector,
+        ) -> Result<DynamicMetricValue, MetricsError> {
+            match (info.resource.as_deref(), info.metric.as_str(), namespace) {
+                (Some("pods"), "http_requests", Some("default")) => Self::pod(name, info),
+                (Some("namespaces"), "pods_running", None) if name == "default" => {
+                    let namespaces =
+                        CustomMetricResource::new("v1", "Namespace", "namespaces", false);
+                    let mut metric = DynamicMetricValue::new(&namespaces, &info.metric, None, name);
+                    metric.value = 2.to_quantity();
+                    Ok(metric)
+                }
+                (Some(resource), metric, _) => {
+                    Err(MetricsError::metric_not_found(resource, metric))
+                }
+                _ => Err(MetricsError::Internal("no resource".to_string())),
+            }
+        }
+
+        async fn get_metric_by_selector(
+            &self,
+            _namespace: Option<&str>,
+            selector: &metav1::LabelSelector,
+            info: &DiscoveredMetric,
+            _metric_selector: &metav1::LabelSelector,
+        ) -> Result<DynamicMetricValueList, MetricsError> {
+            let items = Self::PODS
+                .into_iter()
+                .filter(|(_, app, _)| {
+                    let labels = BTreeMap::from([("app".to_string(), app.to_string())]);
+                    selector.matches(&labels)
+                })
+                .map(|(name, ..)| Self::pod(name, info))
+                .collect::<Result<_, _>>()?;
+            Ok(DynamicMetricValueList {
+                items,
+                metadata: default(),
+            })
+        }
+
+        fn list_all_external_metrics(&self) -> Vec<DiscoveredMetric> {
+            vec![DiscoveredMetric {
+                resource: None,
+                metric: "queue_depth".to_string(),
+                namespaced: true,
+            }]
+        }
+
+        async fn get_external_metric(
+            &self,
+            _namespace: &str,
+            metric_selector: &metav1::LabelSelector,
+            metric: &str,
+        ) -> Result<DynamicExternalMetricValueList, MetricsError> {
+            if metric != "queue_depth" {
+                return Err(MetricsError::external_metric_not_found(metric));
+            }
+            let items = [("orders", 18), ("invoices", 3)]
+                .into_iter()
+                .map(|(queue, value)| {
+                    let labels = BTreeMap::from([("queue".to_string(), queue.to_string())]);
+                    DynamicExternalMetricValue::with_labels(metric, labels, value.to_quantity())
+                })
+                .filter(|value| metric_selector.matches(&value.metric_labels))
+                .collect();
+            Ok(DynamicExternalMetricValueList {
+                items,
+                metadata: default(),
+            })
+        }
+    }
+
+    /// Client of the `Provider` served on a random localhost port
+    ///
+    async fn client() -> kube::Client {
+        serve(custom_metrics_router(Provider)).await
+    }
+
+    async fn request<T>(client: &kube::Client, request: http::Request<Vec<u8>>) -> kube::Result<T>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        client.request(request).await
+    }
+
+    fn get(uri: &str) -> http::Request<Vec<u8>> {
+        http::Request::get(uri).body(Vec::new()).unwrap()
+    }
+
+    fn pods() -> CustomMetricResource {
+        CustomMetricResource::new("v1", "Pod", "pods", true)
+    }
+
+    fn values(list: &DynamicMetricValueList) -> Vec<(&str, f64)> {
+        list.items
+            .iter()
+            .map(|item| {
+                let name = item.described_object.name.as_deref().unwrap_or_default();
+                (name, item.value.to_f64().unwrap())
+            })
+            .collect()
+    }
+
+    fn status(err: kube::Error) -> (u16, String, String) {
+        match err {
+            kube::Error::Api(status) => (status.code, status.reason, status.message),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn discovery() {
+        let client = client().await;
+
+        let group: metav1::APIGroup = request(&client, get("/apis/custom.metrics.k8s.io"))
+            .await
+            .unwrap();
+        assert_eq!(Version::detect(&group), Some(Version::V1beta2));
+
+        let list: metav1::APIResourceList =
+            request(&client, get("/apis/custom.metrics.k8s.io/v1beta2"))
+                .await
+                .unwrap();
+        let catalogue = MetricCatalogue::from(&list);
+        assert_eq!(catalogue.group_version, "custom.metrics.k8s.io/v1beta2");
+        assert!(
+            catalogue
+                .find(Some("pods"), "http_requests")
+                .unwrap()
+                .namespaced
+        );
+        assert!(
+            !catalogue
+                .find(Some("namespaces"), "pods_running")
+                .unwrap()
+                .namespaced
+        );
+        assert_eq!(list.resources[0].kind, "MetricValueList");
+
+        let list: metav1::APIResourceList =
+            request(&client, get("/apis/external.metrics.k8s.io/v1beta1"))
+                .await
+                .unwrap();
+        assert_eq!(list.resources[0].name, "queue_depth");
+        assert_eq!(list.resources[0].kind, "ExternalMetricValueList");
+    }
+
+    #[tokio::test]
+    async fn metric_by_name() {
+        let client = client().await;
+        let get = pods()
+            .get(Some("default"), "web-0", "http_requests", &default())
+            .unwrap();
+        let list: DynamicMetricValueList = request(&client, get).await.unwrap();
+        assert_eq!(values(&list), [("web-0", 42.0)]);
+        assert_eq!(list.items[0].metric.name, "http_requests");
+    }
+
+    #[tokio::test]
+    async fn metric_by_selector() {
+        let client = client().await;
+        let params = MetricParams::default().labels("app in (web,db)");
+        let list = pods()
+            .list(Some("default"), "http_requests", &params)
+            .unwrap();
+        let list: DynamicMetricValueList = request(&client, list).await.unwrap();
+        assert_eq!(values(&list), [("web-0", 42.0), ("db-0", 7.0)]);
+
+        let params = MetricParams::default().labels("app=db");
+        let list = pods()
+            .list(Some("default"), "http_requests", &params)
+            .unwrap();
+        let list: DynamicMetricValueList = request(&client, list).await.unwrap();
+        assert_eq!(values(&list), [("db-0", 7.0)]);
+    }
+
+    #[tokio::test]
+    async fn namespace_metric() {
+        let client = client().await;
+        let get = MetricValue::<corev1::Namespace>::namespace_metric(
+            "default",
+            "pods_running",
+            &default(),
+        )
+        .unwrap();
+        let list: DynamicMetricValueList = request(&client, get).await.unwrap();
+        assert_eq!(values(&list), [("default", 2.0)]);
+        assert_eq!(
+            list.items[0].described_object.kind.as_deref(),
+            Some("Namespace")
+        );
+    }
+
+    #[tokio::test]
+    async fn external_metric() {
+        let client = client().await;
+        let resource = ExternalMetricResource::new("queue_depth");
+        let selector = metav1::LabelSelector::parse("queue=orders").unwrap();
+        let list: DynamicExternalMetricValueList = request(
+            &client,
+            get(&resource.list_url("default", &selector).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 18.0);
+
+        let list: DynamicExternalMetricValueList = request(
+            &client,
+            get(&resource.list_url("default", &default()).unwrap()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(list.items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let client = client().await;
+
+        let get = pods()
+            .get(Some("default"), "web-9", "http_requests", &default())
+            .unwrap();
+        let err = request::<DynamicMetricValueList>(&client, get)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            status(err),
+            (
+                404,
+                "NotFound".to_string(),
+                "the server could not find the metric http_requests for pods web-9".to_string()
+            )
+        );
+
+        let get = pods()
+            .get(Some("default"), "web-0", "cpu", &default())
+            .unwrap();
+        let err = request::<DynamicMetricValueList>(&client, get)
+            .await
+            .unwrap_err();
+        assert_eq!(status(err).0, 404);
+
+        let get = ExternalMetricResource::new("unknown").url_path("default");
+        let err = request::<DynamicExternalMetricValueList>(&client, self::get(&get))
+            .await
+            .unwrap_err();
+        assert_eq!(status(err).0, 404);
+
+        let get = self::get("/apis/custom.metrics.k8s.io/v1beta2/pods/web-0");
+        let err = request::<DynamicMetricValueList>(&client, get)
+            .await
+            .unwrap_err();
+        assert_eq!(status(err).0, 404);
+    }
+
+    #[tokio::test]
+    async fn bad_selector() {
+        let client = client().await;
+        let params = MetricParams::default().labels("app in web");
+        let list = pods()
+            .list(Some("default"), "http_requests", &params)
+            .unwrap();
+        let err = request::<DynamicMetricValueList>(&client, list)
+            .await
+            .unwrap_err();
+        let (code, reason, message) = status(err);
+        assert_eq!((code, reason.as_str()), (400, "BadRequest"));
+        assert!(message.contains("app in web"), "{message}");
+    }
+}
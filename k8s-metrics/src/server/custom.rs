@@ -0,0 +1,150 @@
+use super::*;
+
+const API_VERSION: &str = DynamicMetricValueList::API_VERSION;
+const LIST_KIND: &str = DynamicMetricValueList::KIND;
+
+/// Object(s) and metric requested by the custom metrics path
+///
+/// The paths, relative to `/apis/custom.metrics.k8s.io/v1beta2`, are
+///
+/// - `namespaces/{namespace}/metrics/{metric}` for the namespace itself
+/// - `namespaces/{namespace}/{resource}/{name}/{metric}` for namespaced objects
+/// - `{resource}/{name}/{metric}` for cluster scoped objects
+///
+/// where `name` is `*` for all the objects selected by `labelSelector`.
+///
+#[derive(Debug, PartialEq, Eq)]
+struct MetricPath<'a> {
+    namespace: Option<&'a str>,
+    resource: &'a str,
+    name: &'a str,
+    metric: &'a str,
+}
+
+impl<'a> MetricPath<'a> {
+    fn parse(path: &'a str) -> Option<Self> {
+        let segments = path.trim_matches('/').split('/').collect::<Vec<_>>();
+        let path = match segments[..] {
+            ["namespaces", namespace, "metrics", metric] => Self {
+                namespace: None,
+                resource: "namespaces",
+                name: namespace,
+                metric,
+            },
+            ["namespaces", namespace, resource, name, metric] => Self {
+                namespace: Some(namespace),
+                resource,
+                name,
+                metric,
+            },
+            [resource, name, metric] => Self {
+                namespace: None,
+                resource,
+                name,
+                metric,
+            },
+            _ => return None,
+        };
+        let valid = segments.iter().all(|segment| !segment.is_empty());
+        valid.then_some(path)
+    }
+
+    fn is_wildcard(&self) -> bool {
+        self.name == "*"
+    }
+
+    fn info(&self) -> DiscoveredMetric {
+        DiscoveredMetric {
+            resource: Some(self.resource.to_string()),
+            metric: self.metric.to_string(),
+            namespaced: self.namespace.is_some(),
+        }
+    }
+}
+
+pub(super) async fn group() -> Response {
+    respond(StatusCode::OK, &api_group(API_VERSION))
+}
+
+pub(super) async fn resources<P: CustomMetricsProvider>(
+    State(provider): State<Arc<P>>,
+) -> Response {
+    let metrics = provider.list_all_metrics();
+    respond(
+        StatusCode::OK,
+        &api_resource_list(API_VERSION, LIST_KIND, &metrics),
+    )
+}
+
+pub(super) async fn metric<P: CustomMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    Path(path): Path<String>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let path = MetricPath::parse(&path).ok_or_else(|| {
+        MetricsError::NotFound("the server could not find the requested resource".to_string())
+    })?;
+    let info = path.info();
+    let metric_selector = selector(query.as_deref(), "metricLabelSelector")?;
+
+    let list = if path.is_wildcard() {
+        let selector = selector(query.as_deref(), "labelSelector")?;
+        provider
+            .get_metric_by_selector(path.namespace, &selector, &info, &metric_selector)
+            .await?
+    } else {
+        let value = provider
+            .get_metric_by_name(path.namespace, path.name, &info, &metric_selector)
+            .await?;
+        DynamicMetricValueList {
+            items: vec![value],
+            metadata: default(),
+        }
+    };
+
+    Ok(respond(StatusCode::OK, &list))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let path = MetricPath::parse("namespaces/default/pods/*/http_requests").unwrap();
+        assert_eq!(
+            path,
+            MetricPath {
+                namespace: Some("default"),
+                resource: "pods",
+                name: "*",
+                metric: "http_requests"
+            }
+        );
+        assert!(path.is_wildcard());
+        assert!(path.info().namespaced);
+
+        let path = MetricPath::parse("namespaces/default/metrics/pods_running").unwrap();
+        assert_eq!(path.namespace, None);
+        assert_eq!((path.resource, path.name), ("namespaces", "default"));
+
+        let path = MetricPath::parse("/nodes/node-1/load").unwrap();
+        assert_eq!(path.namespace, None);
+        assert_eq!(path.info().to_string(), "nodes/load");
+        assert!(!path.info().namespaced);
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for path in [
+            "",
+            "pods",
+            "pods/web-0",
+            "namespaces/default/pods/web-0",
+            "pods//http_requests",
+            "namespaces/default/pods/web-0/http_requests/extra",
+        ] {
+            assert_eq!(MetricPath::parse(path), None, "{path}");
+        }
+    }
+}
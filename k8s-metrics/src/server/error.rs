@@ -0,0 +1,94 @@
+use super::*;
+
+/// Error of serving the metrics, answered with Kubernetes `Status`
+///
+#[derive(Debug, thiserror::Error)]
+pub enum MetricsError {
+    /// The metric or the object it describes is not known (404)
+    ///
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The request is malformed, e.g. has invalid selector (400)
+    ///
+    #[error("{0}")]
+    BadRequest(String),
+
+    /// The metrics are not available at the moment (503)
+    ///
+    #[error("{0}")]
+    Unavailable(String),
+
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl MetricsError {
+    /// `metric` is not provided for `resource` (e.g. `pods`) at all
+    ///
+    pub fn metric_not_found(resource: &str, metric: &str) -> Self {
+        Self::NotFound(format!(
+            "the server could not find the metric {metric} for {resource}"
+        ))
+    }
+
+    /// `metric` is not available for the object `name` of `resource`
+    ///
+    pub fn metric_not_found_for(resource: &str, metric: &str, name: &str) -> Self {
+        Self::NotFound(format!(
+            "the server could not find the metric {metric} for {resource} {name}"
+        ))
+    }
+
+    /// External `metric` is not provided
+    ///
+    pub fn external_metric_not_found(metric: &str) -> Self {
+        Self::NotFound(format!(
+            "the server could not find the external metric {metric}"
+        ))
+    }
+
+    pub fn code(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Machine readable `reason` of the `Status`
+    ///
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "NotFound",
+            Self::BadRequest(_) => "BadRequest",
+            Self::Unavailable(_) => "ServiceUnavailable",
+            Self::Internal(_) => "InternalError",
+        }
+    }
+
+    /// Failure `Status` describing this error
+    ///
+    pub fn status(&self) -> metav1::Status {
+        metav1::Status {
+            code: Some(i32::from(self.code().as_u16())),
+            message: Some(self.to_string()),
+            reason: Some(self.reason().to_string()),
+            status: Some("Failure".to_string()),
+            ..default()
+        }
+    }
+}
+
+impl From<SelectorParseError> for MetricsError {
+    fn from(err: SelectorParseError) -> Self {
+        Self::BadRequest(err.to_string())
+    }
+}
+
+impl IntoResponse for MetricsError {
+    fn into_response(self) -> Response {
+        respond(self.code(), &self.status())
+    }
+}
@@ -0,0 +1,32 @@
+use super::*;
+
+use external_metrics::v1beta1::DynamicExternalMetricValueList;
+
+const API_VERSION: &str = DynamicExternalMetricValueList::API_VERSION;
+const LIST_KIND: &str = DynamicExternalMetricValueList::KIND;
+
+pub(super) async fn group() -> Response {
+    respond(StatusCode::OK, &api_group(API_VERSION))
+}
+
+pub(super) async fn resources<P: CustomMetricsProvider>(
+    State(provider): State<Arc<P>>,
+) -> Response {
+    let metrics = provider.list_all_external_metrics();
+    respond(
+        StatusCode::OK,
+        &api_resource_list(API_VERSION, LIST_KIND, &metrics),
+    )
+}
+
+pub(super) async fn metric<P: CustomMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    Path((namespace, metric)): Path<(String, String)>,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let metric_selector = selector(query.as_deref(), "labelSelector")?;
+    let list = provider
+        .get_external_metric(&namespace, &metric_selector, &metric)
+        .await?;
+    Ok(respond(StatusCode::OK, &list))
+}
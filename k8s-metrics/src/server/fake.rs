@@ -0,0 +1,562 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time;
+
+use super::*;
+
+use custom_metrics::v1beta2::CustomMetricResource;
+use external_metrics::v1beta1::DynamicExternalMetricValue;
+use v1beta1::{NodeMetrics, PodMetrics};
+
+/// Deterministic in-process stand-in for metrics-server and metrics adapters
+///
+/// Serves `metrics.k8s.io`, `custom.metrics.k8s.io` and `external.metrics.k8s.io`
+/// from the seeded values. Clones share the same state, so metrics can be
+/// seeded and scenarios scripted while a controller under test is using
+/// the `client()`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct FakeMetricsServer {
+    state: Arc<Mutex<State>>,
+}
+
+/// Behaviour of the fake for a request
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Scenario {
+    /// Seeded metrics are served as is
+    ///
+    #[default]
+    Healthy,
+
+    /// Nothing is scraped yet: lists are empty, objects and metrics are not found
+    ///
+    NotYetAvailable,
+
+    /// Every metrics request is answered with 503 `ServiceUnavailable`
+    ///
+    /// Discovery of the served metrics (`list_all_metrics` and `list_all_external_metrics`)
+    /// cannot fail and keeps listing the seeded metrics.
+    ///
+    Unavailable,
+
+    /// Metrics are served with timestamps this far in the past
+    ///
+    Stale(time::Duration),
+
+    /// Pod metrics are served without the named containers, without any containers if empty
+    ///
+    MissingContainers(Vec<String>),
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pods: Vec<PodMetrics>,
+    nodes: Vec<NodeMetrics>,
+    custom: Vec<CustomEntry>,
+    external: Vec<ExternalEntry>,
+    script: VecDeque<Scenario>,
+    requests: usize,
+}
+
+#[derive(Debug)]
+struct CustomEntry {
+    info: DiscoveredMetric,
+    labels: BTreeMap<String, String>,
+    value: DynamicMetricValue,
+}
+
+#[derive(Debug)]
+struct ExternalEntry {
+    namespace: String,
+    value: DynamicExternalMetricValue,
+}
+
+impl FakeMetricsServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace metrics of the pod
+    ///
+    pub fn add_pod_metrics(&self, pod: PodMetrics) -> &Self {
+        let mut state = self.lock();
+        state.pods.retain(|existing| {
+            existing.metadata.namespace != pod.metadata.namespace
+                || existing.metadata.name != pod.metadata.name
+        });
+        state.pods.push(pod);
+        self
+    }
+
+    /// Add or replace metrics of the node
+    ///
+    pub fn add_node_metrics(&self, node: NodeMetrics) -> &Self {
+        let mut state = self.lock();
+        state
+            .nodes
+            .retain(|existing| existing.metadata.name != node.metadata.name);
+        state.nodes.push(node);
+        self
+    }
+
+    /// Add or replace custom metric `value` describing object of `resource` labelled with `labels`
+    ///
+    /// `labels` are the labels of the described object, as matched by `labelSelector`.
+    ///
+    pub fn add_custom_metric<K, V>(
+        &self,
+        resource: &CustomMetricResource,
+        value: DynamicMetricValue,
+        labels: impl IntoIterator<Item = (K, V)>,
+    ) -> &Self
+    where
+        K: ToString,
+        V: ToString,
+    {
+        let info = DiscoveredMetric {
+            resource: Some(resource.url_path_segment()),
+            metric: value.metric.name.clone(),
+            namespaced: resource.namespaced,
+        };
+        let labels = labels
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        let mut state = self.lock();
+        state.custom.retain(|entry| {
+            entry.info != info || entry.value.described_object != value.described_object
+        });
+        state.custom.push(CustomEntry {
+            info,
+            labels,
+            value,
+        });
+        self
+    }
+
+    /// Add or replace external metric `value` in `namespace`
+    ///
+    pub fn add_external_metric(&self, namespace: &str, value: DynamicExternalMetricValue) -> &Self {
+        let mut state = self.lock();
+        state.external.retain(|entry| {
+            entry.namespace != namespace
+                || entry.value.metric_name != value.metric_name
+                || entry.value.metric_labels != value.metric_labels
+        });
+        state.external.push(ExternalEntry {
+            namespace: namespace.to_string(),
+            value,
+        });
+        self
+    }
+
+    /// Behave according to `scenario` from now on
+    ///
+    pub fn set_scenario(&self, scenario: Scenario) -> &Self {
+        self.script([scenario])
+    }
+
+    /// Behave according to `scenarios`, one request after another
+    ///
+    /// Every metrics request consumes the next scenario, the last one stays in effect.
+    /// E.g. `[Unavailable, Unavailable, Healthy]` fails the first two requests.
+    /// Discovery requests neither consume scenarios nor fail.
+    ///
+    pub fn script(&self, scenarios: impl IntoIterator<Item = Scenario>) -> &Self {
+        self.lock().script = scenarios.into_iter().collect();
+        self
+    }
+
+    /// Number of metrics requests served so far, including failed ones, but not discovery
+    ///
+    pub fn requests(&self) -> usize {
+        self.lock().requests
+    }
+
+    /// Router serving all the metrics APIs
+    ///
+    pub fn router(&self) -> Router {
+        custom_metrics_router(self.clone()).merge(resource_metrics_router(self.clone()))
+    }
+
+    /// Client talking to this fake in-process, without any networking
+    ///
+    pub fn client(&self) -> kube::Client {
+        kube::Client::new(self.router(), "default")
+    }
+
+    /// Serve this fake on a random localhost port until the runtime shuts down
+    ///
+    pub async fn serve(&self) -> io::Result<SocketAddr> {
+        let listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = listener.local_addr()?;
+        let router = self.router();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok(addr)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Account for the request and pick the scenario it is served in
+    ///
+    fn request(&self) -> Result<(Scenario, MutexGuard<'_, State>), MetricsError> {
+        let mut state = self.lock();
+        state.requests += 1;
+        let scenario = if state.script.len() > 1 {
+            state.script.pop_front()
+        } else {
+            state.script.front().cloned()
+        }
+        .unwrap_or_default();
+
+        if scenario == Scenario::Unavailable {
+            Err(MetricsError::Unavailable(
+                "the server is currently unable to handle the request".to_string(),
+            ))
+        } else {
+            Ok((scenario, state))
+        }
+    }
+}
+
+impl Scenario {
+    fn is_available(&self) -> bool {
+        *self != Self::NotYetAvailable
+    }
+
+    fn timestamp(&self, timestamp: &mut metav1::Time) {
+        if let Self::Stale(age) = self {
+            let age = k8s::chrono::TimeDelta::from_std(*age).unwrap_or_default();
+            *timestamp = metav1::Time(now().0 - age);
+        }
+    }
+
+    fn pod(&self, mut pod: PodMetrics) -> PodMetrics {
+        self.timestamp(&mut pod.timestamp);
+        if let Self::MissingContainers(names) = self {
+            pod.containers
+                .retain(|container| !names.is_empty() && !names.contains(&container.name));
+        }
+        pod
+    }
+
+    fn node(&self, mut node: NodeMetrics) -> NodeMetrics {
+        self.timestamp(&mut node.timestamp);
+        node
+    }
+
+    fn custom(&self, mut value: DynamicMetricValue) -> DynamicMetricValue {
+        self.timestamp(&mut value.timestamp);
+        value
+    }
+
+    fn external(&self, mut value: DynamicExternalMetricValue) -> DynamicExternalMetricValue {
+        self.timestamp(&mut value.timestamp);
+        value
+    }
+}
+
+impl CustomEntry {
+    fn is(&self, namespace: Option<&str>, info: &DiscoveredMetric) -> bool {
+        self.info.resource == info.resource
+            && self.info.metric == info.metric
+            && self.value.described_object.namespace.as_deref() == namespace
+    }
+}
+
+impl ResourceMetricsProvider for FakeMetricsServer {
+    async fn list_pod_metrics(
+        &self,
+        namespace: Option<&str>,
+    ) -> Result<Vec<PodMetrics>, MetricsError> {
+        let (scenario, state) = self.request()?;
+        let pods = state
+            .pods
+            .iter()
+            .filter(|_| scenario.is_available())
+            .filter(|pod| namespace.is_none() || pod.metadata.namespace.as_deref() == namespace)
+            .map(|pod| scenario.pod(pod.clone()))
+            .collect();
+        Ok(pods)
+    }
+
+    async fn list_node_metrics(&self) -> Result<Vec<NodeMetrics>, MetricsError> {
+        let (scenario, state) = self.request()?;
+        let nodes = state
+            .nodes
+            .iter()
+            .filter(|_| scenario.is_available())
+            .map(|node| scenario.node(node.clone()))
+            .collect();
+        Ok(nodes)
+    }
+}
+
+impl CustomMetricsProvider for FakeMetricsServer {
+    fn list_all_metrics(&self) -> Vec<DiscoveredMetric> {
+        let mut metrics = self
+            .lock()
+            .custom
+            .iter()
+            .map(|entry| entry.info.clone())
+            .collect::<Vec<_>>();
+        metrics.sort();
+        metrics.dedup();
+        metrics
+    }
+
+    async fn get_metric_by_name(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        info: &DiscoveredMetric,
+        _metric_selector: &metav1::LabelSelector,
+    ) -> Result<DynamicMetricValue, MetricsError> {
+        let (scenario, state) = self.request()?;
+        let resource = info.resource.as_deref().unwrap_or_default();
+        state
+            .custom
+            .iter()
+            .filter(|_| scenario.is_available())
+            .find(|entry| {
+                entry.is(namespace, info)
+                    && entry.value.described_object.name.as_deref() == Some(name)
+            })
+            .map(|entry| scenario.custom(entry.value.clone()))
+            .ok_or_else(|| MetricsError::metric_not_found_for(resource, &info.metric, name))
+    }
+
+    async fn get_metric_by_selector(
+        &self,
+        namespace: Option<&str>,
+        selector: &metav1::LabelSelector,
+        info: &DiscoveredMetric,
+        _metric_selector: &metav1::LabelSelector,
+    ) -> Result<DynamicMetricValueList, MetricsError> {
+        let (scenario, state) = self.request()?;
+        let items = state
+            .custom
+            .iter()
+            .filter(|_| scenario.is_available())
+            .filter(|entry| entry.is(namespace, info) && selector.matches(&entry.labels))
+            .map(|entry| scenario.custom(entry.value.clone()))
+            .collect();
+        Ok(DynamicMetricValueList {
+            items,
+            metadata: default(),
+        })
+    }
+
+    fn list_all_external_metrics(&self) -> Vec<DiscoveredMetric> {
+        let mut metrics = self
+            .lock()
+            .external
+            .iter()
+            .map(|entry| DiscoveredMetric {
+                resource: None,
+                metric: entry.value.metric_name.clone(),
+                namespaced: true,
+            })
+            .collect::<Vec<_>>();
+        metrics.sort();
+        metrics.dedup();
+        metrics
+    }
+
+    async fn get_external_metric(
+        &self,
+        namespace: &str,
+        metric_selector: &metav1::LabelSelector,
+        metric: &str,
+    ) -> Result<DynamicExternalMetricValueList, MetricsError> {
+        let (scenario, state) = self.request()?;
+        if !scenario.is_available() {
+            return Err(MetricsError::external_metric_not_found(metric));
+        }
+        let items = state
+            .external
+            .iter()
+            .filter(|entry| entry.namespace == namespace && entry.value.metric_name == metric)
+            .filter(|entry| metric_selector.matches(&entry.value.metric_labels))
+            .map(|entry| scenario.external(entry.value.clone()))
+            .collect();
+        Ok(DynamicExternalMetricValueList {
+            items,
+            metadata: default(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::api;
+
+    use super::*;
+
+    use custom_metrics::CustomMetricsApi;
+    use external_metrics::ExternalMetricsApi;
+    use fixtures::{node, pod};
+
+    fn fake() -> FakeMetricsServer {
+        let fake = FakeMetricsServer::new();
+        let containers = [("app", "100m", "64Mi"), ("sidecar", "100m", "64Mi")];
+        fake.add_pod_metrics(pod("default", "web-0", &containers))
+            .add_pod_metrics(pod("default", "web-1", &containers))
+            .add_node_metrics(node("node-1", "2", "4Gi"));
+        fake
+    }
+
+    fn code(err: kube::Error) -> u16 {
+        match err {
+            kube::Error::Api(status) => status.code,
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn seeded() {
+        let fake = fake();
+        fake.add_pod_metrics(pod("default", "web-1", &[("app", "100m", "64Mi")]));
+        let pods = api::Api::<PodMetrics>::default_namespaced(fake.client());
+        let list = pods.list(&default()).await.unwrap();
+        assert_eq!(list.items.len(), 2);
+        assert_eq!(list.items[1].containers.len(), 1);
+
+        let nodes = api::Api::<NodeMetrics>::all(fake.client());
+        assert_eq!(nodes.get("node-1").await.unwrap().cpu().unwrap(), 2.0);
+        assert_eq!(fake.requests(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_yet_available() {
+        let fake = fake();
+        fake.set_scenario(Scenario::NotYetAvailable);
+        let pods = api::Api::<PodMetrics>::default_namespaced(fake.client());
+        assert!(pods.list(&default()).await.unwrap().items.is_empty());
+        assert_eq!(code(pods.get("web-0").await.unwrap_err()), 404);
+
+        fake.set_scenario(Scenario::Healthy);
+        assert_eq!(pods.list(&default()).await.unwrap().items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn scripted_unavailable() {
+        let fake = fake();
+        fake.script([
+            Scenario::Unavailable,
+            Scenario::Unavailable,
+            Scenario::Healthy,
+        ]);
+        let nodes = api::Api::<NodeMetrics>::all(fake.client());
+        assert_eq!(code(nodes.list(&default()).await.unwrap_err()), 503);
+        assert_eq!(code(nodes.get("node-1").await.unwrap_err()), 503);
+        assert_eq!(nodes.list(&default()).await.unwrap().items.len(), 1);
+        assert_eq!(nodes.list(&default()).await.unwrap().items.len(), 1);
+        assert_eq!(fake.requests(), 4);
+    }
+
+    #[tokio::test]
+    async fn stale() {
+        let fake = fake();
+        fake.set_scenario(Scenario::Stale(time::Duration::from_secs(600)));
+        let pods = api::Api::<PodMetrics>::default_namespaced(fake.client());
+        let pod = pods.get("web-0").await.unwrap();
+        let age = now().0 - pod.timestamp.0;
+        assert!(age.num_seconds() >= 600, "{age}");
+    }
+
+    #[tokio::test]
+    async fn missing_containers() {
+        let fake = fake();
+        let pods = api::Api::<PodMetrics>::default_namespaced(fake.client());
+
+        fake.set_scenario(Scenario::MissingContainers(vec!["sidecar".to_string()]));
+        let pod = pods.get("web-0").await.unwrap();
+        let containers = pod
+            .containers
+            .iter()
+            .map(|container| container.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(containers, ["app"]);
+
+        fake.set_scenario(Scenario::MissingContainers(Vec::new()));
+        let list = pods.list(&default()).await.unwrap();
+        assert!(list.items.iter().all(|pod| pod.containers.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn custom_metrics() {
+        let fake = FakeMetricsServer::new();
+        let resource = CustomMetricResource::new("v1", "Pod", "pods", true);
+        for (name, app, value) in [("web-0", "web", 10), ("db-0", "db", 3)] {
+            let mut metric =
+                DynamicMetricValue::new(&resource, "http_requests", Some("default"), name);
+            metric.value = value.to_quantity();
+            fake.add_custom_metric(&resource, metric, [("app", app)]);
+        }
+
+        let api = CustomMetricsApi::<corev1::Pod>::default_namespaced(fake.client());
+        let list = api.list("app=web", "http_requests").await.unwrap();
+        assert_eq!(list.items.len(), 1);
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 10.0);
+        let list = api.get("db-0", "http_requests").await.unwrap();
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 3.0);
+        assert_eq!(fake.list_all_metrics().len(), 1);
+
+        fake.set_scenario(Scenario::NotYetAvailable);
+        assert_eq!(
+            code(api.get("db-0", "http_requests").await.unwrap_err()),
+            404
+        );
+    }
+
+    #[tokio::test]
+    async fn external_metrics() {
+        let fake = FakeMetricsServer::new();
+        for (queue, value) in [("orders", 18), ("invoices", 3)] {
+            let metric = DynamicExternalMetricValue::with_labels(
+                "queue_depth",
+                [("queue", queue)],
+                value.to_quantity(),
+            );
+            fake.add_external_metric("default", metric);
+        }
+
+        let api = ExternalMetricsApi::default_namespaced(fake.client());
+        let selector = metav1::LabelSelector::parse("queue=orders").unwrap();
+        let list = api.list("queue_depth", &selector).await.unwrap();
+        assert_eq!(list.items[0].value.to_f64().unwrap(), 18.0);
+        assert_eq!(
+            api.list("queue_depth", &default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            2
+        );
+
+        fake.set_scenario(Scenario::Unavailable);
+        let err = api.list("queue_depth", &default()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            external_metrics::ExternalMetricsError::AdapterUnavailable(_)
+        ));
+        assert_eq!(fake.list_all_external_metrics().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn localhost() {
+        let fake = fake();
+        let addr = fake.serve().await.unwrap();
+        let config = kube::Config::new(format!("http://{addr}").parse().unwrap());
+        let client = kube::Client::try_from(config).unwrap();
+        let pods = api::Api::<PodMetrics>::namespaced(client, "default");
+        assert_eq!(pods.list(&default()).await.unwrap().items.len(), 2);
+    }
+}
@@ -0,0 +1,108 @@
+use std::future::Future;
+
+use super::*;
+
+/// Source of the metrics served by `custom_metrics_router`
+///
+/// Counterpart of Go's `CustomMetricsProvider` and `ExternalMetricsProvider`.
+/// External metrics are optional: by default none are advertised and
+/// every external metric request is answered with 404.
+///
+pub trait CustomMetricsProvider: Send + Sync + 'static {
+    /// Custom metrics advertised in the discovery
+    ///
+    fn list_all_metrics(&self) -> Vec<DiscoveredMetric>;
+
+    /// Value of `info` metric of the object `name`
+    ///
+    /// `namespace` is `None` for cluster scoped objects, including namespaces
+    /// themselves when requested as `namespaces/{namespace}/metrics/{metric}`.
+    ///
+    fn get_metric_by_name(
+        &self,
+        namespace: Option<&str>,
+        name: &str,
+        info: &DiscoveredMetric,
+        metric_selector: &metav1::LabelSelector,
+    ) -> impl Future<Output = Result<DynamicMetricValue, MetricsError>> + Send;
+
+    /// Values of `info` metric of all the objects selected by `selector`
+    ///
+    fn get_metric_by_selector(
+        &self,
+        namespace: Option<&str>,
+        selector: &metav1::LabelSelector,
+        info: &DiscoveredMetric,
+        metric_selector: &metav1::LabelSelector,
+    ) -> impl Future<Output = Result<DynamicMetricValueList, MetricsError>> + Send;
+
+    /// External metrics advertised in the discovery
+    ///
+    fn list_all_external_metrics(&self) -> Vec<DiscoveredMetric> {
+        Vec::new()
+    }
+
+    /// Values of the external `metric` time series in `namespace` selected by `metric_selector`
+    ///
+    fn get_external_metric(
+        &self,
+        namespace: &str,
+        metric_selector: &metav1::LabelSelector,
+        metric: &str,
+    ) -> impl Future<Output = Result<DynamicExternalMetricValueList, MetricsError>> + Send {
+        let _ = (namespace, metric_selector);
+        let err = MetricsError::external_metric_not_found(metric);
+        async move { Err(err) }
+    }
+}
+
+/// Source of the resource metrics served by `resource_metrics_router`
+///
+/// Counterpart of metrics-server's pod and node metrics getters. Label and field
+/// selectors are evaluated by the server, so listing returns metrics of all the
+/// objects in scope, labels included. Getting a single object defaults to
+/// looking it up in the list.
+///
+pub trait ResourceMetricsProvider: Send + Sync + 'static {
+    /// Metrics of the pods in `namespace`, in all namespaces for `None`
+    ///
+    fn list_pod_metrics(
+        &self,
+        namespace: Option<&str>,
+    ) -> impl Future<Output = Result<Vec<v1beta1::PodMetrics>, MetricsError>> + Send;
+
+    /// Metrics of all the nodes
+    ///
+    fn list_node_metrics(
+        &self,
+    ) -> impl Future<Output = Result<Vec<v1beta1::NodeMetrics>, MetricsError>> + Send;
+
+    /// Metrics of the pod `name` in `namespace`, `None` if there are none
+    ///
+    fn get_pod_metrics(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<v1beta1::PodMetrics>, MetricsError>> + Send {
+        async move {
+            let pods = self.list_pod_metrics(Some(namespace)).await?;
+            Ok(pods
+                .into_iter()
+                .find(|pod| pod.metadata.name.as_deref() == Some(name)))
+        }
+    }
+
+    /// Metrics of the node `name`, `None` if there are none
+    ///
+    fn get_node_metrics(
+        &self,
+        name: &str,
+    ) -> impl Future<Output = Result<Option<v1beta1::NodeMetrics>, MetricsError>> + Send {
+        async move {
+            let nodes = self.list_node_metrics().await?;
+            Ok(nodes
+                .into_iter()
+                .find(|node| node.metadata.name.as_deref() == Some(name)))
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use std::time;
+
+use super::*;
+
+use v1beta1::{Container, NodeMetrics, PodMetrics, Usage};
+
+const API_VERSION: &str = <PodMetrics as k8s::Resource>::API_VERSION;
+
+/// Pod and node metrics as served by metrics-server
+///
+pub(super) trait ResourceMetrics:
+    Serialize + k8s::ListableResource + k8s::Metadata<Ty = metav1::ObjectMeta>
+{
+    /// kind of the object returned by a single object request
+    ///
+    const OBJECT_KIND: &'static str;
+    /// `{resource}.{group}` as reported in not found errors
+    ///
+    const GROUP_RESOURCE: &'static str;
+
+    /// Usage reported in the `Table` rows
+    ///
+    fn usage(&self) -> Result<Usage, MetricsError>;
+
+    fn window(&self) -> time::Duration;
+
+    /// Object serialized with its `kind` and `apiVersion`
+    ///
+    fn to_object(&self) -> Result<json::Value, MetricsError> {
+        let mut value =
+            json::to_value(self).map_err(|err| MetricsError::Internal(err.to_string()))?;
+        if let Some(object) = value.as_object_mut() {
+            object.insert("kind".to_string(), Self::OBJECT_KIND.into());
+            object.insert("apiVersion".to_string(), Self::API_VERSION.into());
+        }
+        Ok(value)
+    }
+}
+
+impl ResourceMetrics for PodMetrics {
+    const OBJECT_KIND: &'static str = "PodMetrics";
+    const GROUP_RESOURCE: &'static str = "podmetrics.metrics.k8s.io";
+
+    /// Usage summed over the containers
+    ///
+    fn usage(&self) -> Result<Usage, MetricsError> {
+        match &self.containers[..] {
+            [container] => Ok(container.usage.clone()),
+            containers => {
+                let invalid = |err: QuantityParseError| MetricsError::Internal(err.to_string());
+                let cpu = containers
+                    .iter()
+                    .map(Container::cpu)
+                    .sum::<Result<f64, _>>()
+                    .map_err(invalid)?;
+                let memory = containers
+                    .iter()
+                    .map(Container::memory)
+                    .sum::<Result<i64, _>>()
+                    .map_err(invalid)?;
+                Ok(Usage {
+                    cpu: cpu.to_quantity(),
+                    memory: quantity::binary(i128::from(memory)),
+                })
+            }
+        }
+    }
+
+    fn window(&self) -> time::Duration {
+        self.window
+    }
+}
+
+impl ResourceMetrics for NodeMetrics {
+    const OBJECT_KIND: &'static str = "NodeMetrics";
+    const GROUP_RESOURCE: &'static str = "nodemetrics.metrics.k8s.io";
+
+    fn usage(&self) -> Result<Usage, MetricsError> {
+        Ok(self.usage.clone())
+    }
+
+    fn window(&self) -> time::Duration {
+        self.window
+    }
+}
+
+pub(super) async fn group() -> Response {
+    respond(StatusCode::OK, &api_group(API_VERSION))
+}
+
+pub(super) async fn resources() -> Response {
+    let resource = |name: &str, kind: &str, namespaced| metav1::APIResource {
+        name: name.to_string(),
+        kind: kind.to_string(),
+        namespaced,
+        verbs: vec!["get".to_string(), "list".to_string()],
+        ..default()
+    };
+    let list = metav1::APIResourceList {
+        group_version: API_VERSION.to_string(),
+        resources: vec![
+            resource("nodes", NodeMetrics::OBJECT_KIND, false),
+            resource("pods", PodMetrics::OBJECT_KIND, true),
+        ],
+    };
+    respond(StatusCode::OK, &list)
+}
+
+pub(super) async fn node<P: ResourceMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    Path(name): Path<String>,
+    headers: http::HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let node = provider.get_node_metrics(&name).await?;
+    get(node, &name, &headers, query.as_deref())
+}
+
+pub(super) async fn nodes<P: ResourceMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    headers: http::HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let nodes = provider.list_node_metrics().await?;
+    list(nodes, &headers, query.as_deref())
+}
+
+pub(super) async fn pod<P: ResourceMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    Path((namespace, name)): Path<(String, String)>,
+    headers: http::HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let pod = provider.get_pod_metrics(&namespace, &name).await?;
+    get(
+        pod,
+        &format!("{namespace}/{name}"),
+        &headers,
+        query.as_deref(),
+    )
+}
+
+pub(super) async fn pods<P: ResourceMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    headers: http::HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let pods = provider.list_pod_metrics(None).await?;
+    list(pods, &headers, query.as_deref())
+}
+
+pub(super) async fn namespaced_pods<P: ResourceMetricsProvider>(
+    State(provider): State<Arc<P>>,
+    Path(namespace): Path<String>,
+    headers: http::HeaderMap,
+    RawQuery(query): RawQuery,
+) -> Result<Response, MetricsError> {
+    let pods = provider.list_pod_metrics(Some(&namespace)).await?;
+    list(pods, &headers, query.as_deref())
+}
+
+fn get<K: ResourceMetrics>(
+    item: Option<K>,
+    name: &str,
+    headers: &http::HeaderMap,
+    query: Option<&str>,
+) -> Result<Response, MetricsError> {
+    let item = item.ok_or_else(|| {
+        MetricsError::NotFound(format!("{} \"{name}\" not found", K::GROUP_RESOURCE))
+    })?;
+
+    if let Some(api_version) = table::requested(headers) {
+        let table = Table::new(api_version, &[item], query)?;
+        Ok(respond(StatusCode::OK, &table))
+    } else {
+        Ok(respond(StatusCode::OK, &item.to_object()?))
+    }
+}
+
+fn list<K: ResourceMetrics>(
+    items: Vec<K>,
+    headers: &http::HeaderMap,
+    query: Option<&str>,
+) -> Result<Response, MetricsError> {
+    let labels = selector(query, "labelSelector")?;
+    let fields = query_param(query, "fieldSelector")
+        .as_deref()
+        .unwrap_or_default()
+        .parse::<FieldSelector>()?;
+    let items = items
+        .into_iter()
+        .filter(|item| labels.matches_object(item) && fields.matches(item))
+        .collect::<Vec<_>>();
+
+    if let Some(api_version) = table::requested(headers) {
+        let table = Table::new(api_version, &items, query)?;
+        Ok(respond(StatusCode::OK, &table))
+    } else {
+        let list = k8s::List {
+            items,
+            metadata: default(),
+        };
+        Ok(respond(StatusCode::OK, &list))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kube::api;
+
+    use super::*;
+
+    use fixtures::{labels, node, pod};
+
+    /// Pods `web-0`, `web-1` (`app=web`) in `default` and `coredns` in `kube-system`, node `node-1`
+    ///
+    #[derive(Debug)]
+    struct Provider;
+
+    fn labelled<K>(mut object: K, app: &str) -> K
+    where
+        K: k8s::Metadata<Ty = metav1::ObjectMeta>,
+    {
+        object.metadata_mut().labels = Some(labels(&[("app", app)]));
+        object
+    }
+
+    impl ResourceMetricsProvider for Provider {
+        async fn list_pod_metrics(
+            &self,
+            namespace: Option<&str>,
+        ) -> Result<Vec<PodMetrics>, MetricsError> {
+            let pods = [
+                labelled(
+                    pod("default", "web-0", &[("container-0", "5m", "10Mi")]),
+                    "web",
+                ),
+                labelled(
+                    pod(
+                        "default",
+                        "web-1",
+                        &[
+                            ("container-0", "250m", "64Mi"),
+                            ("container-1", "750m", "64Mi"),
+                        ],
+                    ),
+                    "web",
+                ),
+                labelled(
+                    pod("kube-system", "coredns", &[("container-0", "3m", "20Mi")]),
+                    "dns",
+                ),
+            ];
+            Ok(pods
+                .into_iter()
+                .filter(|pod| {
+                    namespace.is_none_or(|ns| pod.metadata.namespace.as_deref() == Some(ns))
+                })
+                .collect())
+        }
+
+        async fn list_node_metrics(&self) -> Result<Vec<NodeMetrics>, MetricsError> {
+            let mut node = labelled(node("node-1", "1500m", "3Gi"), "node");
+            node.window = time::Duration::from_millis(20500);
+            Ok(vec![node])
+        }
+    }
+
+    async fn client() -> kube::Client {
+        serve(resource_metrics_router(Provider)).await
+    }
+
+    fn names<K: k8s::Metadata<Ty = metav1::ObjectMeta>>(list: &[K]) -> Vec<&str> {
+        list.iter()
+            .filter_map(|item| item.metadata().name.as_deref())
+            .collect()
+    }
+
+    async fn raw(client: &kube::Client, uri: &str, accept: &str) -> json::Value {
+        let request = http::Request::get(uri)
+            .header(http::header::ACCEPT, accept)
+            .body(Vec::new())
+            .unwrap();
+        client.request(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn discovery() {
+        let client = client().await;
+        let request = http::Request::get("/apis/metrics.k8s.io/v1beta1")
+            .body(Vec::new())
+            .unwrap();
+        let list: metav1::APIResourceList = client.request(request).await.unwrap();
+        let resources = list
+            .resources
+            .iter()
+            .map(|resource| {
+                (
+                    resource.name.as_str(),
+                    resource.kind.as_str(),
+                    resource.namespaced,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            resources,
+            [
+                ("nodes", "NodeMetrics", false),
+                ("pods", "PodMetrics", true)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn list_pods() {
+        let client = client().await;
+
+        let pods = api::Api::<PodMetrics>::namespaced(client.clone(), "default");
+        let list = pods.list(&default()).await.unwrap();
+        assert_eq!(names(&list.items), ["web-0", "web-1"]);
+
+        let all = api::Api::<PodMetrics>::all(client);
+        let list = all.list(&default()).await.unwrap();
+        assert_eq!(names(&list.items), ["web-0", "web-1", "coredns"]);
+
+        let params = api::ListParams::default().labels("app!=web");
+        let list = all.list(&params).await.unwrap();
+        assert_eq!(names(&list.items), ["coredns"]);
+
+        let params =
+            api::ListParams::default().fields("metadata.namespace=default,metadata.name!=web-0");
+        let list = all.list(&params).await.unwrap();
+        assert_eq!(names(&list.items), ["web-1"]);
+        assert_eq!(list.items[0].cpu().unwrap(), 1.0);
+    }
+
+    #[tokio::test]
+    async fn get() {
+        let client = client().await;
+
+        let pods = api::Api::<PodMetrics>::namespaced(client.clone(), "default");
+        let pod = pods.get("web-0").await.unwrap();
+        assert_eq!(pod.containers[0].memory().unwrap(), 10 * 1024 * 1024);
+
+        let nodes = api::Api::<NodeMetrics>::all(client.clone());
+        let node = nodes.get("node-1").await.unwrap();
+        assert_eq!(node.cpu().unwrap(), 1.5);
+        assert_eq!(node.window, time::Duration::from_millis(20500));
+
+        let pod = raw(
+            &client,
+            "/apis/metrics.k8s.io/v1beta1/namespaces/default/pods/web-0",
+            "application/json",
+        )
+        .await;
+        assert_eq!(pod["kind"], "PodMetrics");
+        assert_eq!(pod["apiVersion"], "metrics.k8s.io/v1beta1");
+        assert_eq!(pod["window"], "15s");
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let client = client().await;
+        let pods = api::Api::<PodMetrics>::namespaced(client.clone(), "kube-system");
+        match pods.get("web-0").await.unwrap_err() {
+            kube::Error::Api(status) => {
+                assert_eq!(status.code, 404);
+                assert_eq!(
+                    status.message,
+                    r#"podmetrics.metrics.k8s.io "kube-system/web-0" not found"#
+                );
+            }
+            other => panic!("unexpected error {other:?}"),
+        }
+
+        let nodes = api::Api::<NodeMetrics>::all(client);
+        let node = nodes.get_opt("node-2").await.unwrap();
+        assert_eq!(node, None);
+    }
+
+    #[tokio::test]
+    async fn bad_selector() {
+        let client = client().await;
+        let pods = api::Api::<PodMetrics>::all(client);
+        let params = api::ListParams::default().fields("metadata.name");
+        let err = pods.list(&params).await.unwrap_err();
+        match err {
+            kube::Error::Api(status) => assert_eq!(status.code, 400),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn table() {
+        let client = client().await;
+        let accept = "application/json;as=Table;v=v1;g=meta.k8s.io,application/json";
+
+        let table = raw(
+            &client,
+            "/apis/metrics.k8s.io/v1beta1/namespaces/default/pods?labelSelector=app%3Dweb",
+            accept,
+        )
+        .await;
+        assert_eq!(table["kind"], "Table");
+        assert_eq!(table["apiVersion"], "meta.k8s.io/v1");
+        let columns = table["columnDefinitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|column| column["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(columns, ["Name", "cpu", "memory", "Window"]);
+        assert_eq!(
+            table["rows"][1]["cells"],
+            json::json!(["web-1", "1", "128Mi", "15s"])
+        );
+        assert_eq!(table["rows"][0]["object"]["kind"], "PartialObjectMetadata");
+        assert_eq!(table["rows"][0]["object"]["metadata"]["name"], "web-0");
+
+        let table = raw(
+            &client,
+            "/apis/metrics.k8s.io/v1beta1/nodes/node-1?includeObject=None",
+            accept,
+        )
+        .await;
+        assert_eq!(
+            table["rows"],
+            json::json!([{"cells": ["node-1", "1500m", "3Gi", "20.5s"]}])
+        );
+
+        let table = raw(
+            &client,
+            "/apis/metrics.k8s.io/v1beta1/namespaces/empty/pods",
+            accept,
+        )
+        .await;
+        assert_eq!(table["columnDefinitions"], json::json!([]));
+        assert_eq!(table["rows"], json::json!([]));
+    }
+
+    #[test]
+    fn pod_usage() {
+        let web = pod(
+            "default",
+            "web-0",
+            &[("nginx", "250m", "64Mi"), ("envoy", "750m", "64Mi")],
+        );
+        let usage = web.usage().unwrap();
+        assert_eq!(usage.cpu.0, "1");
+        assert_eq!(usage.memory.0, "128Mi");
+
+        let broken = pod(
+            "default",
+            "web-1",
+            &[("nginx", "250m", "64Mi"), ("envoy", "x", "64Mi")],
+        );
+        assert!(matches!(broken.usage(), Err(MetricsError::Internal(_))));
+    }
+}
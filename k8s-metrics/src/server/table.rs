@@ -0,0 +1,227 @@
+use super::*;
+
+use resource_metrics::ResourceMetrics;
+
+const GROUP: &str = "meta.k8s.io";
+
+/// `meta.k8s.io` `Table` rendering of pod and node metrics
+///
+/// Requested by `kubectl get podmetrics` and friends with
+/// `Accept: application/json;as=Table;v=v1;g=meta.k8s.io`.
+/// Columns follow metrics-server: name, usage of each resource and the window.
+///
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Table {
+    kind: &'static str,
+    api_version: &'static str,
+    metadata: metav1::ListMeta,
+    column_definitions: Vec<TableColumnDefinition>,
+    rows: Vec<TableRow>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TableColumnDefinition {
+    name: &'static str,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    format: &'static str,
+    description: &'static str,
+    priority: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct TableRow {
+    cells: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    object: Option<json::Value>,
+}
+
+impl Table {
+    /// Render `items`, embedding them in the rows as asked by `includeObject` in `query`
+    ///
+    pub(super) fn new<K: ResourceMetrics>(
+        api_version: &'static str,
+        items: &[K],
+        query: Option<&str>,
+    ) -> Result<Self, MetricsError> {
+        let include_object = query_param(query, "includeObject");
+        let rows = items
+            .iter()
+            .map(|item| TableRow::new(item, include_object.as_deref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        // metrics-server only defines the columns once it has a row to render
+        let column_definitions = if rows.is_empty() {
+            Vec::new()
+        } else {
+            columns()
+        };
+
+        Ok(Self {
+            kind: "Table",
+            api_version,
+            metadata: default(),
+            column_definitions,
+            rows,
+        })
+    }
+}
+
+impl TableRow {
+    fn new<K: ResourceMetrics>(
+        item: &K,
+        include_object: Option<&str>,
+    ) -> Result<Self, MetricsError> {
+        let usage = item.usage()?;
+        let cells = vec![
+            item.metadata().name.clone().unwrap_or_default(),
+            usage.cpu.0,
+            usage.memory.0,
+            go_duration(item.window()),
+        ];
+
+        let object = match include_object.unwrap_or("Metadata") {
+            "None" => None,
+            "Object" => Some(item.to_object()?),
+            "Metadata" => Some(json::json!({
+                "kind": "PartialObjectMetadata",
+                "apiVersion": format!("{GROUP}/v1"),
+                "metadata": item.metadata(),
+            })),
+            other => {
+                return Err(MetricsError::BadRequest(format!(
+                    "includeObject: unsupported value \"{other}\""
+                )))
+            }
+        };
+
+        Ok(Self { cells, object })
+    }
+}
+
+/// `meta.k8s.io` version of the `Table` requested in `Accept` header, if any
+///
+pub(super) fn requested(headers: &http::HeaderMap) -> Option<&'static str> {
+    headers
+        .get_all(http::header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|media_type| {
+            let params = media_type
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.trim().split_once('='))
+                .collect::<Vec<_>>();
+            let has = |param| params.contains(&param);
+            if !has(("as", "Table")) || !has(("g", GROUP)) {
+                None
+            } else if has(("v", "v1")) {
+                Some("meta.k8s.io/v1")
+            } else if has(("v", "v1beta1")) {
+                Some("meta.k8s.io/v1beta1")
+            } else {
+                None
+            }
+        })
+}
+
+/// Format `duration` the way Go's `time.Duration.String()` does, e.g. `1m30s` or `12.5s`
+///
+fn go_duration(duration: time::Duration) -> String {
+    let seconds = duration.as_secs();
+    let nanos = u64::from(duration.subsec_nanos());
+    if seconds == 0 {
+        return match nanos {
+            0 => "0s".to_string(),
+            1..1_000 => format!("{nanos}ns"),
+            1_000..1_000_000 => format!("{}µs", decimal(nanos, 3)),
+            _ => format!("{}ms", decimal(nanos, 6)),
+        };
+    }
+
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+    let seconds = decimal(seconds % 60 * 1_000_000_000 + nanos, 9);
+    if hours > 0 {
+        format!("{hours}h{minutes}m{seconds}s")
+    } else if minutes > 0 {
+        format!("{minutes}m{seconds}s")
+    } else {
+        format!("{seconds}s")
+    }
+}
+
+/// `value` divided by 10^`digits`, without trailing zeros in the fraction
+///
+fn decimal(value: u64, digits: u32) -> String {
+    let scale = 10_u64.pow(digits);
+    let (integer, fraction) = (value / scale, value % scale);
+    if fraction == 0 {
+        integer.to_string()
+    } else {
+        let fraction = format!("{fraction:0width$}", width = digits as usize);
+        format!("{integer}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+fn columns() -> Vec<TableColumnDefinition> {
+    let column = |name, format, description| TableColumnDefinition {
+        name,
+        type_: "string",
+        format,
+        description,
+        priority: 0,
+    };
+    vec![
+        column("Name", "name", "Name of the resource"),
+        column("cpu", "quantity", ""),
+        column("memory", "quantity", ""),
+        column("Window", "duration", ""),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &'static str) -> http::HeaderMap {
+        let mut headers = http::HeaderMap::new();
+        headers.insert(http::header::ACCEPT, http::HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn requested_table() {
+        let kubectl = accept(
+            "application/json;as=Table;v=v1;g=meta.k8s.io,application/json;as=Table;v=v1beta1;g=meta.k8s.io,application/json",
+        );
+        assert_eq!(requested(&kubectl), Some("meta.k8s.io/v1"));
+        let v1beta1 = accept("application/json; as=Table; v=v1beta1; g=meta.k8s.io");
+        assert_eq!(requested(&v1beta1), Some("meta.k8s.io/v1beta1"));
+        assert_eq!(requested(&accept("application/json")), None);
+        assert_eq!(
+            requested(&accept("application/json;as=Table;v=v2;g=meta.k8s.io")),
+            None
+        );
+        assert_eq!(requested(&http::HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn window() {
+        for (duration, text) in [
+            (time::Duration::ZERO, "0s"),
+            (time::Duration::from_nanos(250), "250ns"),
+            (time::Duration::from_micros(1500), "1.5ms"),
+            (time::Duration::from_millis(12_500), "12.5s"),
+            (time::Duration::from_secs(30), "30s"),
+            (time::Duration::from_secs(60), "1m0s"),
+            (time::Duration::from_secs(90), "1m30s"),
+            (time::Duration::from_millis(150_250), "2m30.25s"),
+            (time::Duration::from_secs(3600), "1h0m0s"),
+            (time::Duration::from_secs(26 * 3600 + 61), "26h1m1s"),
+        ] {
+            assert_eq!(go_duration(duration), text);
+        }
+    }
+}